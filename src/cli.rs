@@ -0,0 +1,687 @@
+use crate::format::document::Document;
+use crate::format::{self, conflict, csv, json, Format};
+use crate::todo::clock::{Clock, SystemClock};
+use crate::todo::depend;
+use crate::todo::item::*;
+use crate::todo::merge;
+use crate::todo::tree;
+use crate::ui::action::*;
+use crate::ui::label::item_label;
+use crate::ui::prompt;
+use std::fs;
+use std::io::{self, Read, Write};
+
+// Subcommands that work on FILE without starting the interface:
+//
+//     todors [--file FILE] add [--porcelain] TEXT
+//     todors [--file FILE] list [--done|--all] [--filter TERM]... [--json|--porcelain]
+//     todors [--file FILE] done [--force] ITEM
+//     todors [--file FILE] undo-done [ITEM]
+//     todors [--file FILE] rm [--done] ITEM
+//     todors [--file FILE] edit [--done] ITEM TEXT
+//     todors [--file FILE] move [--done] ITEM up|down|top|bottom
+//     todors [--file FILE] export [--to FORMAT] [--columns LIST] [OUTPUT]
+//     todors [--file FILE] import [--from FORMAT] INPUT
+//     todors [--file FILE] merge BASE OURS THEIRS
+//
+// ITEM is the position shown by `list` (in TODO unless `--done` is given) or
+// `#ID`. `-` (or no OUTPUT) stands for stdout and stdin. `merge` is a git merge
+// driver writing the result to OURS, FILE only gives the format:
+//
+//     git config merge.todors.driver 'todors --file %P merge %O %A %B'
+//     echo 'TODO merge=todors' >> .gitattributes
+
+pub const COMMANDS: [&str; 10] = [
+    "add",
+    "list",
+    "done",
+    "undo-done",
+    "rm",
+    "edit",
+    "move",
+    "export",
+    "import",
+    "merge",
+];
+
+fn load_lists(path: &str, format: Format) -> Result<(Vec<Item>, Vec<Item>), String> {
+    match format::load(path, format) {
+        Ok(lists) => Ok(lists),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok((Vec::new(), Vec::new())),
+        Err(err) => Err(format!("could not load {}: {}", path, err)),
+    }
+}
+
+fn format_arg(flag: &str, value: Option<&String>) -> Result<Format, String> {
+    value
+        .and_then(|name| Format::from_name(name))
+        .ok_or_else(|| format!("{} expects {}", flag, Format::NAMES))
+}
+
+fn export(args: &[String], file_path: &str, file_format: Format) -> Result<(), String> {
+    let mut to: Option<Format> = None;
+    let mut columns: Option<Vec<String>> = None;
+    let mut output: Option<&str> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => to = Some(format_arg("--to", args.next())?),
+            "--columns" => {
+                let list = args.next().ok_or("--columns expects a list of columns")?;
+                columns = Some(csv::parse_columns(list)?);
+            }
+            _ if output.is_none() => output = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let (todos, dones) = load_lists(file_path, file_format)?;
+    match output.filter(|path| *path != "-") {
+        Some(path) => format::export(path, to, columns.as_deref(), &todos, &dones)
+            .map_err(|err| format!("could not export to {}: {}", path, err)),
+        None => {
+            let content = format::render_export(
+                to.unwrap_or(Format::Json),
+                columns.as_deref(),
+                &todos,
+                &dones,
+            );
+            io::stdout()
+                .write_all(content.as_bytes())
+                .map_err(|err| err.to_string())
+        }
+    }
+}
+
+fn import(args: &[String], file_path: &str, file_format: Format) -> Result<(), String> {
+    let mut from: Option<Format> = None;
+    let mut input: Option<&str> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = Some(format_arg("--from", args.next())?),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let input = input.ok_or("import expects a file to read, `-` for stdin")?;
+    let (new_todos, new_dones) = if input == "-" {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .map_err(|err| err.to_string())?;
+        from.unwrap_or(Format::Json).parse(&content)?
+    } else {
+        format::load(input, from.unwrap_or_else(|| Format::from_path(input)))
+            .map_err(|err| format!("could not import {}: {}", input, err))?
+    };
+    let mut lists = Lists::load(file_path, file_format)?;
+    let count = append_renumbered(&mut lists.todos, &mut lists.dones, new_todos, new_dones);
+    lists.save()?;
+    println!("Imported {} item(s) into {}", count, file_path);
+    Ok(())
+}
+
+/// Merges the items of OURS and THEIRS into OURS. Items both sides changed in
+/// different ways are left between conflict markers and the merge fails.
+fn merge(args: &[String], file_format: Format) -> Result<(), String> {
+    let args = Args::parse(args, &[], &[])?;
+    args.expect(3, "merge BASE OURS THEIRS")?;
+    let [base, ours, theirs] = [0, 1, 2].map(|index| args.positional[index]);
+    let original = fs::read_to_string(ours).ok();
+    // A `.json` file holding an array is Taskwarrior's, whose ids are positions
+    let file_format = file_format.detect(original.as_deref().unwrap_or(""));
+    let base_lists = load_lists(base, file_format)?;
+    let mut our_lists = load_lists(ours, file_format)?;
+    let mut their_lists = load_lists(theirs, file_format)?;
+    if !file_format.keeps_ids() {
+        merge::match_items((&base_lists.0, &base_lists.1), &mut our_lists);
+        merge::match_items((&base_lists.0, &base_lists.1), &mut their_lists);
+    }
+    let merged = merge::merge(
+        (&base_lists.0, &base_lists.1),
+        (&our_lists.0, &our_lists.1),
+        (&their_lists.0, &their_lists.1),
+    );
+    let render = |(todos, dones): (Vec<Item>, Vec<Item>)| {
+        file_format.render(original.as_deref(), &todos, &dones)
+    };
+    let content = if merged.conflicts.is_empty() {
+        render((merged.todos.clone(), merged.dones.clone()))
+    } else {
+        conflict::mark(
+            &render(merged.resolved(true)),
+            &render(merged.resolved(false)),
+        )
+    };
+    fs::write(ours, content).map_err(|err| format!("could not write {}: {}", ours, err))?;
+    if merged.conflicts.is_empty() {
+        Ok(())
+    } else {
+        let ids: Vec<u64> = merged.conflicts.iter().map(|c| c.id()).collect();
+        Err(format!("both sides changed {}", prompt::ids(&ids)))
+    }
+}
+
+/// Arguments of a subcommand: flags, options taking a value and the rest.
+struct Args<'a> {
+    flags: Vec<&'a str>,
+    options: Vec<(&'a str, &'a str)>,
+    positional: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    fn parse(args: &'a [String], flags: &[&str], options: &[&str]) -> Result<Self, String> {
+        let mut parsed = Args {
+            flags: Vec::new(),
+            options: Vec::new(),
+            positional: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let arg = arg.as_str();
+            if flags.contains(&arg) {
+                parsed.flags.push(arg);
+            } else if options.contains(&arg) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} expects a value", arg))?;
+                parsed.options.push((arg, value));
+            } else if arg.starts_with("--") {
+                return Err(format!("unknown option `{}`", arg));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(&name)
+    }
+
+    fn values(&self, name: &str) -> impl Iterator<Item = &'a str> + '_ {
+        let name = name.to_string();
+        self.options
+            .iter()
+            .filter(move |(option, _)| *option == name)
+            .map(|(_, value)| *value)
+    }
+
+    fn expect(&self, count: usize, usage: &str) -> Result<(), String> {
+        if self.positional.len() == count {
+            Ok(())
+        } else {
+            Err(format!("usage: todors {}", usage))
+        }
+    }
+}
+
+/// Finds ITEM, returning whether it is in DONE and its position there.
+fn find(target: &str, done: bool, todos: &[Item], dones: &[Item]) -> Result<(bool, usize), String> {
+    if let Some(id) = target.strip_prefix('#') {
+        let id: u64 = id
+            .parse()
+            .map_err(|_| format!("invalid item id `{}`", target))?;
+        return match (tree::position(todos, id), tree::position(dones, id)) {
+            (Some(index), _) => Ok((false, index)),
+            (None, Some(index)) => Ok((true, index)),
+            (None, None) => Err(format!("no item #{}", id)),
+        };
+    }
+    let list = if done { dones } else { todos };
+    match target.parse::<usize>() {
+        Ok(index) if (1..=list.len()).contains(&index) => Ok((done, index - 1)),
+        Ok(index) => Err(format!(
+            "no item {} in {}",
+            index,
+            if done { "DONE" } else { "TODO" }
+        )),
+        Err(_) => Err(format!(
+            "invalid item `{}`, expected a position or #id",
+            target
+        )),
+    }
+}
+
+struct Lists {
+    document: Document,
+    todos: Vec<Item>,
+    dones: Vec<Item>,
+}
+
+impl Lists {
+    fn load(path: &str, format: Format) -> Result<Self, String> {
+        let (document, todos, dones) = Document::open(path, format)
+            .map_err(|err| format!("could not load {}: {}", path, err))?;
+        Ok(Lists {
+            document,
+            todos,
+            dones,
+        })
+    }
+
+    /// Refuses to overwrite changes someone else saved while we were running.
+    fn save(&mut self) -> Result<(), String> {
+        let saved = self.document.save(&self.todos, &self.dones);
+        let path = &self.document.path;
+        match saved {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(format!("{} changed while running, nothing saved", path)),
+            Err(err) => Err(format!("could not save {}: {}", path, err)),
+        }
+    }
+}
+
+/// `+project`, `@tag`, `pri:X` or text the title has to contain.
+fn matches(item: &Item, term: &str) -> bool {
+    if let Some(project) = term.strip_prefix('+') {
+        item.projects.iter().any(|p| p == project)
+    } else if let Some(tag) = term.strip_prefix('@') {
+        item.tags.iter().any(|t| t == tag)
+    } else if let Some(priority) = term.strip_prefix("pri:") {
+        item.priority
+            .is_some_and(|p| priority.eq_ignore_ascii_case(&p.to_string()))
+    } else {
+        item.title.to_lowercase().contains(&term.to_lowercase())
+    }
+}
+
+/// A title has to stay on one line, a newline would start another item in
+/// line based formats.
+fn title(text: &str) -> Result<&str, String> {
+    if text.chars().any(char::is_control) {
+        Err("TEXT can't have control characters like newlines".to_string())
+    } else {
+        Ok(text)
+    }
+}
+
+fn add(args: &[String], lists: &mut Lists) -> Result<(), String> {
+    let args = Args::parse(args, &["--porcelain"], &[])?;
+    args.expect(1, "add [--porcelain] TEXT")?;
+    let text = title(args.positional[0])?;
+    let mut item = Item::new(next_id(&lists.todos, &lists.dones), text);
+    item.created = Some(SystemClock.now());
+    let id = item.id;
+    lists.todos.push(item);
+    lists.save()?;
+    if args.flag("--porcelain") {
+        println!("{}", id);
+    } else {
+        println!("Added #{}", id);
+    }
+    Ok(())
+}
+
+fn list(args: &[String], lists: &Lists) -> Result<(), String> {
+    let args = Args::parse(
+        args,
+        &["--done", "--all", "--json", "--porcelain"],
+        &["--filter"],
+    )?;
+    args.expect(
+        0,
+        "list [--done|--all] [--filter TERM]... [--json|--porcelain]",
+    )?;
+    let terms: Vec<&str> = args.values("--filter").collect();
+    let keep = |item: &&Item| terms.iter().all(|term| matches(item, term));
+    let show_todos = !args.flag("--done") || args.flag("--all");
+    let show_dones = args.flag("--done") || args.flag("--all");
+    let todos: Vec<&Item> = lists.todos.iter().filter(keep).collect();
+    let dones: Vec<&Item> = lists.dones.iter().filter(keep).collect();
+    if args.flag("--json") {
+        let todos: Vec<Item> = todos.into_iter().filter(|_| show_todos).cloned().collect();
+        let dones: Vec<Item> = dones.into_iter().filter(|_| show_dones).cloned().collect();
+        print!("{}", json::render(&todos, &dones));
+        return Ok(());
+    }
+    let porcelain = args.flag("--porcelain");
+    let open = depend::open_ids(&lists.todos);
+    for (done, list, shown) in [
+        (false, &lists.todos, show_todos),
+        (true, &lists.dones, show_dones),
+    ] {
+        if !shown {
+            continue;
+        }
+        let depths = tree::depths(list);
+        for (index, item) in list.iter().enumerate() {
+            if !terms.iter().all(|term| matches(item, term)) {
+                continue;
+            }
+            if porcelain {
+                // position, id, status, parent id and title separated by tabs
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    index + 1,
+                    item.id,
+                    if done { "done" } else { "todo" },
+                    item.parent.map(|id| id.to_string()).unwrap_or_default(),
+                    item.title
+                );
+            } else {
+                println!(
+                    "{:>3} #{:<3}{}",
+                    index + 1,
+                    item.id,
+                    item_label(
+                        item,
+                        done,
+                        depths[index],
+                        false,
+                        &lists.todos,
+                        &lists.dones,
+                        &open,
+                    )
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn done(args: &[String], lists: &mut Lists) -> Result<(), String> {
+    let args = Args::parse(args, &["--force"], &[])?;
+    args.expect(1, "done [--force] ITEM")?;
+    let (done, mut index) = find(args.positional[0], false, &lists.todos, &lists.dones)?;
+    if done {
+        return Err(format!("{} is already done", args.positional[0]));
+    }
+    let open = depend::open_ids(&lists.todos);
+    let blockers = depend::blockers(&lists.todos[index], &open);
+    if !blockers.is_empty() && !args.flag("--force") {
+        return Err(format!(
+            "#{} is blocked by {}, use --force to complete it anyway",
+            lists.todos[index].id,
+            prompt::ids(&blockers)
+        ));
+    }
+    // Like the prompt of the interface, subtasks only go along when asked
+    let pending = tree::subtree_end(&lists.todos, index) - index - 1;
+    if pending > 0 && !args.flag("--force") {
+        return Err(format!(
+            "#{} has {} incomplete subtask(s), use --force to complete them with it",
+            lists.todos[index].id, pending
+        ));
+    }
+    let id = lists.todos[index].id;
+    let next = complete(&mut lists.todos, &mut lists.dones, &mut index, &SystemClock);
+    lists.save()?;
+    println!("Completed #{}", id);
+    if let Some(due) = next {
+        println!("Next occurrence due {}", due);
+    }
+    Ok(())
+}
+
+fn undo_done(args: &[String], lists: &mut Lists) -> Result<(), String> {
+    let args = Args::parse(args, &[], &[])?;
+    let mut index = match args.positional.as_slice() {
+        [target] => match find(target, true, &lists.todos, &lists.dones)? {
+            (true, index) => index,
+            (false, _) => return Err(format!("{} is not done", target)),
+        },
+        // The item completed last
+        [] => (0..lists.dones.len())
+            .max_by_key(|index| lists.dones[*index].completed)
+            .ok_or("nothing is done")?,
+        _ => return Err("usage: todors undo-done [ITEM]".to_string()),
+    };
+    let id = lists.dones[index].id;
+    reopen(&mut lists.todos, &mut lists.dones, &mut index);
+    lists.save()?;
+    println!("Reopened #{}", id);
+    Ok(())
+}
+
+fn rm(args: &[String], lists: &mut Lists) -> Result<(), String> {
+    let args = Args::parse(args, &["--done"], &[])?;
+    args.expect(1, "rm [--done] ITEM")?;
+    let (done, mut index) = find(
+        args.positional[0],
+        args.flag("--done"),
+        &lists.todos,
+        &lists.dones,
+    )?;
+    let list = if done {
+        &mut lists.dones
+    } else {
+        &mut lists.todos
+    };
+    let id = list[index].id;
+    let count = tree::subtree_end(list, index) - index;
+    delete(list, &mut index);
+    lists.save()?;
+    println!("Removed #{} ({} item(s))", id, count);
+    Ok(())
+}
+
+fn edit(args: &[String], lists: &mut Lists) -> Result<(), String> {
+    let args = Args::parse(args, &["--done"], &[])?;
+    args.expect(2, "edit [--done] ITEM TEXT")?;
+    let text = title(args.positional[1])?;
+    let (done, index) = find(
+        args.positional[0],
+        args.flag("--done"),
+        &lists.todos,
+        &lists.dones,
+    )?;
+    let item = if done {
+        &mut lists.dones[index]
+    } else {
+        &mut lists.todos[index]
+    };
+    item.title = text.to_string();
+    let id = item.id;
+    lists.save()?;
+    println!("Edited #{}", id);
+    Ok(())
+}
+
+fn move_item(args: &[String], lists: &mut Lists) -> Result<(), String> {
+    let args = Args::parse(args, &["--done"], &[])?;
+    args.expect(2, "move [--done] ITEM up|down|top|bottom")?;
+    let (done, mut index) = find(
+        args.positional[0],
+        args.flag("--done"),
+        &lists.todos,
+        &lists.dones,
+    )?;
+    let list = if done {
+        &mut lists.dones
+    } else {
+        &mut lists.todos
+    };
+    let id = list[index].id;
+    match args.positional[1] {
+        "up" => drag(Direction::Up, list, &mut index),
+        "down" => drag(Direction::Down, list, &mut index),
+        "top" | "bottom" => {
+            // Among its siblings, like dragging until it doesn't move anymore
+            let dir = args.positional[1];
+            loop {
+                let before = index;
+                if dir == "top" {
+                    drag(Direction::Up, list, &mut index);
+                } else {
+                    drag(Direction::Down, list, &mut index);
+                }
+                if index == before {
+                    break;
+                }
+            }
+        }
+        dir => return Err(format!("unknown direction `{}`", dir)),
+    }
+    lists.save()?;
+    println!("Moved #{} to position {}", id, index + 1);
+    Ok(())
+}
+
+pub fn run(
+    command: &str,
+    args: &[String],
+    file_path: &str,
+    file_format: Format,
+) -> Result<(), String> {
+    let lists = || Lists::load(file_path, file_format);
+    match command {
+        "add" => add(args, &mut lists()?),
+        "list" => list(args, &lists()?),
+        "done" => done(args, &mut lists()?),
+        "undo-done" => undo_done(args, &mut lists()?),
+        "rm" => rm(args, &mut lists()?),
+        "edit" => edit(args, &mut lists()?),
+        "move" => move_item(args, &mut lists()?),
+        "export" => export(args, file_path, file_format),
+        "import" => import(args, file_path, file_format),
+        "merge" => merge(args, file_format),
+        _ => Err(format!("unknown command `{}`", command)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn title_rejects_control_characters() {
+        assert_eq!(title("one two"), Ok("one two"));
+        assert!(title("one\nDONE: evil").is_err());
+        assert!(title("one\r").is_err());
+        assert!(title("tab\there").is_err());
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("todors-cli-{}-{}", process::id(), name));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn run_on(path: &str, command: &str, args: &[&str]) -> Result<(), String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        run(command, &args, path, Format::from_path(path))
+    }
+
+    fn titles(path: &str) -> (Vec<String>, Vec<String>) {
+        let (todos, dones) = format::load(path, Format::from_path(path)).unwrap();
+        let titles = |list: Vec<Item>| list.into_iter().map(|item| item.title).collect();
+        (titles(todos), titles(dones))
+    }
+
+    const TREE: &str = "\
+TODO: Parent
+  id: 1
+TODO: Child
+  id: 2
+  parent: 1
+TODO: Blocked
+  id: 3
+  depends: 1
+";
+
+    #[test]
+    fn add_appends_to_todo() {
+        let path = temp_path("add");
+        run_on(&path, "add", &["one"]).unwrap();
+        run_on(&path, "add", &["two"]).unwrap();
+        assert!(run_on(&path, "add", &["three\nDONE: four"]).is_err());
+        assert!(run_on(&path, "add", &[]).is_err());
+        assert_eq!(titles(&path), (vec!["one".into(), "two".into()], vec![]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn done_refuses_open_subtasks_and_blockers_without_force() {
+        let path = temp_path("done");
+        fs::write(&path, TREE).unwrap();
+        let err = run_on(&path, "done", &["1"]).unwrap_err();
+        assert!(err.contains("1 incomplete subtask(s)"), "{}", err);
+        let err = run_on(&path, "done", &["#3"]).unwrap_err();
+        assert!(err.contains("blocked by #1"), "{}", err);
+        assert_eq!(titles(&path).1, Vec::<String>::new());
+
+        run_on(&path, "done", &["--force", "1"]).unwrap();
+        assert_eq!(
+            titles(&path),
+            (
+                vec!["Blocked".into()],
+                vec!["Parent".into(), "Child".into()]
+            )
+        );
+        // Nothing blocks it anymore
+        run_on(&path, "done", &["#3"]).unwrap();
+        assert!(run_on(&path, "done", &["#3"]).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rm_removes_the_subtree() {
+        let path = temp_path("rm");
+        fs::write(&path, TREE).unwrap();
+        run_on(&path, "rm", &["1"]).unwrap();
+        assert_eq!(titles(&path), (vec!["Blocked".into()], vec![]));
+        assert!(run_on(&path, "rm", &["--done", "1"]).is_err());
+        assert!(run_on(&path, "rm", &["#9"]).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn move_keeps_subtrees_among_their_siblings() {
+        let path = temp_path("move");
+        fs::write(&path, TREE).unwrap();
+        run_on(&path, "move", &["1", "down"]).unwrap();
+        let order = vec!["Blocked".to_string(), "Parent".into(), "Child".into()];
+        assert_eq!(titles(&path).0, order);
+        // A subtask has no sibling to pass
+        run_on(&path, "move", &["#2", "top"]).unwrap();
+        assert_eq!(titles(&path).0, order);
+        run_on(&path, "move", &["2", "top"]).unwrap();
+        assert_eq!(titles(&path).0, ["Parent", "Child", "Blocked"]);
+        assert!(run_on(&path, "move", &["1", "sideways"]).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn import_appends_with_fresh_ids() {
+        let path = temp_path("import");
+        let input = temp_path("import.txt");
+        fs::write(&path, TREE).unwrap();
+        fs::write(&input, "Imported +work\nx 2024-05-02 Finished\n").unwrap();
+        run_on(&path, "import", &[&input]).unwrap();
+        let (todos, dones) = format::load(&path, Format::Native).unwrap();
+        assert_eq!(todos[3].title, "Imported");
+        assert_eq!(todos[3].projects, ["work"]);
+        assert_eq!(dones[0].title, "Finished");
+        let mut ids: Vec<u64> = todos.iter().chain(&dones).map(|item| item.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 5);
+        assert!(run_on(&path, "import", &[]).is_err());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn merge_takes_the_changes_of_both_sides() {
+        let [base, ours, theirs] = ["base", "ours", "theirs"].map(temp_path);
+        fs::write(&base, TREE).unwrap();
+        fs::write(&ours, format!("{}TODO: Ours\n  id: 4\n", TREE)).unwrap();
+        fs::write(&theirs, TREE.replace("TODO: Child", "TODO: Their child")).unwrap();
+        run_on("todo", "merge", &[&base, &ours, &theirs]).unwrap();
+        assert_eq!(
+            titles(&ours).0,
+            ["Parent", "Their child", "Blocked", "Ours"]
+        );
+
+        // Both sides change the same title
+        fs::write(&ours, TREE.replace("TODO: Child", "TODO: Our child")).unwrap();
+        let err = run_on("todo", "merge", &[&base, &ours, &theirs]).unwrap_err();
+        assert_eq!(err, "both sides changed #2");
+        assert!(fs::read_to_string(&ours).unwrap().contains("<<<<<<<"));
+        for path in [base, ours, theirs] {
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
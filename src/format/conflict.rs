@@ -0,0 +1,54 @@
+/// Joins two renderings of the same items, wrapping every run of lines where
+/// they differ in git style conflict markers.
+pub fn mark(ours: &str, theirs: &str) -> String {
+    let a: Vec<&str> = ours.split_inclusive('\n').collect();
+    let b: Vec<&str> = theirs.split_inclusive('\n').collect();
+    // Length of the longest common subsequence of `a[i..]` and `b[j..]`
+    let mut common = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i] == b[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = String::new();
+    let mut hunk: (Vec<&str>, Vec<&str>) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            flush(&mut result, &mut hunk);
+            result.push_str(a[i]);
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+            hunk.0.push(a[i]);
+            i += 1;
+        } else {
+            hunk.1.push(b[j]);
+            j += 1;
+        }
+    }
+    flush(&mut result, &mut hunk);
+    result
+}
+
+fn flush(result: &mut String, hunk: &mut (Vec<&str>, Vec<&str>)) {
+    if hunk.0.is_empty() && hunk.1.is_empty() {
+        return;
+    }
+    let mut push = |line: &str| {
+        result.push_str(line);
+        if !line.ends_with('\n') {
+            result.push('\n');
+        }
+    };
+    push("<<<<<<< ours");
+    hunk.0.drain(..).for_each(&mut push);
+    push("=======");
+    hunk.1.drain(..).for_each(&mut push);
+    push(">>>>>>> theirs");
+}
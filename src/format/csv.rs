@@ -0,0 +1,138 @@
+use crate::todo::date::format_timestamp;
+use crate::todo::item::Item;
+
+// Flat export for spreadsheets, one row per item with a header row. Lists
+// like projects are joined with spaces.
+
+pub const COLUMNS: [&str; 13] = [
+    "id",
+    "status",
+    "title",
+    "parent",
+    "due",
+    "priority",
+    "projects",
+    "tags",
+    "created",
+    "completed",
+    "recurrence",
+    "depends",
+    "note",
+];
+
+pub const DEFAULT_COLUMNS: [&str; 8] = [
+    "id",
+    "status",
+    "title",
+    "due",
+    "priority",
+    "projects",
+    "tags",
+    "completed",
+];
+
+/// Parses a comma separated list of column names.
+pub fn parse_columns(text: &str) -> Result<Vec<String>, String> {
+    text.split(',')
+        .map(|column| column.trim())
+        .map(|column| {
+            if COLUMNS.contains(&column) {
+                Ok(column.to_string())
+            } else {
+                Err(format!(
+                    "unknown column `{}`, expected one of {}",
+                    column,
+                    COLUMNS.join(",")
+                ))
+            }
+        })
+        .collect()
+}
+
+fn cell(item: &Item, done: bool, column: &str) -> String {
+    let join = |values: &[String]| values.join(" ");
+    match column {
+        "id" => item.id.to_string(),
+        "status" => (if done { "done" } else { "todo" }).to_string(),
+        "title" => item.title.clone(),
+        "parent" => item.parent.map(|id| id.to_string()).unwrap_or_default(),
+        "due" => item.due.map(|due| due.to_string()).unwrap_or_default(),
+        "priority" => item.priority.map(String::from).unwrap_or_default(),
+        "projects" => join(&item.projects),
+        "tags" => join(&item.tags),
+        "created" => item.created.map(format_timestamp).unwrap_or_default(),
+        "completed" => item.completed.map(format_timestamp).unwrap_or_default(),
+        "recurrence" => item
+            .recurrence
+            .as_ref()
+            .map(|rule| rule.to_string())
+            .unwrap_or_default(),
+        "depends" => {
+            let ids: Vec<String> = item.depends.iter().map(|id| id.to_string()).collect();
+            ids.join(" ")
+        }
+        "note" => item.note.clone(),
+        _ => String::new(),
+    }
+}
+
+fn escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn render(todos: &[Item], dones: &[Item], columns: &[String]) -> String {
+    let mut out = String::new();
+    let header: Vec<String> = columns.iter().map(|column| escape(column)).collect();
+    out.push_str(&header.join(","));
+    out.push_str("\r\n");
+    let items = todos
+        .iter()
+        .map(|item| (item, false))
+        .chain(dones.iter().map(|item| (item, true)));
+    for (item, done) in items {
+        let row: Vec<String> = columns
+            .iter()
+            .map(|column| escape(&cell(item, done, column)))
+            .collect();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_chosen_columns_are_written_in_order() {
+        let columns = parse_columns("title, id,status").unwrap();
+        assert_eq!(columns, ["title", "id", "status"]);
+        let mut done = Item::new(2, "Book the room");
+        done.projects = vec!["work".to_string(), "office".to_string()];
+        let content = render(&[Item::new(1, "Write")], &[done], &columns);
+        assert_eq!(
+            content,
+            "title,id,status\r\nWrite,1,todo\r\nBook the room,2,done\r\n"
+        );
+        let columns = parse_columns("projects").unwrap();
+        assert!(render(&[], &[Item::new(2, "x")], &columns).ends_with("\r\n\r\n"));
+        assert!(parse_columns("title,size").is_err());
+    }
+
+    #[test]
+    fn commas_quotes_and_newlines_are_quoted() {
+        let mut item = Item::new(1, "Buy milk, eggs");
+        item.note = "Say \"hi\"\nthen leave".to_string();
+        let columns = parse_columns("title,note,id").unwrap();
+        let content = render(&[item], &[], &columns);
+        assert_eq!(
+            content,
+            "title,note,id\r\n\"Buy milk, eggs\",\"Say \"\"hi\"\"\nthen leave\",1\r\n"
+        );
+    }
+}
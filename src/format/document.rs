@@ -0,0 +1,163 @@
+use super::*;
+use crate::todo::merge::{self, Merge};
+
+/// The file being edited and what it held when it was last read or written.
+pub struct Document {
+    pub path: String,
+    pub format: Format,
+    pub stamp: Stamp,
+    /// Items as they are on disk, the base when merging with someone else's
+    /// changes.
+    pub base: (Vec<Item>, Vec<Item>),
+}
+
+impl Document {
+    /// Loads the items, a missing file is an empty one. The document keeps the
+    /// format the file turned out to be in.
+    pub fn open(path: &str, format: Format) -> io::Result<(Self, Vec<Item>, Vec<Item>)> {
+        let (todos, dones, stamp, format) = match load_stamped(path, format) {
+            Ok(loaded) => loaded,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                (Vec::new(), Vec::new(), Stamp::read(path), format)
+            }
+            Err(err) => return Err(err),
+        };
+        let document = Document {
+            path: path.to_string(),
+            format,
+            stamp,
+            base: (todos.clone(), dones.clone()),
+        };
+        Ok((document, todos, dones))
+    }
+
+    /// Whether the items differ from the ones last read or written.
+    pub fn unsaved(&self, todos: &[Item], dones: &[Item]) -> bool {
+        todos != self.base.0 || dones != self.base.1
+    }
+
+    pub fn changed_on_disk(&self) -> bool {
+        self.stamp.changed(&self.path)
+    }
+
+    fn saved(&mut self, stamp: Stamp, todos: &[Item], dones: &[Item]) {
+        self.stamp = stamp;
+        self.base = (todos.to_vec(), dones.to_vec());
+    }
+
+    /// Saves unless someone else changed the file since, returns whether it did.
+    pub fn save(&mut self, todos: &[Item], dones: &[Item]) -> io::Result<bool> {
+        match save_unless_changed(&self.path, self.format, todos, dones, Some(&self.stamp))? {
+            Some(stamp) => {
+                self.saved(stamp, todos, dones);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn overwrite(&mut self, todos: &[Item], dones: &[Item]) -> io::Result<()> {
+        let stamp = save(&self.path, self.format, todos, dones)?;
+        self.saved(stamp, todos, dones);
+        Ok(())
+    }
+
+    /// Gives the items read from the file the ids they have in the base.
+    /// Formats that do not keep ids number items by their position, so an
+    /// item inserted above would shift the ids of all the ones below.
+    fn match_ids(&self, todos: Vec<Item>, dones: Vec<Item>) -> (Vec<Item>, Vec<Item>) {
+        let mut lists = (todos, dones);
+        if !self.format.keeps_ids() {
+            merge::match_items((&self.base.0, &self.base.1), &mut lists);
+        }
+        lists
+    }
+
+    /// Reads the file again, dropping our changes.
+    pub fn reload(&mut self) -> io::Result<(Vec<Item>, Vec<Item>)> {
+        let (document, todos, dones) = Document::open(&self.path, self.format)?;
+        let (todos, dones) = self.match_ids(todos, dones);
+        *self = Document {
+            base: (todos.clone(), dones.clone()),
+            ..document
+        };
+        Ok((todos, dones))
+    }
+
+    /// Merges our items with the ones on disk, the result becomes ours but is
+    /// not saved yet.
+    pub fn merge(&mut self, todos: &[Item], dones: &[Item]) -> io::Result<Merge> {
+        let (theirs, their_todos, their_dones) = Document::open(&self.path, self.format)?;
+        // Our items already have the ids of the base, we edited them here
+        let (their_todos, their_dones) = self.match_ids(their_todos, their_dones);
+        let merged = merge::merge(
+            (&self.base.0, &self.base.1),
+            (todos, dones),
+            (&their_todos, &their_dones),
+        );
+        *self = Document {
+            base: (their_todos, their_dones),
+            ..theirs
+        };
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn titles(list: &[Item]) -> Vec<&str> {
+        list.iter().map(|item| item.title.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_matches_items_by_title_when_ids_are_positions() {
+        let path = env::temp_dir().join(format!("todors-document-{}.txt", process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, "a\nb\n").unwrap();
+        let (mut document, mut todos, dones) = Document::open(path, Format::TodoTxt).unwrap();
+        todos[1].title = "b edited".to_string();
+        fs::write(path, "new\na\nb\n").unwrap();
+
+        let merged = document.merge(&todos, &dones).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(titles(&merged.todos), ["new", "a", "b edited"]);
+    }
+
+    #[test]
+    fn json_arrays_are_saved_back_as_taskwarrior_tasks() {
+        let path = env::temp_dir().join(format!("todors-document-{}.json", process::id()));
+        let path = path.to_str().unwrap();
+        let task = |uuid: &str, title: &str| {
+            format!(
+                r#"{{"uuid":"{}","description":"{}","status":"pending"}}"#,
+                uuid, title
+            )
+        };
+        fs::write(path, format!("[{},{}]", task("a", "a"), task("b", "b"))).unwrap();
+        let (mut document, mut todos, dones) = Document::open(path, Format::Json).unwrap();
+        assert_eq!(document.format, Format::Taskwarrior);
+        todos[1].title = "b edited".to_string();
+        assert!(document.save(&todos, &dones).unwrap());
+        let saved = fs::read_to_string(path).unwrap();
+        fs::write(
+            path,
+            saved.replacen('[', &format!("[{},", task("c", "new")), 1),
+        )
+        .unwrap();
+
+        let (reloaded, _) = document.reload().unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(saved.starts_with('[') && saved.contains(r#""uuid": "a""#));
+        assert_eq!(titles(&reloaded), ["new", "a", "b edited"]);
+        // Still the items they were, not the ones at their old positions
+        assert_eq!(reloaded[1].id, todos[0].id);
+        assert_eq!(reloaded[2].id, todos[1].id);
+    }
+}
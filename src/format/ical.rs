@@ -0,0 +1,582 @@
+use crate::todo::board;
+use crate::todo::clock::{Clock, SystemClock};
+use crate::todo::date::*;
+use crate::todo::item::*;
+use crate::todo::recurrence::{Frequency, Recurrence};
+use crate::todo::tree;
+use std::collections::HashMap;
+
+// iCalendar (RFC 5545) `VTODO` components:
+//
+//     BEGIN:VTODO
+//     UID:3@todors
+//     SUMMARY:Write the report
+//     STATUS:NEEDS-ACTION
+//     DUE;VALUE=DATE:20240510
+//     PRIORITY:1
+//     CATEGORIES:+work,office
+//     RRULE:FREQ=WEEKLY;BYDAY=MO,FR
+//     RELATED-TO;RELTYPE=PARENT:1@todors
+//     END:VTODO
+//
+// Priorities A-I map to 1-9, projects are categories starting with `+`.
+// Parents and dependencies are `RELATED-TO` the `UID` of the other item, a
+// `UID` that is not ours is kept as the `uid` extension for the way back.
+// Items off the default board carry it in `X-TODORS-BOARD`.
+// Other components and properties are skipped. `DTSTAMP` is when an item
+// last changed, items saved unchanged keep theirs.
+
+const UID_SUFFIX: &str = "@todors";
+const BYDAY: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Joins folded lines, a line starting with a space or tab continues the
+/// previous one.
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ if line.is_empty() => {}
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+    // The value starts after the first colon that is not inside quotes
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+    let mut parts = line[..colon].split(';');
+    let name = parts.next()?.to_ascii_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.to_ascii_uppercase(),
+                value.trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value: line[colon + 1..].to_string(),
+    })
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some(next @ ('\\' | ';' | ',' | ':'))) => {
+                out.push(next);
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Splits a list value on the commas that are not escaped.
+fn split_list(text: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ',' if !escaped => {
+                values.push(unescape(&text[start..i]));
+                start = i + 1;
+            }
+            _ => escaped = false,
+        }
+    }
+    values.push(unescape(&text[start..]));
+    values
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Parses `YYYYMMDD` and `YYYYMMDDTHHMMSS[Z]`, times without a zone are taken as UTC.
+fn parse_date_time(text: &str) -> Option<(Date, i64)> {
+    let number = |range: std::ops::Range<usize>| -> Option<u32> { text.get(range)?.parse().ok() };
+    let date = Date::new(number(0..4)? as i32, number(4..6)?, number(6..8)?)?;
+    let time = match text.get(8..) {
+        Some("") => 0,
+        Some(_) if text.as_bytes()[8] == b'T' => {
+            let (hours, minutes, seconds) = (number(9..11)?, number(11..13)?, number(13..15)?);
+            if hours >= 24 || minutes >= 60 || seconds >= 61 {
+                return None;
+            }
+            (hours * 3600 + minutes * 60 + seconds) as i64
+        }
+        _ => return None,
+    };
+    Some((date, date.days() * 86400 + time))
+}
+
+fn format_date(date: Date) -> String {
+    format!("{:04}{:02}{:02}", date.year, date.month, date.day)
+}
+
+fn format_date_time(secs: i64) -> String {
+    let time = secs.rem_euclid(86400);
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        format_date(Date::from_days(secs.div_euclid(86400))),
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+fn parse_rrule(value: &str) -> Option<Recurrence> {
+    let parts: HashMap<String, &str> = value
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .map(|(key, value)| (key.to_ascii_uppercase(), value))
+        .collect();
+    let interval: u32 = match parts.get("INTERVAL") {
+        Some(interval) => interval.parse().ok().filter(|interval| *interval > 0)?,
+        None => 1,
+    };
+    let frequency = match (parts.get("FREQ")?.to_ascii_uppercase().as_str(), interval) {
+        ("DAILY", 1) => Frequency::Daily,
+        ("DAILY", days) => Frequency::EveryDays(days),
+        ("WEEKLY", 1) => {
+            let mut weekdays = Vec::new();
+            for day in parts.get("BYDAY").map_or("", |days| days).split(',') {
+                if day.is_empty() {
+                    continue;
+                }
+                weekdays.push(
+                    BYDAY
+                        .iter()
+                        .position(|name| day.eq_ignore_ascii_case(name))?,
+                );
+            }
+            weekdays.sort();
+            weekdays.dedup();
+            Frequency::Weekly(weekdays)
+        }
+        ("WEEKLY", weeks) if !parts.contains_key("BYDAY") => Frequency::EveryDays(weeks * 7),
+        ("MONTHLY", 1) => Frequency::Monthly,
+        _ => return None,
+    };
+    Some(Recurrence {
+        frequency,
+        after_completion: false,
+    })
+}
+
+fn render_rrule(recurrence: &Recurrence) -> String {
+    match &recurrence.frequency {
+        Frequency::Daily => "FREQ=DAILY".to_string(),
+        Frequency::EveryDays(days) => format!("FREQ=DAILY;INTERVAL={}", days),
+        Frequency::Weekly(weekdays) if weekdays.is_empty() => "FREQ=WEEKLY".to_string(),
+        Frequency::Weekly(weekdays) => {
+            let days: Vec<&str> = weekdays.iter().map(|day| BYDAY[*day]).collect();
+            format!("FREQ=WEEKLY;BYDAY={}", days.join(","))
+        }
+        Frequency::Monthly => "FREQ=MONTHLY".to_string(),
+    }
+}
+
+struct Todo {
+    item: Item,
+    done: bool,
+    uid: Option<String>,
+    after_completion: bool,
+    /// `(reltype, uid)` of the `RELATED-TO` properties.
+    related: Vec<(String, String)>,
+    /// `DTSTAMP` as written.
+    stamp: Option<String>,
+}
+
+fn apply(todo: &mut Todo, property: &Property) -> Result<(), String> {
+    let item = &mut todo.item;
+    let invalid = || format!("invalid {} `{}`", property.name, property.value);
+    match property.name.as_str() {
+        "UID" => todo.uid = Some(property.value.clone()),
+        "DTSTAMP" => todo.stamp = Some(property.value.clone()),
+        "SUMMARY" => item.title = unescape(&property.value),
+        "DESCRIPTION" => item.note = unescape(&property.value),
+        "STATUS" => todo.done = matches!(property.value.as_str(), "COMPLETED" | "CANCELLED"),
+        "DUE" => item.due = Some(parse_date_time(&property.value).ok_or_else(invalid)?.0),
+        "CREATED" => item.created = Some(parse_date_time(&property.value).ok_or_else(invalid)?.1),
+        "COMPLETED" => {
+            item.completed = Some(parse_date_time(&property.value).ok_or_else(invalid)?.1)
+        }
+        "PRIORITY" => match property.value.parse::<u8>() {
+            Ok(0) => item.priority = None,
+            Ok(priority @ 1..=9) => item.priority = Some((b'A' + priority - 1) as char),
+            _ => return Err(invalid()),
+        },
+        "CATEGORIES" => {
+            for category in split_list(&property.value) {
+                match category.strip_prefix('+') {
+                    Some(project) => item.projects.push(project.to_string()),
+                    None => item.tags.push(category),
+                }
+            }
+        }
+        // Rules we can not follow are left out
+        "RRULE" => item.recurrence = parse_rrule(&property.value),
+        "X-TODORS-AFTER-COMPLETION" => todo.after_completion = true,
+        "X-TODORS-BOARD" => board::assign(item, &unescape(&property.value)),
+        "RELATED-TO" => todo.related.push((
+            property
+                .param("RELTYPE")
+                .unwrap_or("PARENT")
+                .to_ascii_uppercase(),
+            property.value.clone(),
+        )),
+        _ => {}
+    }
+    Ok(())
+}
+
+pub fn parse(content: &str) -> Result<(Vec<Item>, Vec<Item>), String> {
+    parse_stamped(content).map(|(todos, dones, _)| (todos, dones))
+}
+
+/// Items with the `DTSTAMP` of each by id.
+type Stamped = (Vec<Item>, Vec<Item>, HashMap<u64, String>);
+
+fn parse_stamped(content: &str) -> Result<Stamped, String> {
+    let mut todos: Vec<Todo> = Vec::new();
+    let mut current: Option<Todo> = None;
+    // Components nested inside a VTODO, like VALARM
+    let mut nested = 0;
+    for (index, line) in unfold(content).iter().enumerate() {
+        let property =
+            parse_property(line).ok_or_else(|| format!("{}: invalid line", index + 1))?;
+        match (property.name.as_str(), &mut current) {
+            ("BEGIN", None) if property.value.eq_ignore_ascii_case("VTODO") => {
+                current = Some(Todo {
+                    item: Item::new(0, ""),
+                    done: false,
+                    uid: None,
+                    after_completion: false,
+                    related: Vec::new(),
+                    stamp: None,
+                })
+            }
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) => todos.extend(current.take()),
+            (_, Some(todo)) if nested == 0 => {
+                apply(todo, &property).map_err(|err| format!("{}: {}", index + 1, err))?
+            }
+            _ => {}
+        }
+    }
+
+    // Our own UIDs give the ids back, the others get new ones
+    let mut ids: HashMap<String, u64> = HashMap::new();
+    for todo in &mut todos {
+        let own = todo
+            .uid
+            .as_deref()
+            .and_then(|uid| uid.strip_suffix(UID_SUFFIX))
+            .and_then(|id| id.parse().ok())
+            .filter(|id| !ids.values().any(|other| other == id));
+        match (own, &todo.uid) {
+            (Some(id), _) => todo.item.id = id,
+            (None, Some(uid)) => todo.item.extensions.push(("uid".to_string(), uid.clone())),
+            (None, None) => {}
+        }
+        if let (Some(uid), true) = (&todo.uid, todo.item.id != 0) {
+            ids.insert(uid.clone(), todo.item.id);
+        }
+    }
+    let mut next_id = todos.iter().map(|todo| todo.item.id).max().unwrap_or(0) + 1;
+    for todo in &mut todos {
+        if todo.item.id == 0 {
+            todo.item.id = next_id;
+            next_id += 1;
+            if let Some(uid) = &todo.uid {
+                ids.insert(uid.clone(), todo.item.id);
+            }
+        }
+    }
+
+    let mut list_todos = Vec::new();
+    let mut list_dones = Vec::new();
+    let mut stamps = HashMap::new();
+    for mut todo in todos {
+        if let Some(stamp) = todo.stamp.take() {
+            stamps.insert(todo.item.id, stamp);
+        }
+        if let (Some(rule), true) = (&mut todo.item.recurrence, todo.after_completion) {
+            rule.after_completion = true;
+        }
+        for (reltype, uid) in &todo.related {
+            match (reltype.as_str(), ids.get(uid)) {
+                ("PARENT", Some(id)) => todo.item.parent = Some(*id),
+                ("DEPENDS-ON", Some(id)) => todo.item.depends.push(*id),
+                _ => {}
+            }
+        }
+        if todo.done {
+            list_dones.push(todo.item);
+        } else {
+            list_todos.push(todo.item);
+        }
+    }
+    tree::normalize(&mut list_todos);
+    tree::normalize(&mut list_dones);
+    Ok((list_todos, list_dones, stamps))
+}
+
+/// Folds a content line to 75 octets, never splitting a character.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn uid_of(item: &Item) -> String {
+    item.extensions
+        .iter()
+        .find(|(key, _)| key == "uid")
+        .map(|(_, uid)| uid.clone())
+        .unwrap_or_else(|| format!("{}{}", item.id, UID_SUFFIX))
+}
+
+fn render_todo(
+    out: &mut String,
+    item: &Item,
+    done: bool,
+    uids: &HashMap<u64, String>,
+    stamp: &str,
+) {
+    push_line(out, "BEGIN:VTODO");
+    push_line(out, &format!("UID:{}", uid_of(item)));
+    push_line(out, &format!("DTSTAMP:{}", stamp));
+    if let Some(created) = item.created {
+        push_line(out, &format!("CREATED:{}", format_date_time(created)));
+    }
+    push_line(out, &format!("SUMMARY:{}", escape(&item.title)));
+    push_line(
+        out,
+        if done {
+            "STATUS:COMPLETED"
+        } else {
+            "STATUS:NEEDS-ACTION"
+        },
+    );
+    if let Some(due) = item.due {
+        push_line(out, &format!("DUE;VALUE=DATE:{}", format_date(due)));
+    }
+    if let Some(priority) = item.priority {
+        let priority = (priority as u8 - b'A' + 1).min(9);
+        push_line(out, &format!("PRIORITY:{}", priority));
+    }
+    let categories: Vec<String> = item
+        .projects
+        .iter()
+        .map(|project| escape(&format!("+{}", project)))
+        .chain(item.tags.iter().map(|tag| escape(tag)))
+        .collect();
+    if !categories.is_empty() {
+        push_line(out, &format!("CATEGORIES:{}", categories.join(",")));
+    }
+    if let Some(recurrence) = &item.recurrence {
+        push_line(out, &format!("RRULE:{}", render_rrule(recurrence)));
+        if recurrence.after_completion {
+            push_line(out, "X-TODORS-AFTER-COMPLETION:TRUE");
+        }
+    }
+    if let Some(completed) = item.completed {
+        push_line(out, &format!("COMPLETED:{}", format_date_time(completed)));
+    }
+    if !item.note.is_empty() {
+        push_line(out, &format!("DESCRIPTION:{}", escape(&item.note)));
+    }
+    if board::name(item) != board::DEFAULT {
+        push_line(
+            out,
+            &format!("X-TODORS-BOARD:{}", escape(board::name(item))),
+        );
+    }
+    if let Some(uid) = item.parent.and_then(|id| uids.get(&id)) {
+        push_line(out, &format!("RELATED-TO;RELTYPE=PARENT:{}", uid));
+    }
+    for uid in item.depends.iter().filter_map(|id| uids.get(id)) {
+        push_line(out, &format!("RELATED-TO;RELTYPE=DEPENDS-ON:{}", uid));
+    }
+    push_line(out, "END:VTODO");
+}
+
+/// Renders the items, `DTSTAMP` tells when an item last changed so items
+/// that are the same as in `original` keep theirs.
+pub fn render(original: Option<&str>, todos: &[Item], dones: &[Item]) -> String {
+    let uids: HashMap<u64, String> = todos
+        .iter()
+        .chain(dones.iter())
+        .map(|item| (item.id, uid_of(item)))
+        .collect();
+    let before: HashMap<u64, (Item, bool, String)> = match original.map(parse_stamped) {
+        Some(Ok((old_todos, old_dones, mut stamps))) => old_todos
+            .into_iter()
+            .map(|item| (item, false))
+            .chain(old_dones.into_iter().map(|item| (item, true)))
+            .filter_map(|(item, done)| {
+                let stamp = stamps.remove(&item.id)?;
+                Some((item.id, (item, done, stamp)))
+            })
+            .collect(),
+        _ => HashMap::new(),
+    };
+    let now = format_date_time(SystemClock.now());
+    let stamp = |item: &Item, done: bool| -> &str {
+        match before.get(&item.id) {
+            Some((old, old_done, stamp)) if old == item && *old_done == done => stamp,
+            _ => &now,
+        }
+    };
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//todors//todors//EN");
+    for item in todos {
+        render_todo(&mut out, item, false, &uids, stamp(item, false));
+    }
+    for item in dones {
+        render_todo(&mut out, item, true, &uids, stamp(item, true));
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items() -> (Vec<Item>, Vec<Item>) {
+        let mut parent = Item::new(1, "Write the report, part 1; draft");
+        parent.due = Date::parse("2024-05-10");
+        parent.priority = Some('A');
+        parent.projects = vec!["work".to_string()];
+        parent.tags = vec!["office".to_string()];
+        parent.recurrence = Some(Recurrence::parse("weekly mon,fri").unwrap());
+        parent.created = parse_timestamp("2024-05-01T09:00:00Z");
+        parent.note = "First line\nSecond line".to_string();
+        let mut child = Item::new(2, "Gather numbers");
+        child.parent = Some(1);
+        let mut blocked = Item::new(3, "Send it");
+        blocked.depends = vec![1];
+        let mut done = Item::new(4, "Book the room");
+        done.completed = parse_timestamp("2024-05-02T10:00:00Z");
+        done.recurrence = Some(Recurrence::parse("every 3 days after-completion").unwrap());
+        (vec![parent, child, blocked], vec![done])
+    }
+
+    /// Gives every `DTSTAMP` the same old value.
+    fn stamped(content: &str) -> String {
+        content
+            .split("\r\n")
+            .map(|line| {
+                if line.starts_with("DTSTAMP:") {
+                    "DTSTAMP:20200101T000000Z"
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<&str>>()
+            .join("\r\n")
+    }
+
+    #[test]
+    fn items_survive_a_round_trip() {
+        let (todos, dones) = items();
+        let content = render(None, &todos, &dones);
+        assert_eq!(parse(&content), Ok((todos, dones)));
+    }
+
+    #[test]
+    fn boards_survive_a_round_trip() {
+        let (mut todos, dones) = items();
+        board::assign(&mut todos[1], "home");
+        let content = render(None, &todos, &dones);
+        assert!(content.contains("X-TODORS-BOARD:home\r\n"));
+        assert_eq!(parse(&content), Ok((todos, dones)));
+    }
+
+    #[test]
+    fn foreign_uids_are_kept() {
+        let content = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nUID:abc@example.com\r\n\
+                       SUMMARY:Theirs\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let (todos, dones) = parse(content).unwrap();
+        assert_eq!(
+            todos[0].extensions,
+            [("uid".to_string(), "abc@example.com".to_string())]
+        );
+        assert!(render(None, &todos, &dones).contains("UID:abc@example.com\r\n"));
+    }
+
+    #[test]
+    fn unchanged_items_keep_their_stamp() {
+        let (todos, dones) = items();
+        let original = stamped(&render(None, &todos, &dones));
+        assert_eq!(render(Some(&original), &todos, &dones), original);
+    }
+
+    #[test]
+    fn changed_items_get_a_new_stamp() {
+        let (mut todos, dones) = items();
+        let original = stamped(&render(None, &todos, &dones));
+        todos[2].title = "Send it today".to_string();
+        let rendered = render(Some(&original), &todos, &dones);
+        let old = rendered.matches("DTSTAMP:20200101T000000Z").count();
+        assert_eq!(old, 3);
+        assert_eq!(rendered.matches("DTSTAMP:").count(), 4);
+    }
+}
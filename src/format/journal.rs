@@ -0,0 +1,79 @@
+use super::*;
+use std::os::unix::fs::FileExt;
+
+/// Unsaved items of a session, kept next to the file until they are saved so
+/// they can be recovered when the session did not exit cleanly.
+pub struct Journal {
+    path: String,
+    /// Locked for as long as the session runs.
+    file: File,
+    /// What the journal holds, empty when nothing is unsaved.
+    content: String,
+}
+
+pub fn journal_path(path: &str) -> String {
+    format!("{}.journal", path)
+}
+
+impl Journal {
+    /// Takes the journal of `path`, `None` when another session is running on
+    /// the file.
+    pub fn open(path: &str) -> io::Result<Option<Self>> {
+        let path = journal_path(path);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if !try_lock(&file)? {
+            return Ok(None);
+        }
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        Ok(Some(Journal {
+            path,
+            file,
+            content,
+        }))
+    }
+
+    /// What a session that did not exit cleanly left unsaved.
+    pub fn left(&self) -> Option<(Vec<Item>, Vec<Item>)> {
+        if self.content.is_empty() {
+            return None;
+        }
+        // A journal cut short while being written has nothing to recover
+        native::parse(&self.content).ok()
+    }
+
+    /// Records the unsaved items, writing only when they changed.
+    pub fn record(&mut self, todos: &[Item], dones: &[Item]) -> io::Result<()> {
+        let content = native::render(todos, dones);
+        if content != self.content {
+            self.write(content)?;
+        }
+        Ok(())
+    }
+
+    /// Empties the journal once everything is saved.
+    pub fn clear(&mut self) -> io::Result<()> {
+        if !self.content.is_empty() {
+            self.write(String::new())?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, content: String) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.write_all_at(content.as_bytes(), 0)?;
+        self.file.sync_data()?;
+        self.content = content;
+        Ok(())
+    }
+
+    /// Removes the journal when the session exits cleanly.
+    pub fn close(self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+}
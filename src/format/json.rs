@@ -0,0 +1,629 @@
+use crate::todo::date::*;
+use crate::todo::item::*;
+use crate::todo::recurrence::Recurrence;
+
+// Lossless JSON of the whole item model, version 1:
+//
+//     {
+//       "version": 1,
+//       "items": [
+//         {
+//           "id": 3,
+//           "status": "todo",               // or "done"
+//           "title": "Write the report",
+//           "parent": 1,                    // or null
+//           "folded": false,
+//           "note": "First line\nSecond line",
+//           "due": "2024-05-10",            // or null
+//           "recurrence": "weekly mon,fri", // or null, same syntax as `:recur`
+//           "depends": [1, 2],
+//           "created": "2024-05-01T09:00:00Z",   // or null
+//           "completed": "2024-05-09T17:30:00Z", // or null
+//           "history": ["2024-05-02T10:00:00Z"],
+//           "priority": "A",                // or null
+//           "projects": ["work"],
+//           "tags": ["office"],
+//           "extensions": [["key", "value"]]
+//         }
+//       ]
+//     }
+//
+// TODO items come first, then DONE items, each in their list order. The
+// tests pin the schema down, a change to it needs a new version.
+
+pub const VERSION: f64 = 1.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Keys keep their order.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_f64()
+            .filter(|number| *number >= 0.0 && number.fract() == 0.0)
+            .map(|number| number as u64)
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn string(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    /// Writes the value on one line when `indent` is `None`.
+    fn write(&self, out: &mut String, indent: Option<usize>) {
+        let newline = |out: &mut String, depth: usize| {
+            if indent.is_some() {
+                out.push('\n');
+                out.push_str(&"  ".repeat(depth));
+            }
+        };
+        let depth = indent.unwrap_or(0);
+        let inner = indent.map(|indent| indent + 1);
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Value::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => {
+                out.push_str(&format!("{}", *number as i64))
+            }
+            Value::Number(number) => out.push_str(&number.to_string()),
+            Value::String(text) => write_string(out, text),
+            Value::Array(values) if values.is_empty() => out.push_str("[]"),
+            Value::Array(values) => {
+                out.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    value.write(out, inner);
+                }
+                newline(out, depth);
+                out.push(']');
+            }
+            Value::Object(fields) if fields.is_empty() => out.push_str("{}"),
+            Value::Object(fields) => {
+                out.push('{');
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    write_string(out, key);
+                    out.push_str(if indent.is_some() { ": " } else { ":" });
+                    value.write(out, inner);
+                }
+                newline(out, depth);
+                out.push('}');
+            }
+        }
+    }
+
+    pub fn to_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some(0));
+        out.push('\n');
+        out
+    }
+
+    pub fn to_compact(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, None);
+        out
+    }
+}
+
+fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    text: &'a str,
+}
+
+impl Parser<'_> {
+    fn error(&mut self, message: &str) -> String {
+        let offset = self.chars.peek().map_or(self.text.len(), |(i, _)| *i);
+        let line = self.text[..offset].matches('\n').count() + 1;
+        format!("{}: {}", line, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|(_, c)| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected `{}`", expected))),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            match self.chars.next() {
+                Some((_, c)) if c == expected => {}
+                _ => return Err(self.error(&format!("expected `{}`", word))),
+            }
+        }
+        Ok(value)
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, c)| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(text),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => text.push('"'),
+                    Some((_, '\\')) => text.push('\\'),
+                    Some((_, '/')) => text.push('/'),
+                    Some((_, 'b')) => text.push('\u{8}'),
+                    Some((_, 'f')) => text.push('\u{c}'),
+                    Some((_, 'n')) => text.push('\n'),
+                    Some((_, 'r')) => text.push('\r'),
+                    Some((_, 't')) => text.push('\t'),
+                    Some((_, 'u')) => {
+                        let mut code = self.hex()?;
+                        if (0xd800..0xdc00).contains(&code) {
+                            self.keyword("\\u", Value::Null)?;
+                            let low = self.hex()?;
+                            code = 0x10000
+                                + ((code - 0xd800) << 10)
+                                + (low.wrapping_sub(0xdc00) & 0x3ff);
+                        }
+                        text.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    _ => return Err(self.error("invalid escape")),
+                },
+                Some((_, c)) => text.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.chars.peek().map_or(self.text.len(), |(i, _)| *i);
+        let mut end = start;
+        while let Some((i, c)) = self.chars.peek() {
+            if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                end = i + c.len_utf8();
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        self.text[start..end]
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek().map(|(_, c)| *c) {
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.chars.next();
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.chars.peek().is_some_and(|(_, c)| *c == ']') {
+                    self.chars.next();
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some((_, ',')) => {}
+                        Some((_, ']')) => return Ok(Value::Array(values)),
+                        _ => return Err(self.error("expected `,` or `]`")),
+                    }
+                }
+            }
+            Some('{') => {
+                self.chars.next();
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.chars.peek().is_some_and(|(_, c)| *c == '}') {
+                    self.chars.next();
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some((_, ',')) => {}
+                        Some((_, '}')) => return Ok(Value::Object(fields)),
+                        _ => return Err(self.error("expected `,` or `}`")),
+                    }
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+}
+
+/// Parses a JSON document, errors are prefixed with the line number.
+pub fn parse_value(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: text.char_indices().peekable(),
+        text,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(_) => Err(parser.error("trailing characters")),
+    }
+}
+
+fn optional<T>(value: Option<T>, f: impl Fn(T) -> Value) -> Value {
+    value.map_or(Value::Null, f)
+}
+
+fn strings(values: &[String]) -> Value {
+    Value::Array(values.iter().map(|value| Value::string(value)).collect())
+}
+
+fn item_value(item: &Item, done: bool) -> Value {
+    let timestamp = |secs: i64| Value::String(format_timestamp(secs));
+    Value::Object(vec![
+        ("id".to_string(), Value::Number(item.id as f64)),
+        (
+            "status".to_string(),
+            Value::string(if done { "done" } else { "todo" }),
+        ),
+        ("title".to_string(), Value::string(&item.title)),
+        (
+            "parent".to_string(),
+            optional(item.parent, |id| Value::Number(id as f64)),
+        ),
+        ("folded".to_string(), Value::Bool(item.folded)),
+        ("note".to_string(), Value::string(&item.note)),
+        (
+            "due".to_string(),
+            optional(item.due, |due| Value::String(due.to_string())),
+        ),
+        (
+            "recurrence".to_string(),
+            optional(item.recurrence.as_ref(), |rule| {
+                Value::String(rule.to_string())
+            }),
+        ),
+        (
+            "depends".to_string(),
+            Value::Array(
+                item.depends
+                    .iter()
+                    .map(|id| Value::Number(*id as f64))
+                    .collect(),
+            ),
+        ),
+        ("created".to_string(), optional(item.created, timestamp)),
+        ("completed".to_string(), optional(item.completed, timestamp)),
+        (
+            "history".to_string(),
+            Value::Array(item.history.iter().map(|secs| timestamp(*secs)).collect()),
+        ),
+        (
+            "priority".to_string(),
+            optional(item.priority, |priority| {
+                Value::String(priority.to_string())
+            }),
+        ),
+        ("projects".to_string(), strings(&item.projects)),
+        ("tags".to_string(), strings(&item.tags)),
+        (
+            "extensions".to_string(),
+            Value::Array(
+                item.extensions
+                    .iter()
+                    .map(|(key, value)| {
+                        Value::Array(vec![Value::string(key), Value::string(value)])
+                    })
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+pub fn render(todos: &[Item], dones: &[Item]) -> String {
+    let items = todos
+        .iter()
+        .map(|item| item_value(item, false))
+        .chain(dones.iter().map(|item| item_value(item, true)))
+        .collect();
+    Value::Object(vec![
+        ("version".to_string(), Value::Number(VERSION)),
+        ("items".to_string(), Value::Array(items)),
+    ])
+    .to_pretty()
+}
+
+fn field_strings(value: &Value, key: &str) -> Result<Vec<String>, String> {
+    match value.get(key) {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .map(|text| text.to_string())
+                    .ok_or_else(|| format!("`{}` must hold strings", key))
+            })
+            .collect(),
+        Some(_) => Err(format!("`{}` must be an array", key)),
+    }
+}
+
+fn field_timestamp(value: &Value, key: &str) -> Result<Option<i64>, String> {
+    match value.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(text)) => parse_timestamp(text)
+            .map(Some)
+            .ok_or_else(|| format!("invalid timestamp `{}`", text)),
+        Some(_) => Err(format!("`{}` must be a timestamp string", key)),
+    }
+}
+
+fn parse_item(value: &Value) -> Result<(Item, bool), String> {
+    let title = value
+        .get("title")
+        .and_then(Value::as_str)
+        .ok_or("item without a `title`")?;
+    let mut item = Item::new(value.get("id").and_then(Value::as_u64).unwrap_or(0), title);
+    let done = match value.get("status").and_then(Value::as_str) {
+        Some("todo") | None => false,
+        Some("done") => true,
+        Some(status) => return Err(format!("invalid status `{}`", status)),
+    };
+    item.parent = value.get("parent").and_then(Value::as_u64);
+    item.folded = value.get("folded") == Some(&Value::Bool(true));
+    item.note = value
+        .get("note")
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_string();
+    if let Some(due) = value.get("due").and_then(Value::as_str) {
+        item.due = Some(Date::parse(due).ok_or_else(|| format!("invalid date `{}`", due))?);
+    }
+    if let Some(rule) = value.get("recurrence").and_then(Value::as_str) {
+        item.recurrence = Some(Recurrence::parse(rule)?);
+    }
+    if let Some(depends) = value.get("depends").and_then(Value::as_array) {
+        item.depends = depends.iter().filter_map(Value::as_u64).collect();
+    }
+    item.created = field_timestamp(value, "created")?;
+    item.completed = field_timestamp(value, "completed")?;
+    for stamp in field_strings(value, "history")? {
+        item.history
+            .push(parse_timestamp(&stamp).ok_or_else(|| format!("invalid timestamp `{}`", stamp))?);
+    }
+    if let Some(priority) = value.get("priority").and_then(Value::as_str) {
+        match priority.as_bytes() {
+            [priority @ b'A'..=b'Z'] => item.priority = Some(*priority as char),
+            _ => return Err(format!("invalid priority `{}`", priority)),
+        }
+    }
+    item.projects = field_strings(value, "projects")?;
+    item.tags = field_strings(value, "tags")?;
+    if let Some(extensions) = value.get("extensions").and_then(Value::as_array) {
+        for extension in extensions {
+            match extension.as_array() {
+                Some([Value::String(key), Value::String(value)]) => {
+                    item.extensions.push((key.clone(), value.clone()))
+                }
+                _ => return Err("extensions must be `[key, value]` pairs".to_string()),
+            }
+        }
+    }
+    Ok((item, done))
+}
+
+pub fn parse(content: &str) -> Result<(Vec<Item>, Vec<Item>), String> {
+    let document = parse_value(content)?;
+    match document.get("version").and_then(Value::as_f64) {
+        Some(version) if version == VERSION => {}
+        Some(version) => return Err(format!("unsupported version {}", version)),
+        None => return Err("missing `version`".to_string()),
+    }
+    let items = document
+        .get("items")
+        .and_then(Value::as_array)
+        .ok_or("missing `items` array")?;
+    let mut todos = Vec::new();
+    let mut dones = Vec::new();
+    for value in items {
+        match parse_item(value)? {
+            (item, false) => todos.push(item),
+            (item, true) => dones.push(item),
+        }
+    }
+    assign_missing_ids(&mut todos, &mut dones);
+    Ok((todos, dones))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Version 1 of the schema with every field set, as `render` writes it.
+    const SCHEMA_V1: &str = r#"{
+  "version": 1,
+  "items": [
+    {
+      "id": 1,
+      "status": "todo",
+      "title": "Plan the quarter",
+      "parent": null,
+      "folded": true,
+      "note": "",
+      "due": null,
+      "recurrence": null,
+      "depends": [],
+      "created": null,
+      "completed": null,
+      "history": [],
+      "priority": null,
+      "projects": [],
+      "tags": [],
+      "extensions": []
+    },
+    {
+      "id": 3,
+      "status": "done",
+      "title": "Write the report",
+      "parent": 1,
+      "folded": false,
+      "note": "First line\nSecond line",
+      "due": "2024-05-10",
+      "recurrence": "weekly mon,fri",
+      "depends": [
+        1,
+        2
+      ],
+      "created": "2024-05-01T09:00:00Z",
+      "completed": "2024-05-09T17:30:00Z",
+      "history": [
+        "2024-05-02T10:00:00Z"
+      ],
+      "priority": "A",
+      "projects": [
+        "work"
+      ],
+      "tags": [
+        "office"
+      ],
+      "extensions": [
+        [
+          "key",
+          "value"
+        ]
+      ]
+    }
+  ]
+}"#;
+
+    fn items() -> (Vec<Item>, Vec<Item>) {
+        let mut todo = Item::new(1, "Plan the quarter");
+        todo.folded = true;
+        let mut done = Item::new(3, "Write the report");
+        done.parent = Some(1);
+        done.note = "First line\nSecond line".to_string();
+        done.due = Date::parse("2024-05-10");
+        done.recurrence = Some(Recurrence::parse("weekly mon,fri").unwrap());
+        done.depends = vec![1, 2];
+        done.created = parse_timestamp("2024-05-01T09:00:00Z");
+        done.completed = parse_timestamp("2024-05-09T17:30:00Z");
+        done.history = vec![parse_timestamp("2024-05-02T10:00:00Z").unwrap()];
+        done.priority = Some('A');
+        done.projects = vec!["work".to_string()];
+        done.tags = vec!["office".to_string()];
+        done.extensions = vec![("key".to_string(), "value".to_string())];
+        (vec![todo], vec![done])
+    }
+
+    #[test]
+    fn render_writes_version_1() {
+        let (todos, dones) = items();
+        assert_eq!(render(&todos, &dones).trim_end(), SCHEMA_V1);
+    }
+
+    #[test]
+    fn parse_reads_version_1() {
+        assert_eq!(parse(SCHEMA_V1), Ok(items()));
+    }
+
+    #[test]
+    fn optional_fields_can_be_left_out() {
+        let (todos, dones) = parse(r#"{"version": 1, "items": [{"title": "a"}]}"#).unwrap();
+        assert_eq!(todos, [Item::new(1, "a")]);
+        assert!(dones.is_empty());
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        assert_eq!(
+            parse(r#"{"version": 2, "items": []}"#),
+            Err("unsupported version 2".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"items": []}"#),
+            Err("missing `version`".to_string())
+        );
+    }
+}
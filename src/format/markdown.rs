@@ -0,0 +1,411 @@
+use super::section::{self, Writer};
+use super::todotxt;
+use crate::todo::date::Date;
+use crate::todo::item::*;
+use crate::todo::tree;
+use std::collections::HashSet;
+
+// GitHub task lists:
+//
+//     # Release
+//
+//     Anything that is not a task is left alone.
+//
+//     - [ ] Write the changelog
+//       - [x] Collect merged pull requests
+//         Lines indented under a task are its note.
+//
+// Headings become the project of the tasks below them and nested tasks become
+// subtasks. The other fields follow the title as in todo.txt: `+project`,
+// `@context` and `key:value` pairs, with `created:` and `completed:` dates.
+// Note lines that would read as a task start with a `\`. When saving over an
+// existing file the tasks of every section are written where the section's
+// tasks used to be, everything else is kept as is.
+
+enum Line<'a> {
+    Heading(&'a str),
+    Task {
+        indent: usize,
+        done: bool,
+        title: &'a str,
+    },
+    Note {
+        text: &'a str,
+    },
+    Blank,
+    Prose,
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn parse_heading(line: &str) -> Option<&str> {
+    let hashes = line.len() - line.trim_start_matches('#').len();
+    if (1..=6).contains(&hashes) {
+        line[hashes..]
+            .strip_prefix(' ')
+            .map(|text| text.trim().trim_end_matches('#').trim())
+    } else {
+        None
+    }
+}
+
+fn parse_task(line: &str) -> Option<(usize, bool, &str)> {
+    let indent = indent_of(line);
+    let rest = &line[indent..];
+    let rest = match rest.strip_prefix(['-', '*', '+']) {
+        Some(rest) => rest,
+        None => {
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits == 0 {
+                return None;
+            }
+            rest[digits..].strip_prefix(['.', ')'])?
+        }
+    };
+    let rest = rest.strip_prefix(' ')?;
+    let done = match rest.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    match &rest[3..] {
+        "" => Some((indent, done, "")),
+        title => title.strip_prefix(' ').map(|title| (indent, done, title)),
+    }
+}
+
+/// Whether a blank line is part of the note of the task indented by
+/// `indent`, so the next non-blank line is one of its note lines.
+fn continues_note(rest: &[&str], indent: usize) -> bool {
+    rest.iter()
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(|line| {
+            parse_heading(line).is_none() && parse_task(line).is_none() && indent_of(line) > indent
+        })
+}
+
+fn classify(content: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    // Indentation of the task the following lines may belong to as a note
+    let mut task_indent: Option<usize> = None;
+    let raw: Vec<&str> = content.lines().collect();
+    for (index, line) in raw.iter().enumerate() {
+        if let Some(text) = parse_heading(line) {
+            task_indent = None;
+            lines.push(Line::Heading(text));
+        } else if let Some((indent, done, title)) = parse_task(line) {
+            task_indent = Some(indent);
+            lines.push(Line::Task {
+                indent,
+                done,
+                title,
+            });
+        } else if line.trim().is_empty() {
+            if task_indent.is_some_and(|indent| continues_note(&raw[index + 1..], indent)) {
+                lines.push(Line::Note { text: "" });
+            } else {
+                task_indent = None;
+                lines.push(Line::Blank);
+            }
+        } else if let Some(indent) = task_indent.filter(|indent| indent_of(line) > *indent) {
+            let strip = (indent + 2).min(indent_of(line));
+            let text = &line[strip..];
+            lines.push(Line::Note {
+                text: text.strip_prefix('\\').unwrap_or(text),
+            });
+        } else {
+            task_indent = None;
+            lines.push(Line::Prose);
+        }
+    }
+    lines
+}
+
+/// Applies the pairs todo.txt knows, and the dates it writes in front of
+/// the title there.
+fn parse_pair(item: &mut Item, key: &str, value: &str) -> bool {
+    let date = || Date::parse(value).map(|date| date.days() * 86400);
+    match key {
+        "created" => date().map(|secs| item.created = Some(secs)).is_some(),
+        "completed" => date().map(|secs| item.completed = Some(secs)).is_some(),
+        _ => todotxt::parse_extension(item, key, value),
+    }
+}
+
+pub fn parse(content: &str) -> Result<(Vec<Item>, Vec<Item>), String> {
+    let mut items: Vec<(Item, bool)> = Vec::new();
+    // Index of the parent of each item in `items`
+    let mut parents: Vec<Option<usize>> = Vec::new();
+    let mut section: Option<&str> = None;
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    let mut noted = false;
+    for line in classify(content) {
+        match line {
+            Line::Heading(text) => {
+                section = Some(text);
+                stack.clear();
+            }
+            Line::Task {
+                indent,
+                done,
+                title,
+            } => {
+                while stack.last().is_some_and(|(i, _)| *i >= indent) {
+                    stack.pop();
+                }
+                let mut item = Item::new(0, "");
+                item.projects.extend(section.map(|s| s.to_string()));
+                item.title = todotxt::parse_words(&mut item, title, parse_pair);
+                item.parent = None;
+                parents.push(stack.last().map(|(_, index)| *index));
+                stack.push((indent, items.len()));
+                items.push((item, done));
+                noted = false;
+            }
+            Line::Note { text } => {
+                if let Some((item, _)) = items.last_mut() {
+                    if noted {
+                        item.note.push('\n');
+                    }
+                    item.note.push_str(text);
+                    noted = true;
+                }
+            }
+            Line::Blank | Line::Prose => {}
+        }
+    }
+
+    // Ids written with `id:` are kept, the other items get the next free ones
+    let mut used: HashSet<u64> = HashSet::new();
+    for (item, _) in &mut items {
+        if item.id != 0 && !used.insert(item.id) {
+            item.id = 0;
+        }
+    }
+    let mut next_id = used.iter().max().copied().unwrap_or(0) + 1;
+    for (item, _) in &mut items {
+        if item.id == 0 {
+            item.id = next_id;
+            next_id += 1;
+        }
+    }
+    let ids: Vec<u64> = items.iter().map(|(item, _)| item.id).collect();
+    let mut todos: Vec<Item> = Vec::new();
+    let mut dones: Vec<Item> = Vec::new();
+    for ((mut item, done), parent) in items.into_iter().zip(parents) {
+        item.parent = parent.map(|index| ids[index]);
+        if done {
+            dones.push(item);
+        } else {
+            todos.push(item);
+        }
+    }
+    tree::normalize(&mut todos);
+    tree::normalize(&mut dones);
+    Ok((todos, dones))
+}
+
+struct Layout {
+    /// Ids of the items something depends on, the only ones written.
+    referenced: HashSet<u64>,
+}
+
+impl section::Layout for Layout {
+    fn item(
+        &mut self,
+        out: &mut String,
+        _: &Option<String>,
+        item: &Item,
+        done: bool,
+        depth: usize,
+    ) {
+        let indent = "  ".repeat(depth);
+        let mark = if done { 'x' } else { ' ' };
+        let mut words = vec![format!("{}- [{}]", indent, mark)];
+        if !item.title.is_empty() {
+            words.push(item.title.clone());
+        }
+        // The first project is the section
+        words.extend(item.projects.iter().skip(1).map(|p| format!("+{}", p)));
+        words.extend(item.tags.iter().map(|tag| format!("@{}", tag)));
+        if self.referenced.contains(&item.id) {
+            words.push(format!("id:{}", item.id));
+        }
+        if let Some(priority) = item.priority {
+            words.push(format!("pri:{}", priority));
+        }
+        let date = |secs: i64| Date::from_days(secs.div_euclid(86400));
+        if let Some(created) = item.created {
+            words.push(format!("created:{}", date(created)));
+        }
+        if let (Some(completed), true) = (item.completed, done) {
+            words.push(format!("completed:{}", date(completed)));
+        }
+        todotxt::render_pairs(item, &mut words);
+        out.push_str(&words.join(" "));
+        out.push('\n');
+        if !item.note.is_empty() {
+            for line in item.note.split('\n') {
+                if line.is_empty() {
+                    out.push('\n');
+                } else if parse_task(line).is_some() || line.starts_with('\\') {
+                    // Would be read back as a subtask
+                    out.push_str(&format!("{}  \\{}\n", indent, line));
+                } else {
+                    out.push_str(&format!("{}  {}\n", indent, line));
+                }
+            }
+        }
+    }
+
+    fn heading(&mut self, out: &mut String, section: &Option<String>) {
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        if let Some(name) = section {
+            out.push_str(&format!("## {}\n\n", name));
+        }
+    }
+}
+
+/// Renders the items onto `original`, keeping all of its non-task lines.
+pub fn render(original: Option<&str>, todos: &[Item], dones: &[Item]) -> String {
+    let content = original.unwrap_or("");
+    let raw: Vec<&str> = content.lines().collect();
+    let lines = classify(content);
+    let mut default = None;
+    for line in &lines {
+        match line {
+            Line::Heading(text) => default = Some(text.to_string()),
+            Line::Task { .. } => break,
+            _ => {}
+        }
+    }
+    if !lines.iter().any(|line| matches!(line, Line::Task { .. })) {
+        default = None;
+    }
+    let referenced = todos
+        .iter()
+        .chain(dones.iter())
+        .flat_map(|item| item.depends.iter())
+        .copied()
+        .collect();
+    let mut writer = Writer::new(todos, dones, default, Layout { referenced });
+    let is_task = |line: &Line| matches!(line, Line::Task { .. } | Line::Note { .. });
+    let mut section: Option<String> = None;
+    for (index, line) in lines.iter().enumerate() {
+        match line {
+            Line::Heading(text) => {
+                let before = writer.out.len();
+                writer.write_section(&section);
+                if writer.out.len() > before {
+                    writer.out.push('\n');
+                }
+                writer.out.push_str(raw[index]);
+                writer.out.push('\n');
+                section = Some(text.to_string());
+            }
+            Line::Task { .. } | Line::Note { .. } => writer.write_section(&section),
+            Line::Blank => {
+                // Blank lines between the tasks of a loose list go with the tasks
+                let prev = lines[..index]
+                    .iter()
+                    .rev()
+                    .find(|l| !matches!(l, Line::Blank));
+                let next = lines[index..].iter().find(|l| !matches!(l, Line::Blank));
+                if !(prev.is_some_and(is_task) && next.is_some_and(is_task)) {
+                    writer.out.push('\n');
+                }
+            }
+            Line::Prose => {
+                writer.out.push_str(raw[index]);
+                writer.out.push('\n');
+            }
+        }
+    }
+    writer.write_section(&section);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::todo::recurrence::Recurrence;
+
+    const FILE: &str = "\
+# Release
+
+Anything that is not a task is left alone.
+
+- [ ] Write the changelog
+  - [x] Collect merged pull requests
+    Lines indented under a task are its note.
+- [ ] Tag the release
+";
+
+    #[test]
+    fn round_trip_keeps_the_file() {
+        let (todos, dones) = parse(FILE).unwrap();
+        assert_eq!(render(Some(FILE), &todos, &dones), FILE);
+    }
+
+    #[test]
+    fn new_sections_go_to_the_end() {
+        let (mut todos, dones) = parse(FILE).unwrap();
+        let mut item = Item::new(4, "Book the venue");
+        item.projects.push("Party".to_string());
+        todos.push(item);
+        todos.push(Item::new(5, "Announce it"));
+        let expected = format!("{}\n## Party\n\n- [ ] Book the venue\n", FILE).replace(
+            "- [ ] Tag the release\n",
+            "- [ ] Tag the release\n- [ ] Announce it\n",
+        );
+        assert_eq!(render(Some(FILE), &todos, &dones), expected);
+    }
+
+    #[test]
+    fn notes_keep_blank_lines_and_task_like_lines() {
+        let mut item = Item::new(1, "Write");
+        item.note = "\none\n\ntwo\n- [ ] not a task\n\\slash".to_string();
+        let rendered = render(None, &[item.clone()], &[]);
+        let (todos, dones) = parse(&rendered).unwrap();
+        assert_eq!(todos, [item]);
+        assert_eq!(render(Some(&rendered), &todos, &dones), rendered);
+    }
+
+    #[test]
+    fn fields_follow_the_title() {
+        let mut blocker = Item::new(1, "Order parts");
+        blocker.priority = Some('A');
+        blocker.tags.push("shop".to_string());
+        blocker.projects = vec!["Bike".to_string(), "Money".to_string()];
+        let mut item = Item::new(2, "Fix the bike");
+        item.projects.push("Bike".to_string());
+        item.due = Date::parse("2024-05-10");
+        item.recurrence = Some(Recurrence::parse("weekly mon,fri").unwrap());
+        item.depends.push(1);
+        item.created = Some(Date::parse("2024-05-01").unwrap().days() * 86400);
+        item.history = vec![Date::parse("2024-05-03").unwrap().days() * 86400];
+        item.extensions
+            .push(("board".to_string(), "home".to_string()));
+        let mut done = Item::new(3, "Pump tires");
+        done.projects.push("Bike".to_string());
+        done.completed = Some(Date::parse("2024-05-02").unwrap().days() * 86400);
+        let todos = [blocker, item];
+        let dones = [done];
+        let rendered = render(None, &todos, &dones);
+        assert_eq!(
+            rendered,
+            "\
+## Bike
+
+- [ ] Order parts +Money @shop id:1 pri:A
+- [ ] Fix the bike created:2024-05-01 due:2024-05-10 rec:+mon,fri dep:1 history:2024-05-03 board:home
+- [x] Pump tires completed:2024-05-02
+"
+        );
+        assert_eq!(parse(&rendered).unwrap(), (todos.to_vec(), dones.to_vec()));
+    }
+}
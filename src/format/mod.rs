@@ -0,0 +1,453 @@
+pub mod conflict;
+pub mod csv;
+pub mod document;
+pub mod ical;
+pub mod journal;
+pub mod json;
+pub mod markdown;
+pub mod native;
+pub mod org;
+pub mod section;
+pub mod taskwarrior;
+pub mod todotxt;
+
+use crate::todo::item::Item;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Native,
+    TodoTxt,
+    Markdown,
+    Org,
+    Json,
+    ICalendar,
+    /// `task export` output, `.json` files holding an array are read and
+    /// saved as it too.
+    Taskwarrior,
+    /// Export only, with the default columns unless given to [`export`].
+    Csv,
+}
+
+impl Format {
+    pub const NAMES: &'static str =
+        "`native`, `todotxt`, `markdown`, `org`, `json`, `ics`, `taskwarrior` or `csv`";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(Format::Native),
+            "todotxt" | "todo.txt" => Some(Format::TodoTxt),
+            "markdown" | "md" => Some(Format::Markdown),
+            "org" => Some(Format::Org),
+            "json" => Some(Format::Json),
+            "ics" | "icalendar" => Some(Format::ICalendar),
+            "taskwarrior" | "tw" => Some(Format::Taskwarrior),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the file extension, falling back to the native one.
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("txt") => Format::TodoTxt,
+            Some("md") | Some("markdown") => Format::Markdown,
+            Some("org") => Format::Org,
+            Some("json") => Format::Json,
+            Some("ics") => Format::ICalendar,
+            Some("csv") => Format::Csv,
+            _ => Format::Native,
+        }
+    }
+
+    /// Whether items keep their ids between runs, other formats only write
+    /// the ids something refers to. Taskwarrior numbers tasks by their place
+    /// in the array, they are told apart by their `uuid` instead.
+    pub fn keeps_ids(&self) -> bool {
+        matches!(self, Format::Native | Format::Json | Format::ICalendar)
+    }
+
+    /// The format `content` is actually in, which the file is saved back in.
+    pub fn detect(&self, content: &str) -> Format {
+        match self {
+            Format::Json if content.trim_start().starts_with('[') => Format::Taskwarrior,
+            format => *format,
+        }
+    }
+
+    pub fn parse(&self, content: &str) -> Result<(Vec<Item>, Vec<Item>), String> {
+        let (mut todos, mut dones) = self.parse_items(content)?;
+        for item in todos.iter_mut().chain(dones.iter_mut()) {
+            clean_title(&mut item.title);
+        }
+        Ok((todos, dones))
+    }
+
+    fn parse_items(&self, content: &str) -> Result<(Vec<Item>, Vec<Item>), String> {
+        match self.detect(content) {
+            Format::Native => native::parse(content),
+            Format::TodoTxt => todotxt::parse(content),
+            Format::Markdown => markdown::parse(content),
+            Format::Org => org::parse(content),
+            Format::Json => json::parse(content),
+            Format::ICalendar => ical::parse(content),
+            Format::Taskwarrior => taskwarrior::parse(content),
+            Format::Csv => Err("CSV can only be exported".to_string()),
+        }
+    }
+
+    /// Renders the items, formats that can carry other content keep the one
+    /// of `original`, the file being overwritten, and what they wrote for
+    /// items that did not change.
+    pub fn render(&self, original: Option<&str>, todos: &[Item], dones: &[Item]) -> String {
+        match self.detect(original.unwrap_or("")) {
+            Format::Native => native::render(todos, dones),
+            Format::TodoTxt => todotxt::render(original, todos, dones),
+            Format::Markdown => markdown::render(original, todos, dones),
+            Format::Org => org::render(original, todos, dones),
+            Format::Json => json::render(todos, dones),
+            Format::ICalendar => ical::render(original, todos, dones),
+            Format::Taskwarrior => taskwarrior::render(todos, dones),
+            Format::Csv => {
+                let columns: Vec<String> =
+                    csv::DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect();
+                csv::render(todos, dones, &columns)
+            }
+        }
+    }
+}
+
+/// Titles stay on one line, control characters like newlines that other
+/// programs put in them become spaces.
+fn clean_title(title: &mut String) {
+    if title.contains(char::is_control) {
+        *title = title.replace(char::is_control, " ");
+    }
+}
+
+/// What a file held when it was last read or written, to tell whether
+/// someone else wrote it since.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stamp {
+    modified: Option<SystemTime>,
+    /// Hash of the content, `None` when there is no file.
+    hash: Option<u64>,
+}
+
+fn hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Stamp {
+    pub fn read(path: &str) -> Self {
+        Stamp {
+            modified: fs::metadata(path).and_then(|meta| meta.modified()).ok(),
+            hash: fs::read_to_string(path).ok().map(|content| hash(&content)),
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.hash.is_some()
+    }
+
+    /// Whether the content differs, it is only read again when the
+    /// modification time moved.
+    pub fn changed(&self, path: &str) -> bool {
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        if modified.is_some() && modified == self.modified {
+            return false;
+        }
+        Stamp::read(path).hash != self.hash
+    }
+}
+
+/// Takes an advisory lock on the file, released when it is closed.
+fn lock(file: &File, exclusive: bool) -> io::Result<()> {
+    let operation = if exclusive {
+        libc::LOCK_EX
+    } else {
+        libc::LOCK_SH
+    };
+    // SAFETY: `flock` only uses the descriptor, which `file` keeps open.
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Takes an exclusive lock unless someone else holds one, returns whether it did.
+fn try_lock(file: &File) -> io::Result<bool> {
+    // SAFETY: as in `lock`.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.kind() {
+        io::ErrorKind::WouldBlock => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Items of the file with its stamp and the format it turned out to be in.
+type Loaded = (Vec<Item>, Vec<Item>, Stamp, Format);
+
+pub fn load_stamped(path: &str, format: Format) -> io::Result<Loaded> {
+    let mut file = File::open(path)?;
+    lock(&file, false)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    let stamp = Stamp {
+        modified: file.metadata()?.modified().ok(),
+        hash: Some(hash(&content)),
+    };
+    let (todos, dones) = format
+        .parse(&content)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}", path, err)))?;
+    Ok((todos, dones, stamp, format.detect(&content)))
+}
+
+pub fn load(path: &str, format: Format) -> io::Result<(Vec<Item>, Vec<Item>)> {
+    load_stamped(path, format).map(|(todos, dones, _, _)| (todos, dones))
+}
+
+pub fn archive_path(path: &str) -> String {
+    format!("{}.archive", path)
+}
+
+/// Adds items to the archive next to `path`. Line based formats append to
+/// it, a JSON document only holds one array so it is read and rewritten.
+pub fn append_archive(path: &str, format: Format, items: &[Item]) -> io::Result<()> {
+    if let Format::Json | Format::Taskwarrior = format {
+        let mut archived = load_archive(path, format)?;
+        archived.extend_from_slice(items);
+        return save(&archive_path(path), format, &[], &archived).map(|_| ());
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(archive_path(path))?;
+    file.write_all(format.render(None, &[], items).as_bytes())
+}
+
+pub fn load_archive(path: &str, format: Format) -> io::Result<Vec<Item>> {
+    match load(&archive_path(path), format) {
+        Ok((todos, dones)) => Ok(todos.into_iter().chain(dones).collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Opens `path` and locks it exclusively, again when another save replaced
+/// the file while we were waiting for the lock.
+fn open_locked(path: &str) -> io::Result<(File, bool)> {
+    loop {
+        let existed = Path::new(path).exists();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        lock(&file, true)?;
+        let locked = file.metadata()?;
+        if fs::metadata(path)
+            .is_ok_and(|meta| meta.ino() == locked.ino() && meta.dev() == locked.dev())
+        {
+            return Ok((file, existed));
+        }
+    }
+}
+
+/// Creates a temporary file next to `path` that no one else has open, with
+/// the mode of the file it replaces so a private file is never readable by
+/// others.
+fn create_temp(path: &str, mode: u32) -> io::Result<(String, File)> {
+    let mut attempt: u32 = 0;
+    loop {
+        let temp_path = format!("{}.{:016x}.tmp", path, RandomState::new().hash_one(attempt));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 16 => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Replaces `path` with `content` through a temporary file renamed over it,
+/// so a crash leaves either the old or the new content.
+fn write_atomic(path: &str, content: &str, permissions: fs::Permissions) -> io::Result<Stamp> {
+    let (temp_path, mut temp) = create_temp(path, permissions.mode())?;
+    let written = (|| {
+        temp.write_all(content.as_bytes())?;
+        // The umask may have taken bits away
+        temp.set_permissions(permissions)?;
+        temp.sync_all()?;
+        let modified = temp.metadata()?.modified().ok();
+        fs::rename(&temp_path, path)?;
+        Ok(modified)
+    })();
+    let modified = match written {
+        Ok(modified) => modified,
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err);
+        }
+    };
+    let dir = Path::new(path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    File::open(dir)?.sync_all()?;
+    Ok(Stamp {
+        modified,
+        hash: Some(hash(content)),
+    })
+}
+
+/// Writes the items under an exclusive lock, unless the file no longer
+/// matches `expected`. Returns the stamp of the new content, `None` when
+/// nothing was written.
+pub fn save_unless_changed(
+    path: &str,
+    format: Format,
+    todos: &[Item],
+    dones: &[Item],
+    expected: Option<&Stamp>,
+) -> io::Result<Option<Stamp>> {
+    let (mut file, existed) = open_locked(path)?;
+    let mut original = String::new();
+    file.read_to_string(&mut original)?;
+    let original = (existed || !original.is_empty()).then_some(original);
+    if expected.is_some_and(|stamp| stamp.hash != original.as_deref().map(hash)) {
+        return Ok(None);
+    }
+    let content = format.render(original.as_deref(), todos, dones);
+    // The lock is held until the old file is closed, after the rename
+    write_atomic(path, &content, file.metadata()?.permissions()).map(Some)
+}
+
+pub fn save(path: &str, format: Format, todos: &[Item], dones: &[Item]) -> io::Result<Stamp> {
+    save_unless_changed(path, format, todos, dones, None)
+        .map(|stamp| stamp.expect("saving without an expected stamp always writes"))
+}
+
+/// Writes the items to another file, the format is guessed from its extension
+/// unless given. `columns` only matters for CSV.
+pub fn export(
+    path: &str,
+    format: Option<Format>,
+    columns: Option<&[String]>,
+    todos: &[Item],
+    dones: &[Item],
+) -> io::Result<()> {
+    let content = render_export(
+        format.unwrap_or_else(|| Format::from_path(path)),
+        columns,
+        todos,
+        dones,
+    );
+    fs::write(path, content)
+}
+
+pub fn render_export(
+    format: Format,
+    columns: Option<&[String]>,
+    todos: &[Item],
+    dones: &[Item],
+) -> String {
+    match (format, columns) {
+        (Format::Csv, Some(columns)) => csv::render(todos, dones, columns),
+        (format, _) => format.render(None, todos, dones),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("todors-{}-{}", process::id(), name));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn imported_titles_stay_on_one_line() {
+        let json = r#"{"version": 1, "items": [{"title": "a\nb\tc"}]}"#;
+        let (todos, dones) = Format::Json.parse(json).unwrap();
+        assert_eq!(todos[0].title, "a b c");
+        let native = Format::Native.render(None, &todos, &dones);
+        assert_eq!(Format::Native.parse(&native).unwrap(), (todos, dones));
+    }
+
+    #[test]
+    fn archiving_twice_keeps_the_archive_readable() {
+        for (name, format) in [
+            ("archive.json", Format::Json),
+            ("archive-tw.json", Format::Taskwarrior),
+            ("archive.ics", Format::ICalendar),
+            ("archive.txt", Format::TodoTxt),
+            ("archive", Format::Native),
+        ] {
+            let path = temp_path(name);
+            let mut first = Item::new(1, "first");
+            first.completed = Some(86400);
+            let mut second = Item::new(2, "second");
+            second.completed = Some(2 * 86400);
+            append_archive(&path, format, &[first]).unwrap();
+            append_archive(&path, format, &[second]).unwrap();
+            let archived = load_archive(&path, format);
+            fs::remove_file(archive_path(&path)).unwrap();
+            let titles: Vec<String> = archived
+                .unwrap_or_else(|err| panic!("{:?}: {}", format, err))
+                .into_iter()
+                .map(|item| item.title)
+                .collect();
+            assert_eq!(titles, ["first", "second"], "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn saving_keeps_a_private_file_private() {
+        let path = temp_path("private");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        // Left behind by a writer that did not get to rename it
+        fs::write(format!("{}.tmp", path), "stale").unwrap();
+        let (first, _) = create_temp(&path, 0o600).unwrap();
+        let (second, _) = create_temp(&path, 0o600).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::metadata(&first).unwrap().mode() & 0o777, 0o600);
+
+        save(&path, Format::Native, &[Item::new(1, "a")], &[]).unwrap();
+        let mode = fs::metadata(&path).unwrap().mode() & 0o777;
+        let name = Path::new(&path).file_name().unwrap().to_str().unwrap();
+        let temps = fs::read_dir(env::temp_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|other| other.starts_with(name) && other.ends_with(".tmp"))
+            .count();
+        for other in [path.clone(), format!("{}.tmp", path), first, second] {
+            fs::remove_file(other).unwrap();
+        }
+        assert_eq!(mode, 0o600);
+        // The stale one and the two made above
+        assert_eq!(temps, 3);
+    }
+}
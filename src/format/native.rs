@@ -0,0 +1,219 @@
+use crate::todo::date::*;
+use crate::todo::item::*;
+use crate::todo::recurrence::Recurrence;
+
+// Every item starts with a `TODO: ` or `DONE: ` line holding its title and is
+// followed by indented `key: value` attribute lines:
+//
+//     TODO: Write the report
+//       id: 3
+//       note: First line of the note
+//       note: Second line of the note
+
+const TODO_PREFIX: &str = "TODO: ";
+const DONE_PREFIX: &str = "DONE: ";
+const ATTR_INDENT: &str = "  ";
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+fn parse_attr(item: &mut Item, key: &str, value: &str) -> Result<(), String> {
+    match key {
+        "id" => {
+            item.id = value
+                .parse()
+                .map_err(|_| format!("invalid id `{}`", value))?
+        }
+        "parent" => {
+            item.parent = Some(
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid parent `{}`", value))?,
+            )
+        }
+        "folded" => item.folded = value == "true",
+        "due" => {
+            item.due = Some(Date::parse(value).ok_or_else(|| format!("invalid date `{}`", value))?)
+        }
+        "depends" => {
+            for id in value.split(',') {
+                let id = id.trim();
+                item.depends.push(
+                    id.parse()
+                        .map_err(|_| format!("invalid dependency `{}`", id))?,
+                );
+            }
+        }
+        "completed" => {
+            item.completed = Some(
+                parse_timestamp(value).ok_or_else(|| format!("invalid timestamp `{}`", value))?,
+            )
+        }
+        "history" => {
+            for stamp in value.split(',') {
+                item.history.push(
+                    parse_timestamp(stamp.trim())
+                        .ok_or_else(|| format!("invalid timestamp `{}`", stamp))?,
+                );
+            }
+        }
+        "created" => {
+            item.created = Some(
+                parse_timestamp(value).ok_or_else(|| format!("invalid timestamp `{}`", value))?,
+            )
+        }
+        "priority" => match value.chars().next() {
+            Some(priority @ 'A'..='Z') if value.len() == 1 => item.priority = Some(priority),
+            _ => return Err(format!("invalid priority `{}`", value)),
+        },
+        "projects" => item.projects = split_list(value),
+        "tags" => item.tags = split_list(value),
+        "ext" => {
+            let (key, value) = value
+                .split_once(':')
+                .ok_or_else(|| format!("expected `key:value` extension, got `{}`", value))?;
+            item.extensions.push((key.to_string(), value.to_string()));
+        }
+        "recur" => item.recurrence = Some(Recurrence::parse(value)?),
+        _ => return Err(format!("unknown attribute `{}`", key)),
+    }
+    Ok(())
+}
+
+/// Parses the native file format, errors are prefixed with the line number.
+pub fn parse(content: &str) -> Result<(Vec<Item>, Vec<Item>), String> {
+    let mut todos: Vec<Item> = Vec::new();
+    let mut dones: Vec<Item> = Vec::new();
+    let mut last_done: Option<bool> = None;
+    // Whether the item has a note line, which may be empty
+    let mut noted = false;
+    for (index, line) in content.lines().enumerate() {
+        let row = index + 1;
+        if let Some(title) = line.strip_prefix(TODO_PREFIX) {
+            todos.push(Item::new(0, title));
+            last_done = Some(false);
+            noted = false;
+        } else if let Some(title) = line.strip_prefix(DONE_PREFIX) {
+            dones.push(Item::new(0, title));
+            last_done = Some(true);
+            noted = false;
+        } else if let Some(attr) = line.strip_prefix(ATTR_INDENT) {
+            let item = match last_done {
+                Some(false) => todos.last_mut(),
+                Some(true) => dones.last_mut(),
+                None => None,
+            }
+            .ok_or_else(|| format!("{}: attribute outside of any item", row))?;
+            let (key, value) = attr
+                .split_once(':')
+                .ok_or_else(|| format!("{}: expected `key: value`", row))?;
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            if key == "note" {
+                if noted {
+                    item.note.push('\n');
+                }
+                item.note.push_str(value);
+                noted = true;
+            } else {
+                parse_attr(item, key, value).map_err(|err| format!("{}: {}", row, err))?;
+            }
+        } else if !line.trim().is_empty() {
+            return Err(format!("{}: expected `TODO: ` or `DONE: ` item", row));
+        }
+    }
+
+    // Items written by hand may come without an id
+    assign_missing_ids(&mut todos, &mut dones);
+    Ok((todos, dones))
+}
+
+fn render_item(out: &mut String, prefix: &str, item: &Item) {
+    out.push_str(&format!("{}{}\n", prefix, item.title));
+    out.push_str(&format!("{}id: {}\n", ATTR_INDENT, item.id));
+    if let Some(parent) = item.parent {
+        out.push_str(&format!("{}parent: {}\n", ATTR_INDENT, parent));
+    }
+    if item.folded {
+        out.push_str(&format!("{}folded: true\n", ATTR_INDENT));
+    }
+    if let Some(created) = item.created {
+        out.push_str(&format!(
+            "{}created: {}\n",
+            ATTR_INDENT,
+            format_timestamp(created)
+        ));
+    }
+    if let Some(priority) = item.priority {
+        out.push_str(&format!("{}priority: {}\n", ATTR_INDENT, priority));
+    }
+    if !item.projects.is_empty() {
+        out.push_str(&format!(
+            "{}projects: {}\n",
+            ATTR_INDENT,
+            item.projects.join(",")
+        ));
+    }
+    if !item.tags.is_empty() {
+        out.push_str(&format!("{}tags: {}\n", ATTR_INDENT, item.tags.join(",")));
+    }
+    if let Some(due) = item.due {
+        out.push_str(&format!("{}due: {}\n", ATTR_INDENT, due));
+    }
+    if let Some(recurrence) = &item.recurrence {
+        out.push_str(&format!("{}recur: {}\n", ATTR_INDENT, recurrence));
+    }
+    if !item.depends.is_empty() {
+        let ids: Vec<String> = item.depends.iter().map(|id| id.to_string()).collect();
+        out.push_str(&format!("{}depends: {}\n", ATTR_INDENT, ids.join(",")));
+    }
+    if let Some(completed) = item.completed {
+        out.push_str(&format!(
+            "{}completed: {}\n",
+            ATTR_INDENT,
+            format_timestamp(completed)
+        ));
+    }
+    if !item.history.is_empty() {
+        let stamps: Vec<String> = item.history.iter().map(|s| format_timestamp(*s)).collect();
+        out.push_str(&format!("{}history: {}\n", ATTR_INDENT, stamps.join(",")));
+    }
+    for (key, value) in &item.extensions {
+        out.push_str(&format!("{}ext: {}:{}\n", ATTR_INDENT, key, value));
+    }
+    if !item.note.is_empty() {
+        for line in item.note.split('\n') {
+            out.push_str(&format!("{}note: {}\n", ATTR_INDENT, line));
+        }
+    }
+}
+
+pub fn render(todos: &[Item], dones: &[Item]) -> String {
+    let mut out = String::new();
+    for item in todos {
+        render_item(&mut out, TODO_PREFIX, item);
+    }
+    for item in dones {
+        render_item(&mut out, DONE_PREFIX, item);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_keep_their_empty_lines() {
+        for note in ["\nfoo", "foo\n\nbar", "\n", "  indented"] {
+            let mut item = Item::new(1, "Write");
+            item.note = note.to_string();
+            let (todos, _) = parse(&render(&[item.clone()], &[])).unwrap();
+            assert_eq!(todos, [item], "{:?}", note);
+        }
+    }
+}
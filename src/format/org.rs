@@ -0,0 +1,884 @@
+use super::section::{self, Writer};
+use crate::todo::clock::{Clock, SystemClock};
+use crate::todo::date::*;
+use crate::todo::item::*;
+use crate::todo::recurrence::{Frequency, Recurrence};
+use crate::todo::tree;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+// Emacs Org mode headlines:
+//
+//     #+TODO: TODO NEXT | DONE CANCELLED
+//     * Release
+//     Anything that is not a task is left alone.
+//     ** TODO [#A] Write the changelog :docs:
+//     DEADLINE: <2024-05-10 Fri +1w>
+//     :PROPERTIES:
+//     :ID: 3
+//     :CREATED: [2024-05-01 Wed 09:00]
+//     :END:
+//     Body text is the note.
+//     *** DONE Collect merged pull requests
+//     CLOSED: [2024-05-09 Thu 17:30]
+//
+// Headlines with a keyword are items, deeper ones their subtasks. Headlines
+// without one are sections like in Markdown: the project of the items below
+// them, kept with everything else that is not an item when saving. Keywords
+// other than TODO and DONE come from `#+TODO:` lines and are kept, as are
+// `SCHEDULED` and unknown properties. Tasks that did not change keep their
+// original lines. Dependencies are the `BLOCKER` property of org-depend,
+// listing the `ID`s of the other items. Note lines starting with `*` or `#+`
+// get a `,` in front, the way Org escapes them in source blocks, so they
+// are not read back as headlines or settings.
+
+const KEYWORD: &str = "keyword";
+const SCHEDULED: &str = "scheduled";
+
+fn keywords(content: &str) -> (Vec<String>, Vec<String>) {
+    let mut todo = vec!["TODO".to_string()];
+    let mut done = vec!["DONE".to_string()];
+    for line in content.lines() {
+        let words = match line.trim_start().split_once(':') {
+            Some((setting, words))
+                if ["#+TODO", "#+SEQ_TODO", "#+TYP_TODO"]
+                    .contains(&setting.to_ascii_uppercase().as_str()) =>
+            {
+                words
+            }
+            _ => continue,
+        };
+        // `TODO(t)` has a shortcut key, without a bar only the last keyword is done
+        let words: Vec<&str> = words
+            .split_whitespace()
+            .map(|word| word.split('(').next().unwrap_or(word))
+            .collect();
+        let bar = words
+            .iter()
+            .position(|word| *word == "|")
+            .unwrap_or(words.len().saturating_sub(1));
+        for (index, word) in words.iter().enumerate() {
+            let list = if index < bar { &mut todo } else { &mut done };
+            if *word != "|" && !word.is_empty() && !list.iter().any(|known| known == word) {
+                list.push(word.to_string());
+            }
+        }
+    }
+    (todo, done)
+}
+
+struct Headline<'a> {
+    level: usize,
+    /// Keyword and whether it is a done one, `None` for plain headlines.
+    keyword: Option<(&'a str, bool)>,
+    priority: Option<char>,
+    title: &'a str,
+    tags: Vec<&'a str>,
+}
+
+fn parse_headline<'a>(
+    line: &'a str,
+    keywords: &(Vec<String>, Vec<String>),
+) -> Option<Headline<'a>> {
+    let level = line.len() - line.trim_start_matches('*').len();
+    if level == 0 {
+        return None;
+    }
+    let mut rest = match &line[level..] {
+        "" => "",
+        rest => rest.strip_prefix(' ')?.trim(),
+    };
+    let (word, after) = rest.split_once(' ').unwrap_or((rest, ""));
+    let keyword = if keywords.0.iter().any(|known| known == word) {
+        Some((word, false))
+    } else if keywords.1.iter().any(|known| known == word) {
+        Some((word, true))
+    } else {
+        None
+    };
+    if keyword.is_some() {
+        rest = after.trim_start();
+    }
+    let mut priority = None;
+    if let [b'[', b'#', p @ b'A'..=b'Z', b']', ..] = rest.as_bytes() {
+        priority = Some(*p as char);
+        rest = rest[4..].trim_start();
+    }
+    let mut tags = Vec::new();
+    let (title, last) = rest.rsplit_once(' ').unwrap_or(("", rest));
+    let is_tag = |tag: &str| {
+        !tag.is_empty()
+            && tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || "_@#%".contains(c))
+    };
+    if let Some(names) = last
+        .strip_prefix(':')
+        .and_then(|last| last.strip_suffix(':'))
+        .filter(|names| names.split(':').all(is_tag))
+    {
+        tags = names.split(':').collect();
+        rest = title.trim_end();
+    }
+    Some(Headline {
+        level,
+        keyword,
+        priority,
+        title: rest,
+        tags,
+    })
+}
+
+enum Line<'a> {
+    Heading {
+        level: usize,
+        text: &'a str,
+    },
+    Task {
+        headline: Headline<'a>,
+        /// Text of the section headline the task is under.
+        section: Option<&'a str>,
+    },
+    /// Planning, drawer and body lines of the task above.
+    Owned,
+    /// Blank lines ending the lines of a task.
+    Blank,
+    Prose,
+}
+
+fn classify<'a>(content: &'a str, keywords: &(Vec<String>, Vec<String>)) -> Vec<Line<'a>> {
+    let mut lines = Vec::new();
+    // Level of the innermost task the following lines belong to
+    let mut task_level: Option<usize> = None;
+    let mut section: Option<(&str, usize)> = None;
+    let raw: Vec<&str> = content.lines().collect();
+    for line in &raw {
+        match parse_headline(line, keywords) {
+            Some(headline) if headline.keyword.is_some() => {
+                task_level = Some(headline.level);
+                // A task as high as the section headline is not in it
+                if section.is_some_and(|(_, level)| headline.level <= level) {
+                    section = None;
+                }
+                lines.push(Line::Task {
+                    headline,
+                    section: section.map(|(text, _)| text),
+                });
+            }
+            Some(headline) if task_level.is_some_and(|level| headline.level > level) => {
+                lines.push(Line::Owned)
+            }
+            Some(headline) => {
+                task_level = None;
+                let text = line[headline.level..].trim();
+                section = Some((text, headline.level));
+                lines.push(Line::Heading {
+                    level: headline.level,
+                    text,
+                });
+            }
+            None if task_level.is_some() => lines.push(Line::Owned),
+            None => lines.push(Line::Prose),
+        }
+    }
+    // Blank lines at the end of a task separate it from what follows
+    for index in (0..lines.len()).rev() {
+        if matches!(lines[index], Line::Owned)
+            && content
+                .lines()
+                .nth(index)
+                .is_some_and(|line| line.trim().is_empty())
+            && lines
+                .get(index + 1)
+                .is_none_or(|next| !matches!(next, Line::Owned))
+        {
+            lines[index] = Line::Blank;
+        }
+    }
+    lines
+}
+
+/// Parses `<2024-05-10 Fri 10:00 +1w>` into the date, time of day in seconds
+/// and repeater.
+fn parse_org_timestamp(text: &str) -> Option<(Date, i64, Option<&str>)> {
+    let inner = text.strip_prefix(['<', '['])?.strip_suffix(['>', ']'])?;
+    let mut words = inner.split_whitespace();
+    let date = Date::parse(words.next()?)?;
+    let mut time = 0;
+    let mut repeater = None;
+    for word in words {
+        if let Some((hours, minutes)) = word.split_once(':') {
+            let hours: i64 = hours.parse().ok()?;
+            let minutes: i64 = minutes.get(..2)?.parse().ok()?;
+            time = hours * 3600 + minutes * 60;
+        } else if word.starts_with(['+', '.']) {
+            repeater = Some(word);
+        }
+    }
+    Some((date, time, repeater))
+}
+
+fn weekday_name(date: Date) -> String {
+    let name = WEEKDAYS[date.weekday()];
+    name[..1].to_ascii_uppercase() + &name[1..]
+}
+
+fn format_org_date(date: Date, repeater: Option<&str>) -> String {
+    match repeater {
+        Some(repeater) => format!("<{} {} {}>", date, weekday_name(date), repeater),
+        None => format!("<{} {}>", date, weekday_name(date)),
+    }
+}
+
+fn format_org_time(secs: i64, utc_offset: i64) -> String {
+    let local = secs + utc_offset;
+    let date = Date::from_days(local.div_euclid(86400));
+    let time = local.rem_euclid(86400);
+    format!(
+        "[{} {} {:02}:{:02}]",
+        date,
+        weekday_name(date),
+        time / 3600,
+        time % 3600 / 60
+    )
+}
+
+fn parse_repeater(text: &str) -> Option<Recurrence> {
+    let (after_completion, value) = match text.strip_prefix(".+") {
+        Some(value) => (true, value),
+        None => (false, text.trim_start_matches('+')),
+    };
+    let count: u32 = value.get(..value.len().checked_sub(1)?)?.parse().ok()?;
+    let frequency = match (count, value.chars().last()?) {
+        (1, 'd') => Frequency::Daily,
+        (_, 'd') if count > 0 => Frequency::EveryDays(count),
+        (1, 'w') => Frequency::Weekly(Vec::new()),
+        (_, 'w') if count > 0 => Frequency::EveryDays(count * 7),
+        (1, 'm') => Frequency::Monthly,
+        _ => return None,
+    };
+    Some(Recurrence {
+        frequency,
+        after_completion,
+    })
+}
+
+/// Repeater of the `DEADLINE`, rules it can't hold go to the `RECUR` property.
+fn render_repeater(recurrence: &Recurrence) -> Option<String> {
+    let value = match &recurrence.frequency {
+        Frequency::Daily => "1d".to_string(),
+        Frequency::EveryDays(days) => format!("{}d", days),
+        Frequency::Weekly(weekdays) if weekdays.is_empty() => "1w".to_string(),
+        Frequency::Weekly(_) => return None,
+        Frequency::Monthly => "1m".to_string(),
+    };
+    Some(if recurrence.after_completion {
+        format!(".+{}", value)
+    } else {
+        format!("+{}", value)
+    })
+}
+
+/// Takes the timestamp following a planning keyword like `DEADLINE:`.
+fn planning<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    let start = line.find(keyword)? + keyword.len();
+    let rest = line[start..].trim_start();
+    let close = match rest.chars().next()? {
+        '<' => '>',
+        '[' => ']',
+        _ => return None,
+    };
+    rest.find(close).map(|end| &rest[..=end])
+}
+
+fn is_planning(line: &str) -> bool {
+    let line = line.trim_start();
+    ["SCHEDULED:", "DEADLINE:", "CLOSED:"]
+        .iter()
+        .any(|keyword| line.starts_with(keyword))
+}
+
+/// Where a `,` goes in front of `line`, the start of a headline or setting
+/// behind any commas of earlier escaping.
+fn escape_position(line: &str) -> Option<usize> {
+    let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+    let rest = line[indent..].trim_start_matches(',');
+    (rest.starts_with('*') || rest.starts_with("#+")).then_some(indent)
+}
+
+fn escape_note_line(line: &str) -> String {
+    match escape_position(line) {
+        Some(indent) => format!("{},{}", &line[..indent], &line[indent..]),
+        None => line.to_string(),
+    }
+}
+
+fn unescape_note_line(line: &str) -> String {
+    match escape_position(line) {
+        Some(indent) if line[indent..].starts_with(',') => {
+            format!("{}{}", &line[..indent], &line[indent + 1..])
+        }
+        _ => line.to_string(),
+    }
+}
+
+struct Task {
+    item: Item,
+    done: bool,
+    level: usize,
+    org_id: Option<String>,
+    blockers: Vec<String>,
+    /// Indices of the headline and the lines under it.
+    lines: Range<usize>,
+}
+
+/// Fills the item from the lines under its headline.
+fn parse_body(task: &mut Task, lines: &[&str], utc_offset: i64) -> Result<(), String> {
+    let item = &mut task.item;
+    let mut index = 0;
+    if lines.first().is_some_and(|line| is_planning(line)) {
+        let line = lines[0];
+        if let Some(deadline) = planning(line, "DEADLINE:") {
+            let (due, _, repeater) = parse_org_timestamp(deadline)
+                .ok_or_else(|| format!("invalid DEADLINE `{}`", deadline))?;
+            item.due = Some(due);
+            item.recurrence = repeater.and_then(parse_repeater);
+        }
+        if let Some(scheduled) = planning(line, "SCHEDULED:") {
+            item.extensions
+                .push((SCHEDULED.to_string(), scheduled.to_string()));
+        }
+        if let Some(closed) = planning(line, "CLOSED:") {
+            let (date, time, _) = parse_org_timestamp(closed)
+                .ok_or_else(|| format!("invalid CLOSED `{}`", closed))?;
+            item.completed = Some(date.days() * 86400 + time - utc_offset);
+        }
+        index = 1;
+    }
+    if lines
+        .get(index)
+        .is_some_and(|line| line.trim() == ":PROPERTIES:")
+    {
+        index += 1;
+        while let Some(line) = lines.get(index) {
+            index += 1;
+            let line = line.trim();
+            if line == ":END:" {
+                break;
+            }
+            let Some((key, value)) = line.strip_prefix(':').and_then(|line| line.split_once(':'))
+            else {
+                continue;
+            };
+            let value = value.trim();
+            match key {
+                "ID" => match value.parse() {
+                    Ok(id) => item.id = id,
+                    Err(_) => {
+                        item.extensions.push((key.to_string(), value.to_string()));
+                        task.org_id = Some(value.to_string());
+                    }
+                },
+                "CREATED" => {
+                    let (date, time, _) = parse_org_timestamp(value)
+                        .ok_or_else(|| format!("invalid CREATED `{}`", value))?;
+                    item.created = Some(date.days() * 86400 + time - utc_offset);
+                }
+                "BLOCKER" => {
+                    task.blockers = value.split_whitespace().map(|id| id.to_string()).collect()
+                }
+                "RECUR" => item.recurrence = Some(Recurrence::parse(value)?),
+                _ => item.extensions.push((key.to_string(), value.to_string())),
+            }
+        }
+    }
+    if task.org_id.is_none() && item.id != 0 {
+        task.org_id = Some(item.id.to_string());
+    }
+    let body = &lines[index..];
+    let end = body.len()
+        - body
+            .iter()
+            .rev()
+            .take_while(|line| line.trim().is_empty())
+            .count();
+    item.note = body[..end]
+        .iter()
+        .map(|line| unescape_note_line(line))
+        .collect::<Vec<String>>()
+        .join("\n");
+    Ok(())
+}
+
+/// Parses the tasks in file order, with their ids, parents and dependencies.
+fn parse_tasks(
+    content: &str,
+    keywords: &(Vec<String>, Vec<String>),
+    utc_offset: i64,
+) -> Result<Vec<Task>, String> {
+    let raw: Vec<&str> = content.lines().collect();
+    let lines = classify(content, keywords);
+    let mut tasks: Vec<Task> = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        match &lines[index] {
+            Line::Task { headline, section } => {
+                let (keyword, done) = headline.keyword.unwrap_or(("TODO", false));
+                let mut item = Item::new(0, headline.title);
+                item.priority = headline.priority;
+                item.tags = headline.tags.iter().map(|tag| tag.to_string()).collect();
+                item.projects.extend(section.map(|text| text.to_string()));
+                if keyword != "TODO" && keyword != "DONE" {
+                    item.extensions
+                        .push((KEYWORD.to_string(), keyword.to_string()));
+                }
+                let owned = lines[index + 1..]
+                    .iter()
+                    .take_while(|line| matches!(line, Line::Owned))
+                    .count();
+                let mut task = Task {
+                    item,
+                    done,
+                    level: headline.level,
+                    org_id: None,
+                    blockers: Vec::new(),
+                    lines: index..index + 1 + owned,
+                };
+                parse_body(&mut task, &raw[index + 1..index + 1 + owned], utc_offset)
+                    .map_err(|err| format!("{}: {}", index + 1, err))?;
+                tasks.push(task);
+                index += owned;
+            }
+            Line::Heading { .. } | Line::Owned | Line::Blank | Line::Prose => {}
+        }
+        index += 1;
+    }
+
+    // Headlines with a numeric `ID` keep it, the others get the next free one
+    let mut used: HashSet<u64> = HashSet::new();
+    for task in &mut tasks {
+        if task.item.id != 0 && !used.insert(task.item.id) {
+            task.item.id = 0;
+        }
+    }
+    let mut next_id = used.iter().max().copied().unwrap_or(0) + 1;
+    let mut ids: HashMap<String, u64> = HashMap::new();
+    for task in &mut tasks {
+        if task.item.id == 0 {
+            task.item.id = next_id;
+            next_id += 1;
+        }
+        ids.insert(
+            task.org_id
+                .clone()
+                .unwrap_or_else(|| task.item.id.to_string()),
+            task.item.id,
+        );
+    }
+
+    // The parent is the closest task above with a lower level
+    let mut stack: Vec<(usize, u64)> = Vec::new();
+    let mut last_section = None;
+    for task in &mut tasks {
+        let section = task.item.projects.first().cloned();
+        if section != last_section {
+            stack.clear();
+            last_section = section;
+        }
+        while stack.last().is_some_and(|(level, _)| *level >= task.level) {
+            stack.pop();
+        }
+        task.item.parent = stack.last().map(|(_, id)| *id);
+        stack.push((task.level, task.item.id));
+        task.item.depends = task
+            .blockers
+            .iter()
+            .filter_map(|id| ids.get(id))
+            .copied()
+            .collect();
+    }
+    Ok(tasks)
+}
+
+pub fn parse(content: &str) -> Result<(Vec<Item>, Vec<Item>), String> {
+    let tasks = parse_tasks(content, &keywords(content), SystemClock.utc_offset())?;
+    let mut todos = Vec::new();
+    let mut dones = Vec::new();
+    for task in tasks {
+        if task.done {
+            dones.push(task.item);
+        } else {
+            todos.push(task.item);
+        }
+    }
+    tree::normalize(&mut todos);
+    tree::normalize(&mut dones);
+    Ok((todos, dones))
+}
+
+struct Layout<'a> {
+    keywords: (Vec<String>, Vec<String>),
+    /// Levels of the section headlines in the original file.
+    levels: HashMap<Option<String>, usize>,
+    /// Org `ID` of each item referenced by a dependency.
+    org_ids: HashMap<u64, String>,
+    utc_offset: i64,
+    /// Lines of the items of the original file by how they would be
+    /// rendered, to keep those of items that did not change.
+    unchanged: HashMap<String, Vec<&'a [&'a str]>>,
+}
+
+impl Layout<'_> {
+    fn keyword(&self, item: &Item, done: bool) -> String {
+        let list = if done {
+            &self.keywords.1
+        } else {
+            &self.keywords.0
+        };
+        item.extensions
+            .iter()
+            .find(|(key, _)| key == KEYWORD)
+            .map(|(_, keyword)| keyword)
+            .filter(|keyword| list.contains(keyword))
+            .cloned()
+            .unwrap_or_else(|| (if done { "DONE" } else { "TODO" }).to_string())
+    }
+
+    /// The lines of `item` as they are written when it changed.
+    fn render_item(&self, item: &Item, done: bool, level: usize) -> String {
+        let mut out = String::new();
+        let mut headline = format!("{} {}", "*".repeat(level), self.keyword(item, done));
+        if let Some(priority) = item.priority {
+            headline.push_str(&format!(" [#{}]", priority));
+        }
+        if !item.title.is_empty() {
+            headline.push(' ');
+            headline.push_str(&item.title);
+        }
+        if !item.tags.is_empty() {
+            headline.push_str(&format!(" :{}:", item.tags.join(":")));
+        }
+        out.push_str(&headline);
+        out.push('\n');
+
+        let extension = |key: &str| {
+            item.extensions
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone())
+        };
+        let mut planning = Vec::new();
+        if let (Some(completed), true) = (item.completed, done) {
+            planning.push(format!(
+                "CLOSED: {}",
+                format_org_time(completed, self.utc_offset)
+            ));
+        }
+        let repeater = item.recurrence.as_ref().and_then(render_repeater);
+        if let Some(due) = item.due {
+            planning.push(format!(
+                "DEADLINE: {}",
+                format_org_date(due, repeater.as_deref())
+            ));
+        }
+        if let Some(scheduled) = extension(SCHEDULED) {
+            planning.push(format!("SCHEDULED: {}", scheduled));
+        }
+        if !planning.is_empty() {
+            out.push_str(&planning.join(" "));
+            out.push('\n');
+        }
+
+        let mut properties: Vec<(String, String)> = Vec::new();
+        if let Some(id) = self.org_ids.get(&item.id) {
+            if extension("ID").is_none() {
+                properties.push(("ID".to_string(), id.clone()));
+            }
+        }
+        if let Some(created) = item.created {
+            properties.push((
+                "CREATED".to_string(),
+                format_org_time(created, self.utc_offset),
+            ));
+        }
+        for (key, value) in &item.extensions {
+            if key != KEYWORD && key != SCHEDULED {
+                properties.push((key.clone(), value.clone()));
+            }
+        }
+        let blockers: Vec<&str> = item
+            .depends
+            .iter()
+            .filter_map(|id| self.org_ids.get(id))
+            .map(|id| id.as_str())
+            .collect();
+        if !blockers.is_empty() {
+            properties.push(("BLOCKER".to_string(), blockers.join(" ")));
+        }
+        // Repeaters only go on the deadline and can't name weekdays
+        if let Some(recurrence) = &item.recurrence {
+            if repeater.is_none() || item.due.is_none() {
+                properties.push(("RECUR".to_string(), recurrence.to_string()));
+            }
+        }
+        if !properties.is_empty() {
+            out.push_str(":PROPERTIES:\n");
+            for (key, value) in properties {
+                out.push_str(&format!(":{}: {}\n", key, value));
+            }
+            out.push_str(":END:\n");
+        }
+        if !item.note.is_empty() {
+            for line in item.note.split('\n') {
+                out.push_str(&escape_note_line(line));
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+impl section::Layout for Layout<'_> {
+    fn item(
+        &mut self,
+        out: &mut String,
+        section: &Option<String>,
+        item: &Item,
+        done: bool,
+        depth: usize,
+    ) {
+        let level = self.levels.get(section).copied().unwrap_or(0) + 1 + depth;
+        let rendered = self.render_item(item, done, level);
+        match self
+            .unchanged
+            .get_mut(&rendered)
+            .and_then(|lines| lines.pop())
+        {
+            Some(lines) => {
+                for line in lines {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+            None => out.push_str(&rendered),
+        }
+    }
+
+    fn heading(&mut self, out: &mut String, section: &Option<String>) {
+        if let Some(name) = section {
+            out.push_str(&format!("* {}\n", name));
+            self.levels.insert(section.clone(), 1);
+        }
+    }
+}
+
+/// Items someone depends on need an `ID` to be referred to, the others keep
+/// the one they have.
+fn org_ids<'a>(items: impl Iterator<Item = &'a Item> + Clone) -> HashMap<u64, String> {
+    let referenced: HashSet<u64> = items
+        .clone()
+        .flat_map(|item| item.depends.iter())
+        .copied()
+        .collect();
+    items
+        .filter_map(|item| {
+            let own = item
+                .extensions
+                .iter()
+                .find(|(key, _)| key == "ID")
+                .map(|(_, id)| id.clone());
+            match own {
+                Some(id) => Some((item.id, id)),
+                None if referenced.contains(&item.id) => Some((item.id, item.id.to_string())),
+                None => None,
+            }
+        })
+        .collect()
+}
+
+/// Lines of each task of the original file by how it would be rendered. The
+/// blank lines after a task go with it when another task follows them.
+fn original_lines<'a>(
+    raw: &'a [&'a str],
+    lines: &[Line],
+    tasks: &[Task],
+    layout: &Layout,
+) -> HashMap<String, Vec<&'a [&'a str]>> {
+    let mut rendered: HashMap<String, Vec<&[&str]>> = HashMap::new();
+    // Reversed so that popping gives identical tasks in file order
+    for task in tasks.iter().rev() {
+        let mut end = task.lines.end;
+        while matches!(lines.get(end), Some(Line::Blank)) {
+            end += 1;
+        }
+        if !matches!(lines.get(end), Some(Line::Task { .. })) {
+            end = task.lines.end;
+        }
+        rendered
+            .entry(layout.render_item(&task.item, task.done, task.level))
+            .or_default()
+            .push(&raw[task.lines.start..end]);
+    }
+    rendered
+}
+
+/// Renders the items onto `original`, keeping all of its non-task lines and
+/// the lines of the tasks that did not change.
+pub fn render(original: Option<&str>, todos: &[Item], dones: &[Item]) -> String {
+    let content = original.unwrap_or("");
+    let keywords = keywords(content);
+    let raw: Vec<&str> = content.lines().collect();
+    let lines = classify(content, &keywords);
+    let mut levels = HashMap::new();
+    let mut sections: Vec<Option<&str>> = Vec::new();
+    for line in &lines {
+        match line {
+            Line::Heading { level, text } => {
+                levels.entry(Some(text.to_string())).or_insert(*level);
+            }
+            Line::Task { section, .. } => sections.push(*section),
+            _ => {}
+        }
+    }
+    // New items go where the tasks without a section are, if there are any
+    let default = match sections.first() {
+        Some(_) if sections.contains(&None) => None,
+        Some(section) => section.map(|text| text.to_string()),
+        None => None,
+    };
+
+    let utc_offset = SystemClock.utc_offset();
+    // A file that does not parse has no tasks to keep
+    let tasks = parse_tasks(content, &keywords, utc_offset).unwrap_or_default();
+    let mut layout = Layout {
+        keywords,
+        levels,
+        org_ids: org_ids(tasks.iter().map(|task| &task.item)),
+        utc_offset,
+        unchanged: HashMap::new(),
+    };
+    layout.unchanged = original_lines(&raw, &lines, &tasks, &layout);
+    layout.org_ids = org_ids(todos.iter().chain(dones.iter()));
+
+    let mut writer = Writer::new(todos, dones, default, layout);
+    let is_task = |line: &Line| matches!(line, Line::Task { .. } | Line::Owned);
+    let mut section: Option<String> = None;
+    for (index, line) in lines.iter().enumerate() {
+        match line {
+            Line::Heading { text, .. } => {
+                if section.is_some() {
+                    writer.write_section(&section);
+                }
+                writer.out.push_str(raw[index]);
+                writer.out.push('\n');
+                section = Some(text.to_string());
+            }
+            Line::Task { section: of, .. } => {
+                section = of.map(|text| text.to_string());
+                writer.write_section(&section);
+            }
+            Line::Owned => {}
+            Line::Blank => {
+                // Blank lines between tasks go with the tasks
+                let prev = lines[..index]
+                    .iter()
+                    .rev()
+                    .find(|l| !matches!(l, Line::Blank));
+                let next = lines[index..].iter().find(|l| !matches!(l, Line::Blank));
+                if !(prev.is_some_and(is_task) && next.is_some_and(is_task)) {
+                    writer.out.push('\n');
+                }
+            }
+            Line::Prose => {
+                writer.out.push_str(raw[index]);
+                writer.out.push('\n');
+            }
+        }
+    }
+    writer.write_section(&section);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "\
+#+TODO: TODO NEXT | DONE CANCELLED
+* Release
+Anything that is not a task is left alone.
+** NEXT [#A] Write the changelog :docs:
+   SCHEDULED: <2024-05-08 Wed> DEADLINE: <2024-05-10 Fri +1w>
+   :PROPERTIES:
+   :ID: 3
+   :CREATED: [2024-05-01 Wed 09:00]
+   :END:
+   Body text is the note.
+*** DONE Collect merged pull requests
+    CLOSED: [2024-05-09 Thu 17:30]
+
+** TODO Tag the release
+   :PROPERTIES:
+   :BLOCKER: 3
+   :END:
+* Notes
+Just prose.
+";
+
+    #[test]
+    fn unchanged_tasks_are_written_back_byte_for_byte() {
+        let (todos, dones) = parse(FILE).unwrap();
+        assert_eq!(render(Some(FILE), &todos, &dones), FILE);
+    }
+
+    #[test]
+    fn only_changed_tasks_are_rewritten() {
+        let (mut todos, dones) = parse(FILE).unwrap();
+        let tag = todos
+            .iter_mut()
+            .find(|item| item.title == "Tag the release")
+            .unwrap();
+        tag.title = "Tag and publish the release".to_string();
+        let expected = FILE.replace(
+            "\
+** TODO Tag the release
+   :PROPERTIES:
+   :BLOCKER: 3
+   :END:
+",
+            "\
+** TODO Tag and publish the release
+:PROPERTIES:
+:BLOCKER: 3
+:END:
+",
+        );
+        assert_eq!(render(Some(FILE), &todos, &dones), expected);
+    }
+
+    #[test]
+    fn parsed_tasks_keep_their_fields() {
+        let (todos, dones) = parse(FILE).unwrap();
+        let changelog = &todos[0];
+        assert_eq!(changelog.id, 3);
+        assert_eq!(changelog.priority, Some('A'));
+        assert_eq!(changelog.tags, ["docs"]);
+        assert_eq!(changelog.projects, ["Release"]);
+        assert_eq!(changelog.due, Date::parse("2024-05-10"));
+        assert_eq!(changelog.note, "   Body text is the note.");
+        assert_eq!(dones[0].parent, Some(3));
+        assert_eq!(todos[1].depends, [3]);
+    }
+
+    #[test]
+    fn note_lines_like_headlines_are_escaped() {
+        let mut item = Item::new(1, "Write");
+        item.note = "* not a task\n,* escaped already\n  #+TODO: A | B\nplain, text".to_string();
+        let content = render(None, &[item.clone()], &[]);
+        assert!(content.contains("\n,* not a task\n,,* escaped already\n  ,#+TODO: A | B\n"));
+        let (todos, dones) = parse(&content).unwrap();
+        assert_eq!(todos.len(), 1);
+        assert!(dones.is_empty());
+        assert_eq!(todos[0].note, item.note);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::todo::item::Item;
+use std::collections::HashSet;
+
+// Markdown and Org files group tasks under headings, the section of an item
+// is its first project. When saving over a file the tasks of every section
+// are written where the section's tasks used to be, sections the file does
+// not have yet go to its end.
+
+/// How a format writes the tasks and headings of a section.
+pub trait Layout {
+    /// Writes `item` without its subtasks, which follow at `depth + 1`.
+    fn item(
+        &mut self,
+        out: &mut String,
+        section: &Option<String>,
+        item: &Item,
+        done: bool,
+        depth: usize,
+    );
+
+    /// Writes the heading of a section that is not in the file yet, also
+    /// called for the tasks without a section.
+    fn heading(&mut self, out: &mut String, section: &Option<String>);
+}
+
+pub struct Writer<'a, L> {
+    todos: &'a [Item],
+    dones: &'a [Item],
+    pub out: String,
+    written: HashSet<Option<String>>,
+    /// Section of items without a project, the first one holding tasks.
+    default: Option<String>,
+    layout: L,
+}
+
+impl<'a, L: Layout> Writer<'a, L> {
+    pub fn new(todos: &'a [Item], dones: &'a [Item], default: Option<String>, layout: L) -> Self {
+        Writer {
+            todos,
+            dones,
+            out: String::new(),
+            written: HashSet::new(),
+            default,
+            layout,
+        }
+    }
+
+    fn items(&self) -> impl Iterator<Item = (&'a Item, bool)> {
+        self.todos
+            .iter()
+            .map(|item| (item, false))
+            .chain(self.dones.iter().map(|item| (item, true)))
+    }
+
+    fn exists(&self, id: u64) -> bool {
+        self.items().any(|(item, _)| item.id == id)
+    }
+
+    fn section_of(&self, item: &Item) -> Option<String> {
+        item.projects
+            .first()
+            .cloned()
+            .or_else(|| self.default.clone())
+    }
+
+    fn roots(&self, section: &Option<String>) -> Vec<(&'a Item, bool)> {
+        self.items()
+            .filter(|(item, _)| !item.parent.is_some_and(|parent| self.exists(parent)))
+            .filter(|(item, _)| self.section_of(item) == *section)
+            .collect()
+    }
+
+    fn write_tree(&mut self, section: &Option<String>, item: &Item, done: bool, depth: usize) {
+        self.layout.item(&mut self.out, section, item, done, depth);
+        let children: Vec<(&Item, bool)> = self
+            .items()
+            .filter(|(child, _)| child.parent == Some(item.id))
+            .collect();
+        for (child, done) in children {
+            self.write_tree(section, child, done, depth + 1);
+        }
+    }
+
+    /// Writes the tasks of a section, only the first time it is asked to.
+    pub fn write_section(&mut self, section: &Option<String>) {
+        if !self.written.insert(section.clone()) {
+            return;
+        }
+        for (item, done) in self.roots(section) {
+            self.write_tree(section, item, done, 0);
+        }
+    }
+
+    /// Writes the sections that are not in the file yet and returns the content.
+    pub fn finish(mut self) -> String {
+        let mut sections: Vec<Option<String>> = Vec::new();
+        for (item, _) in self.items() {
+            let section = self.section_of(item);
+            if !sections.contains(&section) {
+                sections.push(section);
+            }
+        }
+        for section in sections {
+            if self.written.contains(&section) || self.roots(&section).is_empty() {
+                continue;
+            }
+            self.layout.heading(&mut self.out, &section);
+            self.write_section(&section);
+        }
+        self.out
+    }
+}
@@ -0,0 +1,461 @@
+use super::json::{self, Value};
+use crate::todo::clock::{Clock, SystemClock};
+use crate::todo::date::*;
+use crate::todo::item::*;
+use crate::todo::recurrence::{Frequency, Recurrence};
+use std::collections::HashMap;
+
+// The JSON array of `task export`:
+//
+//     [{"uuid":"5f8b...","description":"Write the report","status":"pending",
+//       "entry":"20240501T090000Z","project":"work","tags":["office"],
+//       "priority":"H","due":"20240509T220000Z",
+//       "annotations":[{"entry":"20240502T100000Z","description":"Draft sent"}]}]
+//
+// `pending` and `waiting` tasks go to TODO, `completed` and `deleted` ones to
+// DONE. Priorities H, M and L are A, B and C, annotations are the lines of the
+// note and dependencies point at other tasks by `uuid`. Fields without a place
+// in the item, `uuid` included, are kept as extensions and exported back as
+// they came.
+
+/// Fields recomputed by Taskwarrior, they are not worth keeping.
+const DROPPED: [&str; 2] = ["id", "urgency"];
+
+const MAPPED: [&str; 11] = [
+    "description",
+    "status",
+    "entry",
+    "end",
+    "project",
+    "tags",
+    "priority",
+    "due",
+    "annotations",
+    "depends",
+    "recur",
+];
+
+/// Extension holding the entry time of each annotation, in note line order.
+const ANNOTATION_ENTRIES: &str = "annotation_entries";
+
+fn parse_time(text: &str) -> Option<i64> {
+    let number = |range: std::ops::Range<usize>| -> Option<u32> { text.get(range)?.parse().ok() };
+    if text.len() != 16 || text.as_bytes()[8] != b'T' || !text.ends_with('Z') {
+        return None;
+    }
+    let date = Date::new(number(0..4)? as i32, number(4..6)?, number(6..8)?)?;
+    let time = number(9..11)? * 3600 + number(11..13)? * 60 + number(13..15)?;
+    Some(date.days() * 86400 + time as i64)
+}
+
+fn format_time(secs: i64) -> String {
+    // YYYY-MM-DDTHH:MM:SSZ without the separators
+    format_timestamp(secs).replace(['-', ':'], "")
+}
+
+fn parse_recur(text: &str) -> Option<Frequency> {
+    match text {
+        "daily" | "day" | "1d" => Some(Frequency::Daily),
+        "weekly" | "week" | "1w" | "7d" => Some(Frequency::Weekly(Vec::new())),
+        "monthly" | "month" | "1mo" => Some(Frequency::Monthly),
+        _ => {
+            let (count, unit) = text.split_at(text.find(|c: char| !c.is_ascii_digit())?);
+            let count: u32 = count.parse().ok().filter(|count| *count > 0)?;
+            match unit {
+                "d" | "days" => Some(Frequency::EveryDays(count)),
+                "w" | "weeks" => Some(Frequency::EveryDays(count * 7)),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn render_recur(frequency: &Frequency) -> String {
+    match frequency {
+        Frequency::Daily => "daily".to_string(),
+        // Taskwarrior has no rule for given weekdays, the closest is every week
+        Frequency::Weekly(_) => "weekly".to_string(),
+        Frequency::Monthly => "monthly".to_string(),
+        Frequency::EveryDays(days) => format!("{}d", days),
+    }
+}
+
+/// Extension value of a field, strings as they are, anything else as JSON.
+fn extension_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_compact(),
+    }
+}
+
+fn field_value(text: &str) -> Value {
+    match json::parse_value(text) {
+        Ok(value @ (Value::Array(_) | Value::Object(_) | Value::Number(_) | Value::Bool(_))) => {
+            value
+        }
+        _ => Value::string(text),
+    }
+}
+
+fn uuids(value: &Value) -> Vec<String> {
+    match value {
+        // Taskwarrior before 2.6 joins them with commas
+        Value::String(text) => text.split(',').map(|uuid| uuid.to_string()).collect(),
+        Value::Array(values) => values
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|uuid| uuid.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn parse_task(
+    value: &Value,
+    id: u64,
+    utc_offset: i64,
+) -> Result<(Item, bool, Vec<String>), String> {
+    let fields = match value {
+        Value::Object(fields) => fields,
+        _ => return Err("tasks must be objects".to_string()),
+    };
+    let text = |key: &str| value.get(key).and_then(Value::as_str);
+    let time = |key: &str| -> Result<Option<i64>, String> {
+        match text(key) {
+            Some(stamp) => parse_time(stamp)
+                .map(Some)
+                .ok_or_else(|| format!("invalid {} `{}`", key, stamp)),
+            None => Ok(None),
+        }
+    };
+    let mut item = Item::new(id, text("description").unwrap_or(""));
+    let status = text("status").unwrap_or("pending");
+    let done = matches!(status, "completed" | "deleted");
+    item.created = time("entry")?;
+    if done {
+        item.completed = time("end")?;
+    }
+    item.projects
+        .extend(text("project").map(|project| project.to_string()));
+    if let Some(tags) = value.get("tags").and_then(Value::as_array) {
+        item.tags = tags
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|tag| tag.to_string())
+            .collect();
+    }
+    item.priority = match text("priority") {
+        Some("H") => Some('A'),
+        Some("M") => Some('B'),
+        Some("L") => Some('C'),
+        _ => None,
+    };
+    item.due = time("due")?.map(|due| Date::from_days((due + utc_offset).div_euclid(86400)));
+    let mut entries = Vec::new();
+    let mut lines = Vec::new();
+    for annotation in value
+        .get("annotations")
+        .and_then(Value::as_array)
+        .unwrap_or(&[])
+    {
+        lines.push(
+            annotation
+                .get("description")
+                .and_then(Value::as_str)
+                .unwrap_or(""),
+        );
+        entries.push(
+            annotation
+                .get("entry")
+                .and_then(Value::as_str)
+                .unwrap_or(""),
+        );
+    }
+    item.note = lines.join("\n");
+    let mut recur = text("recur");
+    if let Some(frequency) = recur.and_then(parse_recur) {
+        item.recurrence = Some(Recurrence {
+            frequency,
+            after_completion: false,
+        });
+        recur = None;
+    }
+    for (key, field) in fields {
+        let keep = (!DROPPED.contains(&key.as_str()) && !MAPPED.contains(&key.as_str()))
+            || key == "status" && !matches!(status, "pending" | "completed")
+            || key == "end" && !done
+            || key == "recur" && recur.is_some();
+        if !keep {
+            continue;
+        }
+        let extension = (key.clone(), extension_value(field));
+        // `status` is always written second, so it comes before the others
+        // to keep their order the same when read back
+        if key == "status" {
+            item.extensions.insert(0, extension);
+        } else {
+            item.extensions.push(extension);
+        }
+    }
+    if !entries.is_empty() {
+        item.extensions
+            .push((ANNOTATION_ENTRIES.to_string(), entries.join(",")));
+    }
+    let depends = value.get("depends").map(uuids).unwrap_or_default();
+    Ok((item, done, depends))
+}
+
+pub fn parse(content: &str) -> Result<(Vec<Item>, Vec<Item>), String> {
+    let utc_offset = SystemClock.utc_offset();
+    let tasks = match json::parse_value(content)? {
+        Value::Array(tasks) => tasks,
+        _ => return Err("expected the array of `task export`".to_string()),
+    };
+    let mut parsed = Vec::new();
+    for (index, task) in tasks.iter().enumerate() {
+        parsed.push(parse_task(task, index as u64 + 1, utc_offset)?);
+    }
+    let ids: HashMap<String, u64> = parsed
+        .iter()
+        .filter_map(|(item, _, _)| {
+            let uuid = item.extensions.iter().find(|(key, _)| key == "uuid")?;
+            Some((uuid.1.clone(), item.id))
+        })
+        .collect();
+    let mut todos = Vec::new();
+    let mut dones = Vec::new();
+    for (mut item, done, depends) in parsed {
+        item.depends = depends
+            .iter()
+            .filter_map(|uuid| ids.get(uuid))
+            .copied()
+            .collect();
+        if done {
+            dones.push(item);
+        } else {
+            todos.push(item);
+        }
+    }
+    Ok((todos, dones))
+}
+
+fn render_task(item: &Item, done: bool, uuids: &HashMap<u64, String>, utc_offset: i64) -> Value {
+    let extension = |key: &str| {
+        item.extensions
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    };
+    let mut fields: Vec<(String, Value)> = Vec::new();
+    let mut field = |key: &str, value: Value| fields.push((key.to_string(), value));
+    let status = match (extension("status"), done) {
+        (Some(status @ ("waiting" | "recurring")), false) => status,
+        (Some("deleted"), true) => "deleted",
+        (_, false) => "pending",
+        (_, true) => "completed",
+    };
+    field("description", Value::string(&item.title));
+    field("status", Value::string(status));
+    if let Some(created) = item.created {
+        field("entry", Value::String(format_time(created)));
+    }
+    if let (Some(completed), true) = (item.completed, done) {
+        field("end", Value::String(format_time(completed)));
+    }
+    if let Some(project) = item.projects.first() {
+        field("project", Value::string(project));
+    }
+    // Taskwarrior has one project per task, the others become tags
+    let tags: Vec<Value> = item
+        .tags
+        .iter()
+        .chain(item.projects.iter().skip(1))
+        .map(|tag| Value::string(tag))
+        .collect();
+    if !tags.is_empty() {
+        field("tags", Value::Array(tags));
+    }
+    match item.priority {
+        Some('A') => field("priority", Value::string("H")),
+        Some('B') => field("priority", Value::string("M")),
+        Some(_) => field("priority", Value::string("L")),
+        None => {}
+    }
+    if let Some(due) = item.due {
+        field(
+            "due",
+            Value::String(format_time(due.days() * 86400 - utc_offset)),
+        );
+    }
+    if let Some(recurrence) = &item.recurrence {
+        field("recur", Value::String(render_recur(&recurrence.frequency)));
+    }
+    if !item.note.is_empty() {
+        let entries: Vec<&str> = extension(ANNOTATION_ENTRIES)
+            .map_or(Vec::new(), |entries| entries.split(',').collect());
+        let fallback = format_time(item.created.unwrap_or(0));
+        let annotations = item
+            .note
+            .split('\n')
+            .enumerate()
+            .map(|(index, line)| {
+                let entry = entries
+                    .get(index)
+                    .filter(|entry| !entry.is_empty())
+                    .map_or(fallback.clone(), |entry| entry.to_string());
+                Value::Object(vec![
+                    ("entry".to_string(), Value::String(entry)),
+                    ("description".to_string(), Value::string(line)),
+                ])
+            })
+            .collect();
+        field("annotations", Value::Array(annotations));
+    }
+    let depends: Vec<Value> = item
+        .depends
+        .iter()
+        .filter_map(|id| uuids.get(id))
+        .map(|uuid| Value::string(uuid))
+        .collect();
+    if !depends.is_empty() {
+        field("depends", Value::Array(depends));
+    }
+    // Fields written above win over extensions of the same name
+    for (key, value) in &item.extensions {
+        if key != ANNOTATION_ENTRIES && !fields.iter().any(|(name, _)| name == key) {
+            fields.push((key.clone(), field_value(value)));
+        }
+    }
+    Value::Object(fields)
+}
+
+pub fn render(todos: &[Item], dones: &[Item]) -> String {
+    let utc_offset = SystemClock.utc_offset();
+    // Dependencies need a uuid on the other end, items that never came from
+    // Taskwarrior get one made up from their id
+    let uuids: HashMap<u64, String> = todos
+        .iter()
+        .chain(dones.iter())
+        .map(|item| {
+            let uuid = item
+                .extensions
+                .iter()
+                .find(|(key, _)| key == "uuid")
+                .map(|(_, uuid)| uuid.clone())
+                .unwrap_or_else(|| format!("00000000-0000-4000-8000-{:012x}", item.id));
+            (item.id, uuid)
+        })
+        .collect();
+    let tasks = todos
+        .iter()
+        .map(|item| (item, false))
+        .chain(dones.iter().map(|item| (item, true)))
+        .map(|(item, done)| {
+            let mut task = render_task(item, done, &uuids, utc_offset);
+            if let Value::Object(fields) = &mut task {
+                if !fields.iter().any(|(key, _)| key == "uuid") {
+                    fields.push(("uuid".to_string(), Value::String(uuids[&item.id].clone())));
+                }
+            }
+            task
+        })
+        .collect();
+    Value::Array(tasks).to_pretty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `task export` of Taskwarrior 2.6, dependencies joined with commas.
+    const EXPORT_2_6: &str = include_str!("../../tests/fixtures/task-export-2.6.json");
+    /// `task export` of Taskwarrior 3, dependencies as an array.
+    const EXPORT_3: &str = include_str!("../../tests/fixtures/task-export-3.json");
+
+    fn titles(list: &[Item]) -> Vec<&str> {
+        list.iter().map(|item| item.title.as_str()).collect()
+    }
+
+    fn extension<'a>(item: &'a Item, key: &str) -> Option<&'a str> {
+        item.extensions
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn tasks(content: &str) -> Vec<Value> {
+        match json::parse_value(content).unwrap() {
+            Value::Array(tasks) => tasks,
+            _ => panic!("not an array"),
+        }
+    }
+
+    #[test]
+    fn import_maps_tasks_to_items() {
+        let (todos, dones) = parse(EXPORT_2_6).unwrap();
+        assert_eq!(
+            titles(&todos),
+            ["Write the report", "Send the report", "Water plants"]
+        );
+        assert_eq!(titles(&dones), ["Book the room", "Old idea"]);
+
+        let report = &todos[0];
+        assert_eq!(report.priority, Some('A'));
+        assert_eq!(report.projects, ["work"]);
+        assert_eq!(report.tags, ["office", "writing"]);
+        assert_eq!(report.note, "Draft sent\nWaiting for numbers");
+        assert_eq!(report.created, parse_timestamp("2024-05-01T09:00:00Z"));
+        assert_eq!(
+            extension(report, "uuid"),
+            Some("5f8b3c1e-2d4a-4f6b-9c8d-1e2f3a4b5c6d")
+        );
+        assert_eq!(extension(report, "urgency"), None);
+        assert_eq!(todos[1].depends, [report.id]);
+        assert_eq!(extension(&todos[2], "status"), Some("waiting"));
+        assert_eq!(dones[0].completed, parse_timestamp("2024-04-30T15:00:00Z"));
+        assert_eq!(dones[0].priority, Some('C'));
+        assert_eq!(extension(&dones[1], "status"), Some("deleted"));
+
+        let (todos, _) = parse(EXPORT_3).unwrap();
+        assert_eq!(
+            todos[0].recurrence.as_ref().map(|rule| rule.to_string()),
+            Some("monthly".to_string())
+        );
+        assert_eq!(todos[1].depends, [todos[0].id]);
+    }
+
+    #[test]
+    fn export_gives_back_what_taskwarrior_imports() {
+        let (todos, dones) = parse(EXPORT_2_6).unwrap();
+        let exported = tasks(&render(&todos, &dones));
+        let original = tasks(EXPORT_2_6);
+        assert_eq!(exported.len(), original.len());
+        for (task, before) in exported.iter().zip(&original) {
+            for key in ["uuid", "description", "status", "entry", "end", "project"] {
+                assert_eq!(task.get(key), before.get(key), "{}", key);
+            }
+            for key in ["tags", "priority", "annotations", "modified", "wait"] {
+                assert_eq!(task.get(key), before.get(key), "{}", key);
+            }
+            assert_eq!(task.get("id"), None);
+            assert_eq!(task.get("urgency"), None);
+        }
+        assert_eq!(
+            exported[1].get("depends"),
+            Some(&Value::Array(vec![Value::string(
+                "5f8b3c1e-2d4a-4f6b-9c8d-1e2f3a4b5c6d"
+            )]))
+        );
+    }
+
+    #[test]
+    fn items_survive_a_round_trip() {
+        for export in [EXPORT_2_6, EXPORT_3] {
+            let (todos, dones) = parse(export).unwrap();
+            let rendered = render(&todos, &dones);
+            assert_eq!(parse(&rendered), Ok((todos, dones)));
+            let (todos, dones) = parse(&rendered).unwrap();
+            assert_eq!(render(&todos, &dones), rendered);
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use crate::todo::date::{Date, WEEKDAYS};
+use crate::todo::item::*;
+use crate::todo::recurrence::{Frequency, Recurrence};
+use std::collections::{HashMap, HashSet};
+
+// One item per line, see https://github.com/todotxt/todo.txt:
+//
+//     x 2024-05-15 2024-05-01 Call mom +family @phone due:2024-05-16
+//     (A) 2024-05-02 Write the report +work id:3
+//
+// `id:`, `parent:` and `dep:` keep subtasks and dependencies, `rec:` follows
+// the Simpletask syntax (`rec:+1w` keeps the schedule), with weekdays like
+// `rec:+mon,fri` added. `history:` lists earlier completion dates, `note:`
+// holds the note with `%`, `:` and whitespace escaped as in URLs. Any other
+// `key:value` pair whose key is a word, so not `10:30`, is kept as an
+// extension. Lines of items that did not change are written back as they
+// were, with their words in the same order, other lines put projects,
+// contexts and pairs at the end.
+
+fn take_date(text: &str) -> Option<(Date, &str)> {
+    let date = Date::parse(text.get(..10)?)?;
+    match text.get(10..)? {
+        "" => Some((date, "")),
+        rest => rest.strip_prefix(' ').map(|rest| (date, rest)),
+    }
+}
+
+fn take_priority(text: &str) -> Option<(char, &str)> {
+    let bytes = text.as_bytes();
+    match bytes {
+        [b'(', priority @ b'A'..=b'Z', b')', b' ', ..] => Some((*priority as char, &text[4..])),
+        _ => None,
+    }
+}
+
+fn date_secs(date: Date) -> i64 {
+    date.days() * 86400
+}
+
+fn parse_recurrence(value: &str) -> Option<Recurrence> {
+    let (after_completion, value) = match value.strip_prefix('+') {
+        Some(value) => (false, value),
+        None => (true, value),
+    };
+    let weekdays: Option<Vec<usize>> = value
+        .split(',')
+        .map(|day| WEEKDAYS.iter().position(|name| *name == day))
+        .collect();
+    if let Some(mut weekdays) = weekdays {
+        weekdays.sort();
+        weekdays.dedup();
+        return Some(Recurrence {
+            frequency: Frequency::Weekly(weekdays),
+            after_completion,
+        });
+    }
+    let count: u32 = value.get(..value.len().checked_sub(1)?)?.parse().ok()?;
+    let frequency = match (count, value.chars().last()?) {
+        (1, 'd') => Frequency::Daily,
+        (_, 'd') if count > 0 => Frequency::EveryDays(count),
+        (1, 'w') => Frequency::Weekly(Vec::new()),
+        (_, 'w') if count > 0 => Frequency::EveryDays(count * 7),
+        (1, 'm') => Frequency::Monthly,
+        _ => return None,
+    };
+    Some(Recurrence {
+        frequency,
+        after_completion,
+    })
+}
+
+fn render_recurrence(recurrence: &Recurrence) -> String {
+    let value = match &recurrence.frequency {
+        Frequency::Daily => "1d".to_string(),
+        Frequency::EveryDays(days) => format!("{}d", days),
+        Frequency::Weekly(weekdays) if weekdays.is_empty() => "1w".to_string(),
+        Frequency::Weekly(weekdays) => {
+            let names: Vec<&str> = weekdays.iter().map(|day| WEEKDAYS[*day]).collect();
+            names.join(",")
+        }
+        Frequency::Monthly => "1m".to_string(),
+    };
+    if recurrence.after_completion {
+        value
+    } else {
+        format!("+{}", value)
+    }
+}
+
+/// Escapes a value so it stays one word that reads back as the same pair.
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for (index, c) in value.chars().enumerate() {
+        if c == '%' || c == ':' || c.is_whitespace() || c.is_control() || (index == 0 && c == '/') {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(after.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &after[2..];
+        } else {
+            bytes.push(byte);
+            rest = after;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn parse_dates(value: &str) -> Option<Vec<i64>> {
+    value
+        .split(',')
+        .map(|date| Date::parse(date).map(date_secs))
+        .collect()
+}
+
+fn render_date(secs: i64) -> String {
+    Date::from_days(secs.div_euclid(86400)).to_string()
+}
+
+fn parse_ids(value: &str) -> Option<Vec<u64>> {
+    value.split(',').map(|id| id.parse().ok()).collect()
+}
+
+/// Applies a known `key:value` pair, returns `false` for the ones to keep as
+/// extensions.
+pub fn parse_extension(item: &mut Item, key: &str, value: &str) -> bool {
+    match key {
+        "due" => Date::parse(value).map(|due| item.due = Some(due)).is_some(),
+        "rec" => parse_recurrence(value)
+            .map(|rule| item.recurrence = Some(rule))
+            .is_some(),
+        "id" => value.parse().map(|id| item.id = id).is_ok(),
+        "parent" => value.parse().map(|id| item.parent = Some(id)).is_ok(),
+        "dep" => parse_ids(value).map(|ids| item.depends = ids).is_some(),
+        "history" => parse_dates(value)
+            .map(|dates| item.history = dates)
+            .is_some(),
+        "folded" => {
+            item.folded = value == "yes";
+            item.folded
+        }
+        "note" => unescape(value).map(|note| item.note = note).is_some(),
+        "pri" => match value.as_bytes() {
+            [priority @ b'A'..=b'Z'] => {
+                item.priority = Some(*priority as char);
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Keys start with a letter, so times like `10:30` stay in the title.
+fn is_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphabetic())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn parse_line(line: &str) -> (Item, bool) {
+    let mut item = Item::new(0, "");
+    let mut rest = line;
+    let done = rest.starts_with("x ");
+    if done {
+        rest = &rest[2..];
+        if let Some((completed, after)) = take_date(rest) {
+            item.completed = Some(date_secs(completed));
+            rest = after;
+            if let Some((created, after)) = take_date(rest) {
+                item.created = Some(date_secs(created));
+                rest = after;
+            }
+        }
+    } else {
+        if let Some((priority, after)) = take_priority(rest) {
+            item.priority = Some(priority);
+            rest = after;
+        }
+        if let Some((created, after)) = take_date(rest) {
+            item.created = Some(date_secs(created));
+            rest = after;
+        }
+    }
+
+    item.title = parse_words(&mut item, rest, parse_extension);
+    (item, done)
+}
+
+/// Takes the projects, contexts and pairs out of `text` into `item`, `apply`
+/// taking the pairs it knows, and returns the remaining words, the title.
+pub fn parse_words(
+    item: &mut Item,
+    text: &str,
+    apply: impl Fn(&mut Item, &str, &str) -> bool,
+) -> String {
+    let mut words = Vec::new();
+    for word in text.split(' ') {
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            item.projects.push(project.to_string());
+        } else if let Some(tag) = word.strip_prefix('@').filter(|t| !t.is_empty()) {
+            item.tags.push(tag.to_string());
+        } else if let Some((key, value)) = word
+            .split_once(':')
+            .filter(|(key, _)| is_key(key))
+            .filter(|(_, value)| !value.is_empty())
+            .filter(|(_, value)| !value.contains(':') && !value.starts_with("//"))
+        {
+            if !apply(item, key, value) {
+                item.extensions.push((key.to_string(), value.to_string()));
+            }
+        } else {
+            words.push(word);
+        }
+    }
+    words.join(" ").trim().to_string()
+}
+
+pub fn parse(content: &str) -> Result<(Vec<Item>, Vec<Item>), String> {
+    let mut todos = Vec::new();
+    let mut dones = Vec::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_line(line) {
+            (item, false) => todos.push(item),
+            (item, true) => dones.push(item),
+        }
+    }
+    assign_missing_ids(&mut todos, &mut dones);
+    Ok((todos, dones))
+}
+
+/// Ids are only worth writing when something refers to them.
+fn referenced<'a>(items: impl Iterator<Item = &'a Item>) -> HashSet<u64> {
+    items
+        .flat_map(|item| item.parent.iter().chain(item.depends.iter()))
+        .copied()
+        .collect()
+}
+
+fn render_item(item: &Item, done: bool, referenced: &HashSet<u64>) -> String {
+    let mut words: Vec<String> = Vec::new();
+    if done {
+        words.push("x".to_string());
+        if let Some(completed) = item.completed {
+            words.push(render_date(completed));
+            if let Some(created) = item.created {
+                words.push(render_date(created));
+            }
+        }
+    } else {
+        if let Some(priority) = item.priority {
+            words.push(format!("({})", priority));
+        }
+        if let Some(created) = item.created {
+            words.push(render_date(created));
+        }
+    }
+    if !item.title.is_empty() {
+        words.push(item.title.clone());
+    }
+    words.extend(item.projects.iter().map(|project| format!("+{}", project)));
+    words.extend(item.tags.iter().map(|tag| format!("@{}", tag)));
+    if referenced.contains(&item.id) {
+        words.push(format!("id:{}", item.id));
+    }
+    if let Some(parent) = item.parent {
+        words.push(format!("parent:{}", parent));
+    }
+    if let (true, Some(priority)) = (done, item.priority) {
+        words.push(format!("pri:{}", priority));
+    }
+    render_pairs(item, &mut words);
+    if !item.note.is_empty() {
+        words.push(format!("note:{}", escape(&item.note)));
+    }
+    words.join(" ")
+}
+
+/// Pairs of the fields todo.txt and Markdown lines write the same way.
+pub fn render_pairs(item: &Item, words: &mut Vec<String>) {
+    if let Some(due) = item.due {
+        words.push(format!("due:{}", due));
+    }
+    if let Some(rule) = &item.recurrence {
+        words.push(format!("rec:{}", render_recurrence(rule)));
+    }
+    if !item.depends.is_empty() {
+        let ids: Vec<String> = item.depends.iter().map(|id| id.to_string()).collect();
+        words.push(format!("dep:{}", ids.join(",")));
+    }
+    if item.folded {
+        words.push("folded:yes".to_string());
+    }
+    if !item.history.is_empty() {
+        let dates: Vec<String> = item.history.iter().map(|secs| render_date(*secs)).collect();
+        words.push(format!("history:{}", dates.join(",")));
+    }
+    words.extend(
+        item.extensions
+            .iter()
+            .map(|(key, value)| format!("{}:{}", key, value)),
+    );
+}
+
+/// Lines of `original` by how they would be rendered, to find the line of
+/// an item that did not change.
+fn original_lines(original: &str) -> HashMap<String, Vec<&str>> {
+    let lines: Vec<(&str, (Item, bool))> = original
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| (line, parse_line(line)))
+        .collect();
+    let referenced = referenced(lines.iter().map(|(_, (item, _))| item));
+    let mut rendered: HashMap<String, Vec<&str>> = HashMap::new();
+    // Reversed so that popping gives identical lines in file order
+    for (line, (item, done)) in lines.iter().rev() {
+        rendered
+            .entry(render_item(item, *done, &referenced))
+            .or_default()
+            .push(line);
+    }
+    rendered
+}
+
+pub fn render(original: Option<&str>, todos: &[Item], dones: &[Item]) -> String {
+    let referenced = referenced(todos.iter().chain(dones.iter()));
+    let mut unchanged = original.map(original_lines).unwrap_or_default();
+    let mut out = String::new();
+    let items = todos
+        .iter()
+        .map(|item| (item, false))
+        .chain(dones.iter().map(|item| (item, true)));
+    for (item, done) in items {
+        let line = render_item(item, done, &referenced);
+        match unchanged.get_mut(&line).and_then(|lines| lines.pop()) {
+            Some(original) => out.push_str(original),
+            None => out.push_str(&line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "\
+(A) 2024-05-01 Meeting at 10:30 +work @office
+Call +mom about @phone stuff ext:1 due:2024-05-16 see:p2
+2024-05-02 Plan trip https://example.com id:3 board:home  trail:x
+Pack bags parent:3 rec:+1w
+x 2024-05-15 2024-05-01 Send report +work pri:B zz:last
+";
+
+    fn round_trip(content: &str) -> String {
+        let (todos, dones) = parse(content).unwrap();
+        render(Some(content), &todos, &dones)
+    }
+
+    #[test]
+    fn unchanged_lines_are_written_back_byte_for_byte() {
+        assert_eq!(round_trip(FILE), FILE);
+    }
+
+    #[test]
+    fn times_stay_in_the_title() {
+        let (todos, _) = parse(FILE).unwrap();
+        assert_eq!(todos[0].title, "Meeting at 10:30");
+        assert!(todos[0].extensions.is_empty());
+        assert_eq!(
+            render(None, &todos[..1], &[]),
+            "(A) 2024-05-01 Meeting at 10:30 +work @office\n"
+        );
+    }
+
+    #[test]
+    fn unknown_extensions_are_kept_when_an_item_changes() {
+        let (mut todos, dones) = parse(FILE).unwrap();
+        assert_eq!(
+            todos[1].extensions,
+            [
+                ("ext".to_string(), "1".to_string()),
+                ("see".to_string(), "p2".to_string())
+            ]
+        );
+        todos[1].title = "Call".to_string();
+        let rendered = render(Some(FILE), &todos, &dones);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[1], "Call +mom @phone due:2024-05-16 ext:1 see:p2");
+        // Only the edited line changed
+        let others: Vec<&str> = FILE
+            .lines()
+            .filter(|line| !line.starts_with("Call"))
+            .collect();
+        let kept: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|line| !line.starts_with("Call"))
+            .collect();
+        assert_eq!(kept, others);
+    }
+
+    #[test]
+    fn moved_lines_stay_unchanged() {
+        let (mut todos, dones) = parse(FILE).unwrap();
+        todos.swap(0, 1);
+        let rendered = render(Some(FILE), &todos, &dones);
+        let lines: Vec<&str> = rendered.lines().collect();
+        let original: Vec<&str> = FILE.lines().collect();
+        assert_eq!(lines[0], original[1]);
+        assert_eq!(lines[1], original[0]);
+        assert_eq!(lines[2..], original[2..]);
+    }
+
+    #[test]
+    fn recurrences_without_a_count_are_kept_as_extensions() {
+        for value in ["", "+", "d", "+w"] {
+            assert_eq!(parse_recurrence(value), None);
+        }
+        let (todos, _) = parse("Water plants rec:+\n").unwrap();
+        assert_eq!(todos[0].recurrence, None);
+        assert_eq!(todos[0].extensions, [("rec".to_string(), "+".to_string())]);
+    }
+
+    #[test]
+    fn notes_history_and_folds_survive_a_save() {
+        let mut item = Item::new(1, "Plan trip");
+        item.note = "Book at https://example.com\n\n  50% paid: yes\t/ok".to_string();
+        item.history = vec![date_secs(Date::parse("2024-05-01").unwrap())];
+        item.folded = true;
+        let rendered = render(None, &[item.clone()], &[]);
+        assert_eq!(rendered.lines().count(), 1);
+        let (todos, _) = parse(&rendered).unwrap();
+        assert_eq!(todos, [item]);
+    }
+
+    #[test]
+    fn notes_starting_with_slashes_are_not_urls() {
+        let mut item = Item::new(1, "Read");
+        item.note = "//comment".to_string();
+        let (todos, _) = parse(&render(None, &[item.clone()], &[])).unwrap();
+        assert_eq!(todos[0].note, item.note);
+    }
+
+    #[test]
+    fn weekday_recurrences_are_written() {
+        for text in ["weekly mon,fri", "weekly sun after-completion"] {
+            let mut item = Item::new(1, "Gym");
+            item.recurrence = Some(Recurrence::parse(text).unwrap());
+            let rendered = render(None, &[item.clone()], &[]);
+            let (todos, _) = parse(&rendered).unwrap();
+            assert_eq!(todos[0].recurrence, item.recurrence, "{}", rendered);
+        }
+        let (todos, _) = parse("Gym rec:+mon,fri\n").unwrap();
+        assert_eq!(
+            todos[0].recurrence,
+            Some(Recurrence::parse("weekly mon,fri").unwrap())
+        );
+    }
+}
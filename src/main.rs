@@ -1,29 +1,74 @@
 use ncurses::*;
 use std::collections::HashSet;
+use std::env;
+use std::io;
+use std::process;
+use std::time::{Duration, Instant};
 
+mod cli;
+mod format;
+mod todo;
 mod ui;
 
+use format::document::Document;
+use format::journal::Journal;
+use format::{Format, Stamp};
+use todo::archive;
+use todo::clock::*;
+use todo::date::format_local;
+use todo::depend;
+use todo::item::*;
+use todo::log;
+use todo::merge;
+use todo::tree;
+
 use ui::action::*;
+use ui::board::Boards;
+use ui::command::*;
+use ui::editor;
+use ui::event::*;
+use ui::finder::{self, Finder, Target};
+use ui::help;
 use ui::key_map::*;
+use ui::label::item_label;
 use ui::layout::*;
 use ui::mode::*;
+use ui::notify::*;
+use ui::pager::*;
+use ui::prompt::*;
 use ui::status::*;
 use ui::style;
+use ui::terminal;
 use ui::vec2::*;
+use ui::widget::{Id, Interaction, Widgets};
+
+/// Most actions the palette shows under the command line.
+const PALETTE_ROWS: usize = 8;
+
+/// How often the file is checked for changes made by someone else.
+const RELOAD_POLL: Duration = Duration::from_secs(1);
+/// How long after the last key unsaved changes are saved.
+const AUTOSAVE_DELAY: Duration = Duration::from_secs(2);
+/// Default time between ticks, `--tick` overrides it.
+const TICK: Duration = Duration::from_secs(1);
 
 struct Ui {
     quit: bool,
     layouts: Vec<Layout>,
+    widgets: Widgets,
 }
 
 impl Ui {
     fn new() -> Self {
         initscr();
         keypad(stdscr(), true);
+        mousemask(MOUSE_MASK, None);
+        mouseinterval(0);
         style::init_style();
         Ui {
             quit: false,
             layouts: Vec::new(),
+            widgets: Widgets::default(),
         }
     }
 
@@ -35,22 +80,75 @@ impl Ui {
         self.quit = !self.quit;
     }
 
-    fn label_with_fix_width(&mut self, text: &str, pair: i16, width: i32) {
+    fn label_with_fix_width(&mut self, text: &str, pair: i16, width: i32) -> Rect {
         let layout = self
             .layouts
             .last_mut()
             .expect("Trying to render labele outsize of any layout");
         let new_pos = layout.available_pos();
         mv(new_pos.y, new_pos.x);
-        attron(COLOR_PAIR(pair));
-        addstr(text).unwrap();
-        attroff(COLOR_PAIR(pair));
+        attron(style::attr(pair));
+        // Text holding a NUL byte cannot be drawn
+        let _ = addstr(text);
+        attroff(style::attr(pair));
+        let size = Vec2::new(width, 1);
+        layout.add_widget(size);
+        Rect { pos: new_pos, size }
+    }
+
+    /// Draws `text` padded to `width`, covering whatever was below, with the
+    /// characters at `positions` marked.
+    fn label_with_matches(&mut self, text: &str, positions: &[usize], pair: i16, width: i32) {
+        let layout = self
+            .layouts
+            .last_mut()
+            .expect("Trying to render labele outsize of any layout");
+        let pos = layout.available_pos();
+        mv(pos.y, pos.x);
+        for (index, c) in text
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(width.max(0) as usize)
+            .enumerate()
+        {
+            let attr = if positions.contains(&index) {
+                style::matched(pair)
+            } else {
+                style::attr(pair)
+            };
+            attron(attr);
+            let _ = addstr(c.encode_utf8(&mut [0; 4]));
+            attroff(attr);
+        }
         layout.add_widget(Vec2::new(width, 1));
     }
 
+    /// Up to `rows` matches of the finder, scrolled to the chosen one.
+    fn matches(&mut self, finder: &Finder, rows: usize, width: i32) {
+        let hint_width = width / 3;
+        let skip = (finder.selected + 1).saturating_sub(rows);
+        for (index, found) in finder.matches().iter().enumerate().skip(skip).take(rows) {
+            let pair = if index == finder.selected {
+                style::HIGHLIGHT_PAIR
+            } else {
+                style::REGULAR_PAIR
+            };
+            self.begin_layout(LayoutKind::Horz);
+            self.label_with_matches(
+                &found.entry.text,
+                &found.positions,
+                pair,
+                width - hint_width,
+            );
+            self.label_with_matches(&found.entry.hint, &[], pair, hint_width);
+            self.end_layout();
+        }
+    }
+
     fn begin(&mut self, kind: LayoutKind) {
         assert!(self.layouts.is_empty());
         erase();
+        self.widgets.begin_frame();
         self.layouts.push(Layout::new(kind, Vec2::zero()));
     }
 
@@ -68,7 +166,20 @@ impl Ui {
         self.layouts.push(Layout::new(kind, layout.available_pos()));
     }
 
-    fn end_layout(&mut self) {
+    /// Starts a layout at `pos` that takes no room in the current one, for
+    /// things floating over the screen.
+    fn begin_overlay(&mut self, kind: LayoutKind, pos: Vec2) {
+        assert!(!self.layouts.is_empty());
+        self.layouts.push(Layout::new(kind, pos));
+    }
+
+    fn end_overlay(&mut self) {
+        self.layouts
+            .pop()
+            .expect("Unbalanced Ui::begin_overlay and Ui::end_overlay calls");
+    }
+
+    fn end_layout(&mut self) -> Rect {
         let layout = self
             .layouts
             .pop()
@@ -77,313 +188,1508 @@ impl Ui {
             .last_mut()
             .expect("Unbalanced Ui::begin_layout and Ui::end_layout calls")
             .add_widget(layout.size);
+        Rect {
+            pos: layout.pos,
+            size: layout.size,
+        }
+    }
+}
+
+/// Where the `[ ]` is in a label from `item_label`.
+fn checkbox(label: Rect, depth: usize) -> Rect {
+    Rect {
+        pos: label.pos + Vec2::new(1 + 2 * depth as i32 + 2, 0),
+        size: Vec2::new(3, 1),
+    }
+}
+
+fn current_item<'a>(
+    status: Status,
+    todos: &'a mut [Item],
+    dones: &'a mut [Item],
+    todo_curr: usize,
+    done_curr: usize,
+) -> Option<&'a mut Item> {
+    match status {
+        Status::Todo => todos.get_mut(todo_curr),
+        Status::Done => dones.get_mut(done_curr),
+    }
+}
+
+fn help_pager(mode: &Mode) -> Pager {
+    Pager::overlay(&format!("HELP ({})", mode), help::lines(mode))
+}
+
+/// Byte offset of the `cursor`th character of `title`, its end past the last one.
+fn byte_index(title: &str, cursor: usize) -> usize {
+    title
+        .char_indices()
+        .nth(cursor)
+        .map_or(title.len(), |(index, _)| index)
+}
+
+/// Applies an Insert mode key to the title being edited, through its action
+/// when it is bound. `cursor` counts characters.
+fn edit_title(title: &mut String, cursor: &mut usize, lookup: Lookup, key: i32) {
+    let len = title.chars().count();
+    *cursor = (*cursor).min(len);
+    match (lookup, key) {
+        (Lookup::Action(Action::DeleteNext), _) if *cursor < len => {
+            title.remove(byte_index(title, *cursor));
+        }
+        (Lookup::Action(Action::DeletePrevious), _) if *cursor > 0 => {
+            *cursor -= 1;
+            title.remove(byte_index(title, *cursor));
+        }
+        (Lookup::Action(Action::Left), _) => *cursor = cursor.saturating_sub(1),
+        (Lookup::Action(Action::Right), _) if *cursor < len => *cursor += 1,
+        (Lookup::Unbound, 32..=126) => {
+            title.insert(byte_index(title, *cursor), key as u8 as char);
+            *cursor += 1;
+        }
+        _ => {}
+    }
+}
+
+/// Asks before the TODO items with `ids` go to DONE while one is blocked by
+/// an item staying in TODO on any board, or still has open subtasks that are
+/// not among them.
+fn completion_prompt(
+    todos: &[Item],
+    boards: &Boards,
+    ids: &[u64],
+    blocked_confirmed: bool,
+) -> Option<Prompt> {
+    let open = boards.open_ids(todos);
+    let mut blockers: Vec<u64> = Vec::new();
+    let mut pending: HashSet<u64> = HashSet::new();
+    for index in ids.iter().filter_map(|id| tree::position(todos, *id)) {
+        for blocker in depend::blockers(&todos[index], &open) {
+            if !ids.contains(&blocker) && !blockers.contains(&blocker) {
+                blockers.push(blocker);
+            }
+        }
+        let subtree = &todos[index + 1..tree::subtree_end(todos, index)];
+        pending.extend(
+            subtree
+                .iter()
+                .map(|item| item.id)
+                .filter(|id| !ids.contains(id)),
+        );
+    }
+    let items = ids.to_vec();
+    if !blocked_confirmed && !blockers.is_empty() {
+        return Some(Prompt::CompleteBlocked { blockers, items });
+    }
+    if !pending.is_empty() {
+        let pending = pending.len();
+        return Some(Prompt::CompleteSubtree { pending, items });
+    }
+    None
+}
+
+/// Items of all boards that are blocked now.
+fn blocked_everywhere(todos: &[Item], boards: &Boards) -> (Vec<Item>, HashSet<u64>) {
+    let (all_todos, _) = boards.join(todos, &[]);
+    let blocked = depend::blocked_ids(&all_todos, &depend::open_ids(&all_todos));
+    (all_todos, blocked)
+}
+
+fn notify_unblocked(
+    blocked: &HashSet<u64>,
+    todos: &[Item],
+    boards: &Boards,
+    notifications: &mut Notifications,
+) {
+    let (all_todos, still_blocked) = blocked_everywhere(todos, boards);
+    let titles: Vec<&str> = all_todos
+        .iter()
+        .filter(|item| blocked.contains(&item.id) && !still_blocked.contains(&item.id))
+        .map(|item| item.title.as_str())
+        .collect();
+    if !titles.is_empty() {
+        notifications.info(format!("Unblocked: {}", titles.join(", ")));
     }
 }
 
+/// Moves the TODO items with `ids` to DONE with their subtrees, the cursor
+/// ends up where the last one was.
+fn complete_items(
+    ids: &[u64],
+    todos: &mut Vec<Item>,
+    dones: &mut Vec<Item>,
+    curr: &mut usize,
+    boards: &Boards,
+    clock: &dyn Clock,
+    notifications: &mut Notifications,
+) {
+    let (_, blocked) = blocked_everywhere(todos, boards);
+    for id in ids {
+        // Gone already when it was in the subtree of an earlier one
+        let Some(index) = tree::position(todos, *id) else {
+            continue;
+        };
+        *curr = index;
+        if let Some(due) = complete(todos, dones, curr, clock) {
+            notifications.info(format!("Next occurrence due {}", due));
+        }
+    }
+    notify_unblocked(&blocked, todos, boards, notifications);
+}
+
+/// Quits, unless someone else wrote the file and we have to ask first.
+fn quit_or_ask(ui: &mut Ui, document: &Document) -> Option<Prompt> {
+    if document.changed_on_disk() {
+        return Some(Prompt::Changed { quit: true });
+    }
+    ui.do_quit();
+    None
+}
+
+/// Handles the answer to [`Prompt::Changed`], returns whether to quit.
+fn resolve_change(
+    lookup: Lookup,
+    quit: bool,
+    document: &mut Document,
+    todos: &mut Vec<Item>,
+    dones: &mut Vec<Item>,
+    notifications: &mut Notifications,
+) -> bool {
+    match lookup {
+        Lookup::Action(Action::Reload) => match document.reload() {
+            Ok((new_todos, new_dones)) => {
+                *todos = new_todos;
+                *dones = new_dones;
+                notifications.info(format!("Reloaded {}", document.path));
+                quit
+            }
+            Err(err) => {
+                notifications.error(format!("Could not reload: {}", err));
+                false
+            }
+        },
+        Lookup::Action(Action::Overwrite) => match document.overwrite(todos, dones) {
+            Ok(()) => {
+                notifications.info(format!("Saved {}", document.path));
+                quit
+            }
+            Err(err) => {
+                notifications.error(format!("Could not save: {}", err));
+                false
+            }
+        },
+        Lookup::Action(Action::Merge) => match document.merge(todos, dones) {
+            Ok(merged) => {
+                *todos = merged.todos;
+                *dones = merged.dones;
+                if let Err(err) = document.overwrite(todos, dones) {
+                    notifications.error(format!("Could not save: {}", err));
+                    return false;
+                }
+                if merged.conflicts.is_empty() {
+                    notifications.info(format!("Merged and saved {}", document.path));
+                    quit
+                } else {
+                    // Stay so the conflicting items can be checked
+                    let conflicts: Vec<u64> = merged.conflicts.iter().map(|c| c.id()).collect();
+                    notifications.warn(format!(
+                        "Merged and saved {}, both sides changed {}",
+                        document.path,
+                        ids(&conflicts)
+                    ));
+                    false
+                }
+            }
+            Err(err) => {
+                notifications.error(format!("Could not merge: {}", err));
+                false
+            }
+        },
+        _ => {
+            notifications.info("Cancelled");
+            false
+        }
+    }
+}
+
+/// Reloads the file when someone else changed it and nothing here is unsaved,
+/// returning the items of all boards for `Boards::split`, which keeps the
+/// cursors on the same items. `noticed` is the last change told about, so
+/// unsaved changes only get one notification per change.
+fn reload_if_changed(
+    document: &mut Document,
+    noticed: &mut Stamp,
+    todos: &[Item],
+    dones: &[Item],
+    notifications: &mut Notifications,
+) -> Option<(Vec<Item>, Vec<Item>)> {
+    if !document.changed_on_disk() || !noticed.changed(&document.path) {
+        return None;
+    }
+    *noticed = Stamp::read(&document.path);
+    if document.unsaved(todos, dones) {
+        notifications.warn(format!(
+            "{} changed on disk, :w to reload, overwrite or merge",
+            document.path
+        ));
+        return None;
+    }
+    let (new_todos, new_dones) = match document.reload() {
+        Ok(lists) => lists,
+        Err(err) => {
+            notifications.error(format!("Could not reload: {}", err));
+            return None;
+        }
+    };
+    let (added, removed, changed) = merge::diff((todos, dones), (&new_todos, &new_dones));
+    notifications.info(format!(
+        "Reloaded {}: {} added, {} removed, {} changed",
+        document.path, added, removed, changed
+    ));
+    Some((new_todos, new_dones))
+}
+
+/// Saves unsaved items, when someone else changed the file they are left to
+/// `reload_if_changed`.
+fn autosave(
+    document: &mut Document,
+    todos: &[Item],
+    dones: &[Item],
+    notifications: &mut Notifications,
+) {
+    if !document.unsaved(todos, dones) {
+        return;
+    }
+    if let Err(err) = document.save(todos, dones) {
+        notifications.error(format!("Could not save: {}", err));
+    }
+}
+
+/// Moves DONE items completed before `cutoff` (all without one) to the archive
+/// file, returning how many were archived.
+fn archive_dones(
+    file_path: &str,
+    file_format: Format,
+    todos: &mut [Item],
+    dones: &mut Vec<Item>,
+    cutoff: Option<i64>,
+) -> io::Result<usize> {
+    let archived = archive::select(dones, cutoff);
+    if !archived.is_empty() {
+        format::append_archive(file_path, file_format, &archived)?;
+        archive::remove(todos, dones, &archived);
+    }
+    Ok(archived.len())
+}
+
 fn main() {
+    let clock = SystemClock;
+    let mut file_path = "TODO".to_string();
+    let mut file_format: Option<Format> = None;
+    let mut auto_archive: Option<i64> = None;
+    let mut tick = TICK;
+    let mut subcommand: Option<(String, Vec<String>)> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--file" | "-f" => match args.next() {
+                Some(path) => file_path = path,
+                None => {
+                    eprintln!("ERROR: --file expects a path");
+                    process::exit(1);
+                }
+            },
+            "--auto-archive" => match args.next().and_then(|days| days.parse().ok()) {
+                Some(days) => auto_archive = Some(days),
+                None => {
+                    eprintln!("ERROR: --auto-archive expects a number of days");
+                    process::exit(1);
+                }
+            },
+            "--tick" => match args.next().and_then(|ms| ms.parse().ok()) {
+                Some(ms) if ms > 0 => tick = Duration::from_millis(ms),
+                _ => {
+                    eprintln!("ERROR: --tick expects a number of milliseconds");
+                    process::exit(1);
+                }
+            },
+            "--format" => match args.next().and_then(|name| Format::from_name(&name)) {
+                Some(format) => file_format = Some(format),
+                None => {
+                    eprintln!("ERROR: --format expects {}", Format::NAMES);
+                    process::exit(1);
+                }
+            },
+            name if cli::COMMANDS.contains(&name) => {
+                subcommand = Some((arg, args.by_ref().collect()));
+            }
+            _ => file_path = arg,
+        }
+    }
+    let file_format = file_format.unwrap_or_else(|| Format::from_path(&file_path));
+    if let Some((name, args)) = subcommand {
+        if let Err(err) = cli::run(&name, &args, &file_path, file_format) {
+            eprintln!("ERROR: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+    let mut notifications = Notifications::new(Box::new(SystemClock));
+    let (mut document, mut todos, mut dones) = match Document::open(&file_path, file_format) {
+        Ok(opened) => opened,
+        Err(err) => {
+            eprintln!("ERROR: could not load {}: {}", file_path, err);
+            process::exit(1);
+        }
+    };
+    if !document.stamp.exists() {
+        notifications.info(format!("New file {}", file_path));
+    }
+    let mut prompt: Option<Prompt> = None;
+    let mut journal = match Journal::open(&file_path) {
+        Ok(Some(journal)) => {
+            prompt = journal
+                .left()
+                .map(|(todos, dones)| Prompt::Recover { todos, dones });
+            Some(journal)
+        }
+        Ok(None) => {
+            notifications.warn(format!("{} is open in another session", file_path));
+            None
+        }
+        Err(err) => {
+            notifications.error(format!("Could not open journal: {}", err));
+            None
+        }
+    };
+
+    if let Some(days) = auto_archive {
+        let cutoff = clock.now() - days * 86400;
+        match archive_dones(
+            &file_path,
+            file_format,
+            &mut todos,
+            &mut dones,
+            Some(cutoff),
+        ) {
+            Ok(0) => {}
+            Ok(count) => notifications.info(format!("Archived {} item(s)", count)),
+            Err(err) => notifications.error(format!("Could not archive: {}", err)),
+        }
+    }
+
+    let (mut boards, mut todos, mut dones) = Boards::new(todos, dones);
+
     let mut ui = Ui::new();
+    terminal::install();
     let mut w = 0;
     let mut h = 0;
-    let mut notification: String = String::from("");
+    let mut show_note = false;
+    let mut show_stamps = false;
+    let mut pager: Option<Pager> = None;
+    let mut finder: Option<Finder> = None;
+    let mut palette = Finder::new("ACTIONS", finder::actions());
+    let mut keys: Vec<i32> = Vec::new();
+    let mut command = String::new();
 
     let mut status = Status::Todo;
     let mut mode = Mode::Normal;
     let mut cursor = 0;
     let mut todo_curr: usize = 0;
     let mut done_curr: usize = 0;
-    let mut v_todos: HashSet<String> = HashSet::new();
-    let mut v_dones: HashSet<String> = HashSet::new();
-    let mut todos = vec![
-        "Make todo app".to_string(),
-        "Make a cup of tea".to_string(),
-        "Buy a bread".to_string(),
-    ];
-    let mut dones = vec!["Start the stream".to_string()];
-
-    while !ui.should_quit() {
+    let mut v_todos: HashSet<u64> = HashSet::new();
+    let mut v_dones: HashSet<u64> = HashSet::new();
+
+    let mut noticed = document.stamp;
+    let mut events = Events::new(tick);
+    events.set(Timer::CheckFile, RELOAD_POLL);
+
+    while !ui.should_quit() && !terminal::terminated() {
         getmaxyx(stdscr(), &mut h, &mut w);
+        let column = if show_note { w / 3 } else { w / 2 };
+        let pager_height = match &pager {
+            Some(view) if view.overlay => (h - 4).max(1) as usize,
+            _ => (h - 2).max(1) as usize,
+        };
+        let utc_offset = clock.utc_offset();
 
         ui.begin(LayoutKind::Vert);
         {
-            ui.begin_layout(LayoutKind::Horz);
-            {
+            if let Some(view) = pager.as_ref().filter(|view| !view.overlay) {
                 ui.begin_layout(LayoutKind::Vert);
-                ui.label_with_fix_width(
-                    "TODO",
-                    if status == Status::Todo {
-                        style::HIGHLIGHT_PAIR
-                    } else {
-                        style::REGULAR_PAIR
-                    },
-                    w / 2,
-                );
-
-                for (index, todo) in todos.iter().enumerate() {
-                    ui.label_with_fix_width(
-                        &format!(" - [ ] {}", todo),
-                        if mode == Mode::Normal && todo_curr == index && status == Status::Todo {
-                            style::HIGHLIGHT_PAIR
-                        } else if mode == Mode::Visual
-                            && v_todos.contains(todo)
-                            && status == Status::Todo
-                        {
+                ui.label_with_fix_width(&view.header(), style::HIGHLIGHT_PAIR, w);
+                for line in view.visible(pager_height) {
+                    ui.label_with_fix_width(line, style::REGULAR_PAIR, w);
+                }
+                ui.end_layout();
+            } else {
+                if boards.count() > 1 {
+                    ui.begin_layout(LayoutKind::Horz);
+                    for (index, name) in boards.names().enumerate() {
+                        let tab = ui.label_with_fix_width(
+                            &format!(" {} ", name),
+                            if index == boards.current() {
+                                style::HIGHLIGHT_PAIR
+                            } else {
+                                style::REGULAR_PAIR
+                            },
+                            name.chars().count() as i32 + 2,
+                        );
+                        ui.widgets.register(Id::Board(index), tab);
+                    }
+                    ui.end_layout();
+                }
+                let held = ui.widgets.active().and_then(Id::item);
+                ui.begin_layout(LayoutKind::Horz);
+                {
+                    ui.begin_layout(LayoutKind::Vert);
+                    let header = ui.label_with_fix_width(
+                        "TODO",
+                        if status == Status::Todo {
                             style::HIGHLIGHT_PAIR
                         } else {
                             style::REGULAR_PAIR
                         },
-                        w / 2,
+                        column,
+                    );
+                    ui.widgets.register(
+                        Id::Panel(Status::Todo),
+                        Rect {
+                            pos: header.pos,
+                            size: Vec2::new(column, h),
+                        },
                     );
-                }
-                ui.end_layout();
 
-                ui.begin_layout(LayoutKind::Vert);
-                ui.label_with_fix_width(
-                    "DONE",
-                    if status == Status::Done {
-                        style::HIGHLIGHT_PAIR
-                    } else {
-                        style::REGULAR_PAIR
-                    },
-                    w / 2,
-                );
-                for (index, done) in dones.iter().enumerate() {
-                    ui.label_with_fix_width(
-                        &format!(" - [x] {}", done),
-                        if mode == Mode::Normal && done_curr == index && status == Status::Done {
-                            style::HIGHLIGHT_PAIR
-                        } else if mode == Mode::Visual
-                            && v_dones.contains(done)
-                            && status == Status::Done
-                        {
+                    let depths = tree::depths(&todos);
+                    let open = boards.open_ids(&todos);
+                    let blocked = depend::blocked_ids(&todos, &open);
+                    for index in tree::visible(&todos) {
+                        let todo = &todos[index];
+                        let folded = todo.folded && tree::has_children(&todos, index);
+                        let rect = ui.label_with_fix_width(
+                            &item_label(todo, false, depths[index], folded, &todos, &dones, &open),
+                            if (status == Status::Todo
+                                && ((mode == Mode::Normal && todo_curr == index)
+                                    || (mode == Mode::Visual && v_todos.contains(&todo.id))))
+                                || held == Some((Status::Todo, todo.id))
+                            {
+                                style::HIGHLIGHT_PAIR
+                            } else if blocked.contains(&todo.id) {
+                                style::DIM_PAIR
+                            } else {
+                                style::REGULAR_PAIR
+                            },
+                            column,
+                        );
+                        ui.widgets.register(Id::Item(Status::Todo, todo.id), rect);
+                        ui.widgets.register(
+                            Id::Checkbox(Status::Todo, todo.id),
+                            checkbox(rect, depths[index]),
+                        );
+                    }
+                    ui.end_layout();
+
+                    ui.begin_layout(LayoutKind::Vert);
+                    let header = ui.label_with_fix_width(
+                        "DONE",
+                        if status == Status::Done {
                             style::HIGHLIGHT_PAIR
                         } else {
                             style::REGULAR_PAIR
                         },
-                        w / 2,
+                        column,
+                    );
+                    ui.widgets.register(
+                        Id::Panel(Status::Done),
+                        Rect {
+                            pos: header.pos,
+                            size: Vec2::new(column, h),
+                        },
                     );
+                    let depths = tree::depths(&dones);
+                    for index in tree::visible(&dones) {
+                        let done = &dones[index];
+                        let folded = done.folded && tree::has_children(&dones, index);
+                        let mut label =
+                            item_label(done, true, depths[index], folded, &todos, &dones, &open);
+                        if let (true, Some(completed)) = (show_stamps, done.completed) {
+                            label.push_str(&format!(
+                                " (done {})",
+                                format_local(completed, utc_offset)
+                            ));
+                        }
+                        let rect = ui.label_with_fix_width(
+                            &label,
+                            if (status == Status::Done
+                                && ((mode == Mode::Normal && done_curr == index)
+                                    || (mode == Mode::Visual && v_dones.contains(&done.id))))
+                                || held == Some((Status::Done, done.id))
+                            {
+                                style::HIGHLIGHT_PAIR
+                            } else {
+                                style::REGULAR_PAIR
+                            },
+                            column,
+                        );
+                        ui.widgets.register(Id::Item(Status::Done, done.id), rect);
+                        ui.widgets.register(
+                            Id::Checkbox(Status::Done, done.id),
+                            checkbox(rect, depths[index]),
+                        );
+                    }
+                    ui.end_layout();
+
+                    if show_note {
+                        ui.begin_layout(LayoutKind::Vert);
+                        let current = match status {
+                            Status::Todo => todos.get(todo_curr),
+                            Status::Done => dones.get(done_curr),
+                        };
+                        ui.label_with_fix_width(
+                            &match current {
+                                Some(item) => format!("NOTE #{}", item.id),
+                                None => "NOTE".to_string(),
+                            },
+                            style::REGULAR_PAIR,
+                            w - 2 * column,
+                        );
+                        if let Some(item) = current {
+                            if !item.depends.is_empty() {
+                                ui.label_with_fix_width(
+                                    &format!("Depends on {}", ids(&item.depends)),
+                                    style::REGULAR_PAIR,
+                                    w - 2 * column,
+                                );
+                            }
+                            for line in item.note.lines() {
+                                let line: String =
+                                    line.chars().take((w - 2 * column) as usize).collect();
+                                ui.label_with_fix_width(&line, style::REGULAR_PAIR, w - 2 * column);
+                            }
+                        }
+                        ui.end_layout();
+                    }
                 }
                 ui.end_layout();
             }
-            ui.end_layout();
+            let mut messages = notifications.shown();
+            let (status_line, status_pair) = match (&prompt, messages.next()) {
+                (Some(prompt), _) => (prompt.message(), style::REGULAR_PAIR),
+                (None, _) if mode == Mode::Command => {
+                    (format!(":{}", command), style::REGULAR_PAIR)
+                }
+                (None, Some(message)) => (format!("{} ", message.text), message.level.pair()),
+                (None, None) if mode == Mode::Normal && pager.is_none() => {
+                    ("? help".to_string(), style::DIM_PAIR)
+                }
+                (None, None) => (String::new(), style::REGULAR_PAIR),
+            };
             ui.begin_layout(LayoutKind::Horz);
             ui.label_with_fix_width(&mode.to_string(), style::REGULAR_PAIR, w / 10);
-            ui.label_with_fix_width(
-                &format!("{} ", notification),
-                style::REGULAR_PAIR,
-                w * 9 / 10,
-            );
+            ui.label_with_fix_width(&status_line, status_pair, w * 9 / 10);
             ui.end_layout();
+            // Older messages still on screen stack up below, unless the
+            // palette is there
+            for message in messages.filter(|_| mode != Mode::Command) {
+                ui.begin_layout(LayoutKind::Horz);
+                ui.label_with_fix_width("", style::REGULAR_PAIR, w / 10);
+                ui.label_with_fix_width(&message.text, message.level.pair(), w * 9 / 10);
+                ui.end_layout();
+            }
+            if mode == Mode::Command {
+                ui.begin_layout(LayoutKind::Horz);
+                ui.label_with_fix_width("", style::REGULAR_PAIR, w / 10);
+                ui.begin_layout(LayoutKind::Vert);
+                ui.matches(&palette, PALETTE_ROWS, w * 9 / 10);
+                ui.end_layout();
+                ui.end_layout();
+            }
+            if let Some(view) = &finder {
+                let width = w * 2 / 3;
+                ui.begin_overlay(LayoutKind::Vert, Vec2::new((w - width) / 2, 1));
+                ui.label_with_matches(
+                    &format!("{} > {}", view.title, view.query),
+                    &[],
+                    style::HIGHLIGHT_PAIR,
+                    width,
+                );
+                ui.matches(view, (h - 4).max(1) as usize, width);
+                ui.end_overlay();
+            }
+            if let Some(view) = pager.as_ref().filter(|view| view.overlay) {
+                let width = w * 2 / 3;
+                ui.begin_overlay(LayoutKind::Vert, Vec2::new((w - width) / 2, 1));
+                ui.label_with_fix_width(&view.header(), style::HIGHLIGHT_PAIR, width);
+                for line in view.visible(pager_height) {
+                    ui.label_with_fix_width(line, style::REGULAR_PAIR, width);
+                }
+                ui.end_overlay();
+            }
 
             refresh();
-            let key = getch() as i32;
-            notification.clear();
-            match mode {
-                Mode::Normal => match (status, key) {
-                    (_, KEYMAP_QUIT) => ui.do_quit(),
-                    (_, KEYMAP_TAB) => status = status.toggle(),
-                    (_, KEYMAP_V) => mode = Mode::Visual,
-                    (Status::Todo, KEYMAP_SHIFT_A) => {
-                        mode = Mode::Insert;
-                        let item = todos.get_mut(todo_curr).unwrap();
-                        cursor = item.len() - 1;
-                    }
-                    (Status::Done, KEYMAP_SHIFT_A) => {
-                        mode = Mode::Insert;
-                        let item = dones.get_mut(done_curr).unwrap();
-                        cursor = item.len() - 1;
-                    }
-                    (Status::Todo, KEYMAP_SHIFT_I) => {
-                        mode = Mode::Insert;
-                        cursor = 0;
-                    }
-                    (Status::Done, KEYMAP_SHIFT_I) => {
-                        mode = Mode::Insert;
-                        cursor = 0;
-                    }
-                    (Status::Todo, KEYMAP_O) => {
-                        mode = Mode::Insert;
-                        todo_curr += 1;
-                        todos.insert(todo_curr, String::new());
-                        cursor = 0;
+            let event = events.next();
+            terminal::suspend_if_asked();
+            // Reloading or saving under a prompt or an edit would surprise
+            let idle = prompt.is_none() && matches!(mode, Mode::Normal | Mode::Visual);
+            let key = match event {
+                Event::Key(key) => {
+                    events.set(Timer::Autosave, AUTOSAVE_DELAY);
+                    key
+                }
+                Event::Timer(Timer::CheckFile) => {
+                    let (all_todos, all_dones) = boards.join(&todos, &dones);
+                    let reloaded = if idle {
+                        reload_if_changed(
+                            &mut document,
+                            &mut noticed,
+                            &all_todos,
+                            &all_dones,
+                            &mut notifications,
+                        )
+                    } else {
+                        None
+                    };
+                    if let Some((new_todos, new_dones)) = reloaded {
+                        boards.split(
+                            new_todos,
+                            new_dones,
+                            &mut todos,
+                            &mut dones,
+                            &mut todo_curr,
+                            &mut done_curr,
+                        );
                     }
-                    (Status::Todo, KEYMAP_SHIFT_O) => {
-                        mode = Mode::Insert;
-                        todos.insert(todo_curr, String::new());
-                        cursor = 0;
+                    events.set(Timer::CheckFile, RELOAD_POLL);
+                    ERR
+                }
+                Event::Timer(Timer::Autosave) if idle => {
+                    let (all_todos, all_dones) = boards.join(&todos, &dones);
+                    autosave(&mut document, &all_todos, &all_dones, &mut notifications);
+                    ERR
+                }
+                Event::Timer(Timer::Autosave) => {
+                    events.set(Timer::Autosave, AUTOSAVE_DELAY);
+                    ERR
+                }
+                Event::Mouse(mouse) if pager.is_some() => match mouse.kind {
+                    MouseKind::WheelUp => KEYMAP_K,
+                    MouseKind::WheelDown => KEYMAP_J,
+                    _ => ERR,
+                },
+                // Clicks only select in Normal mode, not in the middle of anything
+                Event::Mouse(_)
+                    if prompt.is_some()
+                        || !keys.is_empty()
+                        || finder.is_some()
+                        || mode != Mode::Normal =>
+                {
+                    ERR
+                }
+                Event::Mouse(mouse) => {
+                    events.set(Timer::Autosave, AUTOSAVE_DELAY);
+                    let interaction = ui.widgets.input(mouse, Instant::now());
+                    match interaction {
+                        None => ERR,
+                        Some(Interaction::Scroll { up }) => {
+                            if let Some(panel) = ui.widgets.hot().and_then(Id::status) {
+                                status = panel;
+                            }
+                            if up {
+                                KEYMAP_K
+                            } else {
+                                KEYMAP_J
+                            }
+                        }
+                        Some(Interaction::Drag { from, to }) => {
+                            // Only onto another item of the same list
+                            if let (Some((list, from)), Some((to_list, to))) =
+                                (from.item(), to.and_then(Id::item))
+                            {
+                                let (items, curr) = match list {
+                                    Status::Todo => (&mut todos, &mut todo_curr),
+                                    Status::Done => (&mut dones, &mut done_curr),
+                                };
+                                let from = items.iter().position(|item| item.id == from);
+                                let to = items.iter().position(|item| item.id == to);
+                                if let (true, Some(from), Some(to)) = (list == to_list, from, to) {
+                                    status = list;
+                                    *curr = from;
+                                    drag_to(items, curr, to);
+                                }
+                            }
+                            ERR
+                        }
+                        Some(
+                            Interaction::Click(Id::Board(index))
+                            | Interaction::DoubleClick(Id::Board(index)),
+                        ) => {
+                            boards.switch(
+                                index,
+                                &mut todos,
+                                &mut dones,
+                                &mut todo_curr,
+                                &mut done_curr,
+                                &mut status,
+                            );
+                            ERR
+                        }
+                        Some(Interaction::Click(widget) | Interaction::DoubleClick(widget)) => {
+                            if let Some(panel) = widget.status() {
+                                status = panel;
+                            }
+                            let (items, curr) = match status {
+                                Status::Todo => (&todos, &mut todo_curr),
+                                Status::Done => (&dones, &mut done_curr),
+                            };
+                            let index = widget
+                                .item()
+                                .and_then(|(_, id)| items.iter().position(|item| item.id == id));
+                            match index {
+                                Some(index) => {
+                                    *curr = index;
+                                    let double =
+                                        matches!(interaction, Some(Interaction::DoubleClick(_)));
+                                    // Completes or reopens like the key would
+                                    if double || matches!(widget, Id::Checkbox(..)) {
+                                        KEYMAP_NEWLINE
+                                    } else {
+                                        ERR
+                                    }
+                                }
+                                None => ERR,
+                            }
+                        }
                     }
-                    (Status::Todo, KEYMAP_J) => go(Direction::Down, todos.len(), &mut todo_curr),
-                    (Status::Done, KEYMAP_J) => go(Direction::Down, dones.len(), &mut done_curr),
-                    (Status::Todo, KEYMAP_SHIFT_J) => {
-                        drag(Direction::Down, &mut todos, &mut todo_curr)
+                }
+                Event::Resize | Event::Timer(Timer::Notification) | Event::Tick | Event::Signal => {
+                    ERR
+                }
+            };
+            if key == ERR {
+                // Nothing more to do, the screen is redrawn for the event
+            } else if let Some(question) = prompt.take() {
+                match (question, lookup(&Mode::Prompt, &[key])) {
+                    (Prompt::CompleteBlocked { items, .. }, Lookup::Action(Action::Confirm)) => {
+                        prompt = completion_prompt(&todos, &boards, &items, true);
+                        if prompt.is_none() {
+                            complete_items(
+                                &items,
+                                &mut todos,
+                                &mut dones,
+                                &mut todo_curr,
+                                &boards,
+                                &clock,
+                                &mut notifications,
+                            );
+                            v_todos.clear();
+                        }
                     }
-                    (Status::Done, KEYMAP_SHIFT_J) => {
-                        drag(Direction::Down, &mut dones, &mut done_curr)
+                    (Prompt::CompleteSubtree { items, .. }, Lookup::Action(Action::Confirm)) => {
+                        complete_items(
+                            &items,
+                            &mut todos,
+                            &mut dones,
+                            &mut todo_curr,
+                            &boards,
+                            &clock,
+                            &mut notifications,
+                        );
+                        v_todos.clear();
                     }
-                    (Status::Todo, KEYMAP_G) => go(Direction::First, todos.len(), &mut todo_curr),
-                    (Status::Done, KEYMAP_G) => go(Direction::First, dones.len(), &mut done_curr),
-                    (Status::Todo, KEYMAP_SHIFT_G) => {
-                        go(Direction::Last, todos.len(), &mut todo_curr)
+                    (
+                        Prompt::Recover {
+                            todos: left,
+                            dones: left_dones,
+                        },
+                        Lookup::Action(Action::Confirm),
+                    ) => {
+                        boards.split(
+                            left,
+                            left_dones,
+                            &mut todos,
+                            &mut dones,
+                            &mut todo_curr,
+                            &mut done_curr,
+                        );
+                        notifications.info("Recovered unsaved changes");
                     }
-                    (Status::Done, KEYMAP_SHIFT_G) => {
-                        go(Direction::Last, dones.len(), &mut done_curr)
+                    (Prompt::DeleteBoard { name, .. }, Lookup::Action(Action::Confirm)) => {
+                        match boards.delete(
+                            &mut todos,
+                            &mut dones,
+                            &mut todo_curr,
+                            &mut done_curr,
+                            &mut status,
+                        ) {
+                            Ok(()) => notifications.info(format!("Deleted board {}", name)),
+                            Err(err) => notifications.error(err),
+                        }
                     }
-                    (Status::Todo, KEYMAP_K) => go(Direction::Up, todos.len(), &mut todo_curr),
-                    (Status::Done, KEYMAP_K) => go(Direction::Up, dones.len(), &mut done_curr),
-                    (Status::Todo, KEYMAP_SHIFT_K) => {
-                        drag(Direction::Up, &mut todos, &mut todo_curr)
+                    (Prompt::Changed { quit }, lookup) => {
+                        let (mut all_todos, mut all_dones) = boards.join(&todos, &dones);
+                        if resolve_change(
+                            lookup,
+                            quit,
+                            &mut document,
+                            &mut all_todos,
+                            &mut all_dones,
+                            &mut notifications,
+                        ) {
+                            ui.do_quit();
+                        }
+                        boards.split(
+                            all_todos,
+                            all_dones,
+                            &mut todos,
+                            &mut dones,
+                            &mut todo_curr,
+                            &mut done_curr,
+                        );
                     }
-                    (Status::Done, KEYMAP_SHIFT_K) => {
-                        drag(Direction::Up, &mut dones, &mut done_curr)
+                    (_, _) => notifications.info("Cancelled"),
+                }
+            } else if let Some(view) = &mut finder {
+                match (lookup(&Mode::Finder, &[key]), key) {
+                    (Lookup::Action(Action::Cancel), _) => finder = None,
+                    (Lookup::Action(Action::Run), _) => {
+                        if let Some(Target::Item(list, id)) = view.chosen() {
+                            if let Some(board) = boards.find(id) {
+                                boards.switch(
+                                    board,
+                                    &mut todos,
+                                    &mut dones,
+                                    &mut todo_curr,
+                                    &mut done_curr,
+                                    &mut status,
+                                );
+                            }
+                            status = list;
+                            let (items, curr) = match list {
+                                Status::Todo => (&mut todos, &mut todo_curr),
+                                Status::Done => (&mut dones, &mut done_curr),
+                            };
+                            if let Some(index) = tree::position(items, id) {
+                                tree::reveal(items, index);
+                                *curr = index;
+                            }
+                        }
+                        finder = None;
                     }
-                    (Status::Todo, KEYMAP_NEWLINE) => {
-                        transfer(&mut dones, &mut todos, &mut todo_curr)
+                    (Lookup::Action(Action::ChooseUp), _) => view.go(Direction::Up),
+                    (Lookup::Action(Action::ChooseDown), _) => view.go(Direction::Down),
+                    (Lookup::Action(Action::DeleteLast), _) => {
+                        let mut query = view.query.clone();
+                        query.pop();
+                        view.search(&query);
                     }
-                    (Status::Done, KEYMAP_NEWLINE) => {
-                        transfer(&mut todos, &mut dones, &mut done_curr)
+                    (Lookup::Unbound, 32..=126) => {
+                        let query = format!("{}{}", view.query, key as u8 as char);
+                        view.search(&query);
                     }
-                    (Status::Done, KEYMAP_D) => {
-                        delete(&mut dones, &mut done_curr);
-                        notification.push_str("Item moved to TODO");
+                    _ => {}
+                }
+            } else if let Some(view) = pager.as_mut().filter(|view| view.searching) {
+                match (lookup(&Mode::Search, &[key]), key) {
+                    (Lookup::Action(Action::Cancel), _) => {
+                        view.search("");
+                        view.searching = false;
                     }
-                    (_, _) => {}
-                },
-                Mode::Visual => match (status, key) {
-                    (_, KEYMAP_QUIT) => ui.do_quit(),
-                    (_, KEYMAP_TAB) => {
-                        status = status.toggle();
-                        mode = Mode::Normal;
+                    (Lookup::Action(Action::Leave), _) => view.searching = false,
+                    (Lookup::Action(Action::DeleteLast), _) => {
+                        let mut query = view.query.clone();
+                        query.pop();
+                        view.search(&query);
                     }
-                    (_, KEYMAP_V) => {
-                        mode = Mode::Normal;
-                        v_todos.clear();
-                        v_dones.clear();
+                    (Lookup::Unbound, 32..=126) => {
+                        let query = format!("{}{}", view.query, key as u8 as char);
+                        view.search(&query);
                     }
-                    (Status::Todo, KEYMAP_J) => {
-                        if let Some(item) = todos.get(todo_curr) {
-                            if !v_todos.remove(item) {
-                                v_todos.insert(item.clone());
+                    _ => {}
+                }
+            } else if let Some(view) = &mut pager {
+                match lookup(&Mode::Pager, &[key]) {
+                    Lookup::Action(Action::Search) => view.searching = true,
+                    Lookup::Action(Action::Down) => view.scroll(Direction::Down, pager_height),
+                    Lookup::Action(Action::Up) => view.scroll(Direction::Up, pager_height),
+                    Lookup::Action(Action::First) => view.scroll(Direction::First, pager_height),
+                    Lookup::Action(Action::Last) => view.scroll(Direction::Last, pager_height),
+                    Lookup::Action(Action::Close) => pager = None,
+                    _ => {}
+                }
+            } else {
+                keys.push(key);
+                let lookup = lookup(&mode, &keys);
+                if lookup != Lookup::Prefix {
+                    keys.clear();
+                }
+                match (&mode, lookup) {
+                    (Mode::Normal, Lookup::Action(action)) => match (status, action) {
+                        (_, Action::Quit) => prompt = quit_or_ask(&mut ui, &document),
+                        (_, Action::SwitchList) => status = status.toggle(),
+                        (_, Action::Dismiss) => notifications.dismiss(),
+                        (_, Action::Help) => pager = Some(help_pager(&mode)),
+                        (_, Action::Select) => mode = Mode::Visual,
+                        (_, Action::ToggleFold | Action::Unfold | Action::Fold) => {
+                            let (list, curr) = match status {
+                                Status::Todo => (&mut todos, &mut todo_curr),
+                                Status::Done => (&mut dones, &mut done_curr),
+                            };
+                            let folded = match action {
+                                Action::Unfold => Some(false),
+                                Action::Fold => Some(true),
+                                _ => None,
+                            };
+                            tree::fold(list, curr, folded)
+                        }
+                        (_, Action::ToggleNote) => show_note = !show_note,
+                        (_, Action::ToggleStamps) => show_stamps = !show_stamps,
+                        (_, Action::Command) => {
+                            mode = Mode::Command;
+                            command.clear();
+                            palette.search("");
+                        }
+                        (_, Action::NextBoard) => boards.switch(
+                            (boards.current() + 1) % boards.count(),
+                            &mut todos,
+                            &mut dones,
+                            &mut todo_curr,
+                            &mut done_curr,
+                            &mut status,
+                        ),
+                        (_, Action::PreviousBoard) => boards.switch(
+                            (boards.current() + boards.count() - 1) % boards.count(),
+                            &mut todos,
+                            &mut dones,
+                            &mut todo_curr,
+                            &mut done_curr,
+                            &mut status,
+                        ),
+                        (_, Action::Find) => {
+                            let (all_todos, all_dones) = boards.join(&todos, &dones);
+                            let entries = finder::items(&all_todos, &all_dones, boards.count() > 1);
+                            finder = Some(Finder::new("FIND", entries));
+                        }
+                        (_, Action::EditNote) => {
+                            if let Some(item) =
+                                current_item(status, &mut todos, &mut dones, todo_curr, done_curr)
+                            {
+                                match editor::edit(&item.id.to_string(), &item.note) {
+                                    Ok(note) => {
+                                        item.note = note;
+                                        show_note = true;
+                                    }
+                                    Err(err) => {
+                                        notifications.error(format!("Could not edit note: {}", err))
+                                    }
+                                }
                             }
-                            go(Direction::Down, todos.len(), &mut todo_curr);
                         }
-                    }
-                    (Status::Done, KEYMAP_J) => {
-                        if let Some(item) = dones.get(done_curr) {
-                            if !v_dones.remove(item) {
-                                v_dones.insert(item.clone());
+                        (_, Action::EditFromEnd) => {
+                            if let Some(item) =
+                                current_item(status, &mut todos, &mut dones, todo_curr, done_curr)
+                            {
+                                mode = Mode::Insert;
+                                cursor = item.title.chars().count();
                             }
-                            go(Direction::Down, dones.len(), &mut done_curr);
                         }
-                    }
-                    (Status::Todo, KEYMAP_K) => {
-                        if let Some(item) = todos.get(todo_curr) {
-                            if !v_todos.remove(item) {
-                                v_todos.insert(item.clone());
+                        (_, Action::EditFromStart) => {
+                            if current_item(status, &mut todos, &mut dones, todo_curr, done_curr)
+                                .is_some()
+                            {
+                                mode = Mode::Insert;
+                                cursor = 0;
                             }
-                            go(Direction::Up, todos.len(), &mut todo_curr);
                         }
-                    }
-                    (Status::Done, KEYMAP_K) => {
-                        if let Some(item) = dones.get(done_curr) {
-                            if !v_dones.remove(item) {
-                                v_dones.insert(item.clone());
+                        (Status::Todo, Action::AddAfter) => {
+                            mode = Mode::Insert;
+                            let mut item = Item::new(next_id(&todos, &dones), "");
+                            item.created = Some(clock.now());
+                            if todo_curr < todos.len() {
+                                item.parent = todos[todo_curr].parent;
+                                todo_curr = tree::subtree_end(&todos, todo_curr);
                             }
-                            go(Direction::Up, dones.len(), &mut done_curr);
+                            todos.insert(todo_curr, item);
+                            cursor = 0;
                         }
-                    }
-                    (Status::Todo, KEYMAP_NEWLINE) => {
-                        if !v_todos.is_empty() {
-                            todos = todos.into_iter().filter(|t| !v_todos.contains(t)).collect();
-                            for t in v_todos.iter() {
-                                dones.push(t.to_string());
+                        (Status::Todo, Action::AddBefore) => {
+                            mode = Mode::Insert;
+                            let mut item = Item::new(next_id(&todos, &dones), "");
+                            item.created = Some(clock.now());
+                            if let Some(todo) = todos.get(todo_curr) {
+                                item.parent = todo.parent;
                             }
-                            v_todos.clear();
+                            todos.insert(todo_curr, item);
+                            cursor = 0;
                         }
-                    }
-                    (Status::Done, KEYMAP_NEWLINE) => {
-                        if !v_dones.is_empty() {
-                            dones = dones.into_iter().filter(|t| !v_dones.contains(t)).collect();
-                            for t in v_dones.iter() {
-                                todos.push(t.to_string());
+                        (Status::Todo, Action::Indent) => tree::indent(&mut todos, todo_curr),
+                        (Status::Done, Action::Indent) => tree::indent(&mut dones, done_curr),
+                        (Status::Todo, Action::Outdent) => {
+                            tree::outdent(&mut todos, &mut todo_curr)
+                        }
+                        (Status::Done, Action::Outdent) => {
+                            tree::outdent(&mut dones, &mut done_curr)
+                        }
+                        (Status::Todo, Action::Down) => {
+                            go_visible(Direction::Down, &todos, &mut todo_curr)
+                        }
+                        (Status::Done, Action::Down) => {
+                            go_visible(Direction::Down, &dones, &mut done_curr)
+                        }
+                        (Status::Todo, Action::DragDown) => {
+                            drag(Direction::Down, &mut todos, &mut todo_curr)
+                        }
+                        (Status::Done, Action::DragDown) => {
+                            drag(Direction::Down, &mut dones, &mut done_curr)
+                        }
+                        (Status::Todo, Action::First) => {
+                            go_visible(Direction::First, &todos, &mut todo_curr)
+                        }
+                        (Status::Done, Action::First) => {
+                            go_visible(Direction::First, &dones, &mut done_curr)
+                        }
+                        (Status::Todo, Action::Last) => {
+                            go_visible(Direction::Last, &todos, &mut todo_curr)
+                        }
+                        (Status::Done, Action::Last) => {
+                            go_visible(Direction::Last, &dones, &mut done_curr)
+                        }
+                        (Status::Todo, Action::Up) => {
+                            go_visible(Direction::Up, &todos, &mut todo_curr)
+                        }
+                        (Status::Done, Action::Up) => {
+                            go_visible(Direction::Up, &dones, &mut done_curr)
+                        }
+                        (Status::Todo, Action::DragUp) => {
+                            drag(Direction::Up, &mut todos, &mut todo_curr)
+                        }
+                        (Status::Done, Action::DragUp) => {
+                            drag(Direction::Up, &mut dones, &mut done_curr)
+                        }
+                        (Status::Todo, Action::Move) => {
+                            if let Some(item) = todos.get(todo_curr) {
+                                let ids = [item.id];
+                                prompt = completion_prompt(&todos, &boards, &ids, false);
+                                if prompt.is_none() {
+                                    complete_items(
+                                        &ids,
+                                        &mut todos,
+                                        &mut dones,
+                                        &mut todo_curr,
+                                        &boards,
+                                        &clock,
+                                        &mut notifications,
+                                    );
+                                }
                             }
-                            v_dones.clear();
                         }
-                    }
-                    (Status::Done, KEYMAP_D) => {
-                        if !v_dones.is_empty() {
-                            dones = dones.into_iter().filter(|t| !v_dones.contains(t)).collect();
+                        (Status::Done, Action::Move) => {
+                            reopen(&mut todos, &mut dones, &mut done_curr)
+                        }
+                        (Status::Done, Action::Delete) => {
+                            delete(&mut dones, &mut done_curr);
+                            notifications.info("Item moved to TODO");
+                        }
+                        (_, _) => {}
+                    },
+                    (Mode::Visual, Lookup::Action(action)) => match (status, action) {
+                        (_, Action::Quit) => prompt = quit_or_ask(&mut ui, &document),
+                        (_, Action::Help) => pager = Some(help_pager(&mode)),
+                        (_, Action::SwitchList) => {
+                            status = status.toggle();
+                            mode = Mode::Normal;
+                        }
+                        (_, Action::Unselect) => {
+                            mode = Mode::Normal;
+                            v_todos.clear();
                             v_dones.clear();
                         }
-                    }
-                    (_, _) => {}
-                },
-                Mode::Insert => match (status, key) {
-                    (_, KEYMAP_ESC) => mode = Mode::Normal,
-                    (_, KEYMAP_NEWLINE) => {
-                        mode = Mode::Normal;
-                        cursor = 0;
-                    }
-                    (_, KEYMAP_LEFT) => {
-                        if cursor > 0 {
-                            cursor -= 1;
+                        (Status::Todo, Action::Down) => {
+                            if let Some(item) = todos.get(todo_curr) {
+                                if !v_todos.remove(&item.id) {
+                                    v_todos.insert(item.id);
+                                }
+                                go_visible(Direction::Down, &todos, &mut todo_curr);
+                            }
                         }
-                    }
-                    (Status::Done, KEYMAP_DELETE) => {
-                        todos.push(format!("key:{}", key));
-                        let item = dones.get_mut(done_curr).unwrap();
-                        if cursor < item.len() {
-                            item.remove(cursor);
+                        (Status::Done, Action::Down) => {
+                            if let Some(item) = dones.get(done_curr) {
+                                if !v_dones.remove(&item.id) {
+                                    v_dones.insert(item.id);
+                                }
+                                go_visible(Direction::Down, &dones, &mut done_curr);
+                            }
                         }
-                    }
-                    (Status::Done, KEYMAP_BACKSPACE) => {
-                        if cursor > 0 {
-                            let item = dones.get_mut(done_curr).unwrap();
-                            item.remove(cursor);
-                            cursor -= 1;
+                        (Status::Todo, Action::Up) => {
+                            if let Some(item) = todos.get(todo_curr) {
+                                if !v_todos.remove(&item.id) {
+                                    v_todos.insert(item.id);
+                                }
+                                go_visible(Direction::Up, &todos, &mut todo_curr);
+                            }
                         }
-                    }
-                    (Status::Done, key) => {
-                        let item = dones.get_mut(done_curr).unwrap();
-                        match key {
-                            32..=126 => {
-                                item.insert(cursor, char::from_u32(key as u32).unwrap());
-                                cursor += 1;
+                        (Status::Done, Action::Up) => {
+                            if let Some(item) = dones.get(done_curr) {
+                                if !v_dones.remove(&item.id) {
+                                    v_dones.insert(item.id);
+                                }
+                                go_visible(Direction::Up, &dones, &mut done_curr);
                             }
-                            _ => {}
                         }
-                    }
-                    (Status::Todo, KEYMAP_DELETE) => {
-                        let item = todos.get_mut(todo_curr).unwrap();
-                        if cursor < item.len() {
-                            item.remove(cursor);
+                        (Status::Todo, Action::Move) => {
+                            if !v_todos.is_empty() {
+                                let ids: Vec<u64> = todos
+                                    .iter()
+                                    .map(|item| item.id)
+                                    .filter(|id| v_todos.contains(id))
+                                    .collect();
+                                prompt = completion_prompt(&todos, &boards, &ids, false);
+                                if prompt.is_none() {
+                                    complete_items(
+                                        &ids,
+                                        &mut todos,
+                                        &mut dones,
+                                        &mut todo_curr,
+                                        &boards,
+                                        &clock,
+                                        &mut notifications,
+                                    );
+                                    v_todos.clear();
+                                }
+                            }
                         }
-                    }
-                    (Status::Todo, KEYMAP_BACKSPACE) => {
-                        if cursor > 0 {
-                            let item = todos.get_mut(todo_curr).unwrap();
-                            item.remove(cursor);
-                            cursor -= 1;
+                        (Status::Done, Action::Move) => {
+                            if !v_dones.is_empty() {
+                                let (selected, rest): (Vec<Item>, Vec<Item>) =
+                                    dones.drain(..).partition(|t| v_dones.contains(&t.id));
+                                dones = rest;
+                                todos.extend(selected.into_iter().map(|mut t| {
+                                    t.reopen();
+                                    t
+                                }));
+                                tree::normalize(&mut todos);
+                                tree::normalize(&mut dones);
+                                tree::snap(&dones, &mut done_curr);
+                                v_dones.clear();
+                            }
                         }
-                    }
-                    (Status::Todo, KEYMAP_RIGHT) => {
-                        let item = todos.get_mut(todo_curr).unwrap();
-                        if cursor < item.len() {
-                            cursor += 1;
+                        (Status::Done, Action::Delete) => {
+                            if !v_dones.is_empty() {
+                                dones.retain(|t| !v_dones.contains(&t.id));
+                                tree::normalize(&mut dones);
+                                tree::snap(&dones, &mut done_curr);
+                                v_dones.clear();
+                            }
                         }
-                    }
-                    (Status::Todo, key) => {
-                        let item = todos.get_mut(todo_curr).unwrap();
-                        match key {
-                            32..=126 => {
-                                item.insert(cursor, char::from_u32(key as u32).unwrap());
-                                cursor += 1;
+                        (_, _) => {}
+                    },
+                    (Mode::Command, Lookup::Action(action)) => match action {
+                        Action::Cancel => mode = Mode::Normal,
+                        Action::DeleteLast if command.is_empty() => mode = Mode::Normal,
+                        Action::DeleteLast => {
+                            command.pop();
+                            palette.search(&command);
+                        }
+                        Action::ChooseUp => palette.go(Direction::Up),
+                        Action::ChooseDown => palette.go(Direction::Down),
+                        // Not a command, so the palette picks
+                        Action::Run if !is_known(&command) && palette.chosen().is_some() => {
+                            match palette.chosen() {
+                                Some(Target::Keys(keys)) => {
+                                    mode = Mode::Normal;
+                                    events.feed(keys);
+                                }
+                                Some(Target::Command(usage)) => match usage.split_once(' ') {
+                                    // Arguments are left to type
+                                    Some((name, _)) => {
+                                        command = format!("{} ", name);
+                                        palette.search(&command);
+                                    }
+                                    None => {
+                                        command = usage.to_string();
+                                        events.feed(&[KEYMAP_NEWLINE]);
+                                    }
+                                },
+                                _ => {}
+                            }
+                        }
+                        Action::Run => {
+                            mode = Mode::Normal;
+                            let item =
+                                current_item(status, &mut todos, &mut dones, todo_curr, done_curr);
+                            match (Command::parse(&command, clock.today()), item) {
+                                (Ok(Command::Write), _) => {
+                                    let (all_todos, all_dones) = boards.join(&todos, &dones);
+                                    match document.save(&all_todos, &all_dones) {
+                                        Ok(true) => {
+                                            notifications.info(format!("Saved {}", file_path))
+                                        }
+                                        Ok(false) => prompt = Some(Prompt::Changed { quit: false }),
+                                        Err(err) => {
+                                            notifications.error(format!("Could not save: {}", err))
+                                        }
+                                    }
+                                }
+                                (Ok(Command::Quit), _) => prompt = quit_or_ask(&mut ui, &document),
+                                (Ok(Command::Export { path, columns }), _) => {
+                                    let (all_todos, all_dones) = boards.join(&todos, &dones);
+                                    match format::export(
+                                        &path,
+                                        None,
+                                        columns.as_deref(),
+                                        &all_todos,
+                                        &all_dones,
+                                    ) {
+                                        Ok(()) => {
+                                            notifications.info(format!("Exported to {}", path))
+                                        }
+                                        Err(err) => notifications
+                                            .error(format!("Could not export: {}", err)),
+                                    }
+                                }
+                                (Ok(Command::Import(path)), _) => {
+                                    match format::load(&path, Format::from_path(&path)) {
+                                        Ok((new_todos, new_dones)) => {
+                                            let count = append_renumbered(
+                                                &mut todos, &mut dones, new_todos, new_dones,
+                                            );
+                                            tree::normalize(&mut todos);
+                                            tree::normalize(&mut dones);
+                                            notifications
+                                                .info(format!("Imported {} item(s)", count))
+                                        }
+                                        Err(err) => notifications
+                                            .error(format!("Could not import: {}", err)),
+                                    }
+                                }
+                                (Ok(Command::Archive { days }), _) => {
+                                    let cutoff = days.map(|days| clock.now() - days as i64 * 86400);
+                                    match archive_dones(
+                                        &file_path,
+                                        file_format,
+                                        &mut todos,
+                                        &mut dones,
+                                        cutoff,
+                                    ) {
+                                        Ok(count) => {
+                                            tree::snap(&dones, &mut done_curr);
+                                            notifications
+                                                .info(format!("Archived {} item(s)", count))
+                                        }
+                                        Err(err) => notifications
+                                            .error(format!("Could not archive: {}", err)),
+                                    }
+                                }
+                                (Ok(Command::Archived), _) => {
+                                    match format::load_archive(&file_path, file_format) {
+                                        Ok(archived) => {
+                                            pager = Some(Pager::new(
+                                                "ARCHIVE",
+                                                archive::lines(&archived, utc_offset),
+                                            ))
+                                        }
+                                        Err(err) => notifications
+                                            .error(format!("Could not load archive: {}", err)),
+                                    }
+                                }
+                                (Ok(Command::Help), _) => pager = Some(help_pager(&mode)),
+                                (Ok(Command::Messages), _) => {
+                                    pager = Some(Pager::new(
+                                        "MESSAGES",
+                                        notifications.lines(utc_offset),
+                                    ))
+                                }
+                                (Ok(Command::Log { by_week }), _) => {
+                                    let (all_todos, all_dones) = boards.join(&todos, &dones);
+                                    pager = Some(Pager::new(
+                                        if by_week {
+                                            "LOG (by week)"
+                                        } else {
+                                            "LOG (by day)"
+                                        },
+                                        log::lines(&all_todos, &all_dones, by_week, utc_offset),
+                                    ))
+                                }
+                                (Ok(Command::Due(due)), Some(item)) => item.due = due,
+                                (Ok(Command::Recur(recurrence)), Some(item)) => {
+                                    item.recurrence = recurrence
+                                }
+                                (Ok(Command::Depend(id)), Some(item)) => {
+                                    // The other item may be on another board
+                                    let from = item.id;
+                                    let (mut all_todos, mut all_dones) =
+                                        boards.join(&todos, &dones);
+                                    match depend::add(from, id, &mut all_todos, &mut all_dones) {
+                                        Ok(()) => boards.split(
+                                            all_todos,
+                                            all_dones,
+                                            &mut todos,
+                                            &mut dones,
+                                            &mut todo_curr,
+                                            &mut done_curr,
+                                        ),
+                                        Err(err) => notifications.error(err),
+                                    }
+                                }
+                                (Ok(Command::Priority(priority)), Some(item)) => {
+                                    item.priority = priority
+                                }
+                                (Ok(Command::Tag(tag)), Some(item)) => {
+                                    let list = match tag.strip_prefix('+') {
+                                        Some(_) => &mut item.projects,
+                                        None => &mut item.tags,
+                                    };
+                                    let name = tag.trim_start_matches(['+', '@']).to_string();
+                                    if !list.contains(&name) {
+                                        list.push(name);
+                                    }
+                                }
+                                (Ok(Command::Untag(tag)), Some(item)) => {
+                                    let name = tag.trim_start_matches(['+', '@']);
+                                    item.projects.retain(|project| project != name);
+                                    item.tags.retain(|t| t != name);
+                                }
+                                (Ok(Command::Undepend(id)), Some(item)) => {
+                                    item.depends.retain(|dep| *dep != id)
+                                }
+                                (Ok(Command::Board(name)), _) => {
+                                    if boards.position(&name).is_none() {
+                                        // Boards only live in the `board` extension of items
+                                        notifications.info(format!(
+                                            "Created board {}, it is saved once it has items",
+                                            name
+                                        ));
+                                    }
+                                    let index = boards.create(&name);
+                                    boards.switch(
+                                        index,
+                                        &mut todos,
+                                        &mut dones,
+                                        &mut todo_curr,
+                                        &mut done_curr,
+                                        &mut status,
+                                    );
+                                }
+                                (Ok(Command::RenameBoard(name)), _) => match boards.rename(&name) {
+                                    Ok(()) => {
+                                        notifications.info(format!("Renamed board to {}", name))
+                                    }
+                                    Err(err) => notifications.error(err),
+                                },
+                                (Ok(Command::DeleteBoard), _) => {
+                                    let items = todos.len() + dones.len();
+                                    if boards.count() == 1 {
+                                        notifications.error("The only board can't be deleted");
+                                    } else if items > 0 {
+                                        prompt = Some(Prompt::DeleteBoard {
+                                            name: boards.current_name().to_string(),
+                                            items,
+                                        });
+                                    } else {
+                                        let name = boards.current_name().to_string();
+                                        if boards
+                                            .delete(
+                                                &mut todos,
+                                                &mut dones,
+                                                &mut todo_curr,
+                                                &mut done_curr,
+                                                &mut status,
+                                            )
+                                            .is_ok()
+                                        {
+                                            notifications.info(format!("Deleted board {}", name));
+                                        }
+                                    }
+                                }
+                                (Ok(Command::Move(name)), Some(_)) => {
+                                    if name == boards.current_name() {
+                                        notifications
+                                            .warn(format!("Item is on board {} already", name));
+                                    } else {
+                                        let to = boards.create(&name);
+                                        let moved = match status {
+                                            Status::Todo => {
+                                                take_subtree(&mut todos, &mut todo_curr)
+                                            }
+                                            Status::Done => {
+                                                take_subtree(&mut dones, &mut done_curr)
+                                            }
+                                        };
+                                        notifications.info(format!(
+                                            "Moved {} item(s) to board {}",
+                                            moved.len(),
+                                            name
+                                        ));
+                                        boards.move_to(to, status, moved);
+                                    }
+                                }
+                                (Ok(_), None) => notifications.warn("No item selected"),
+                                (Err(err), _) => notifications.error(err),
                             }
-                            _ => {}
+                        }
+                        _ => {}
+                    },
+                    (Mode::Command, Lookup::Unbound) if (32..=126).contains(&key) => {
+                        command.push(char::from_u32(key as u32).unwrap());
+                        palette.search(&command);
+                    }
+                    (Mode::Insert, Lookup::Action(Action::Leave)) => {
+                        mode = Mode::Normal;
+                        cursor = 0;
+                    }
+                    (Mode::Insert, lookup) => {
+                        match current_item(status, &mut todos, &mut dones, todo_curr, done_curr) {
+                            Some(item) => edit_title(&mut item.title, &mut cursor, lookup, key),
+                            None => mode = Mode::Normal,
                         }
                     }
-                },
+                    (_, _) => {}
+                }
+            }
+            match notifications.expire(Instant::now()) {
+                Some(expiry) => events.set(
+                    Timer::Notification,
+                    expiry.saturating_duration_since(Instant::now()),
+                ),
+                None => events.cancel(Timer::Notification),
+            }
+            boards.sync(&mut todos, &mut dones);
+            if let Some(journal) = journal.as_mut() {
+                let (all_todos, all_dones) = boards.join(&todos, &dones);
+                let journaled = if document.unsaved(&all_todos, &all_dones) {
+                    journal.record(&all_todos, &all_dones)
+                } else {
+                    journal.clear()
+                };
+                if let Err(err) = journaled {
+                    notifications.error(format!("Could not write journal: {}", err));
+                }
             }
         }
         ui.end();
     }
     endwin();
+
+    let (todos, dones) = boards.join(&todos, &dones);
+    let saved = match document.save(&todos, &dones) {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            // Changed again after quitting, nobody is left to ask
+            eprintln!("WARNING: {} changed on disk, merging", file_path);
+            document.merge(&todos, &dones).and_then(|merged| {
+                if !merged.conflicts.is_empty() {
+                    let conflicts: Vec<u64> = merged.conflicts.iter().map(|c| c.id()).collect();
+                    eprintln!("WARNING: both sides changed {}, kept ours", ids(&conflicts));
+                }
+                document.overwrite(&merged.todos, &merged.dones)
+            })
+        }
+        Err(err) => Err(err),
+    };
+    if let Err(err) = saved {
+        eprintln!("ERROR: could not save {}: {}", file_path, err);
+        process::exit(1);
+    }
+    if let Some(Err(err)) = journal.map(Journal::close) {
+        eprintln!("WARNING: could not remove journal: {}", err);
+    }
 }
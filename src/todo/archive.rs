@@ -0,0 +1,47 @@
+use super::date::format_local;
+use super::item::Item;
+use super::tree;
+use std::collections::HashSet;
+
+/// DONE items completed before `cutoff`, or all of them without a cutoff.
+/// Items without a completion stamp are only archived without a cutoff.
+pub fn select(dones: &[Item], cutoff: Option<i64>) -> Vec<Item> {
+    dones
+        .iter()
+        .filter(|item| match cutoff {
+            Some(cutoff) => item.completed.is_some_and(|completed| completed < cutoff),
+            None => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Drops archived items from DONE. Dependencies on them are dropped as well,
+/// they are done for good and their ids may get reused.
+pub fn remove(todos: &mut [Item], dones: &mut Vec<Item>, archived: &[Item]) {
+    let ids: HashSet<u64> = archived.iter().map(|item| item.id).collect();
+    dones.retain(|item| !ids.contains(&item.id));
+    tree::normalize(dones);
+    for item in todos.iter_mut().chain(dones.iter_mut()) {
+        item.depends.retain(|id| !ids.contains(id));
+    }
+}
+
+/// Lines of the archive browser, most recently archived first.
+pub fn lines(archived: &[Item], utc_offset: i64) -> Vec<String> {
+    let mut lines: Vec<String> = archived
+        .iter()
+        .rev()
+        .map(|item| {
+            let completed = match item.completed {
+                Some(completed) => format_local(completed, utc_offset),
+                None => format!("{:16}", ""),
+            };
+            format!("{}  {}", completed, item.title)
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push("Archive is empty".to_string());
+    }
+    lines
+}
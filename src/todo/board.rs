@@ -0,0 +1,46 @@
+use super::item::Item;
+
+// Boards are separate sets of lists kept in the same file. An item's board is
+// kept in its `board` extension, like the `uuid` of Taskwarrior. Every format
+// we save to writes it back, iCalendar as `X-TODORS-BOARD`. Items without one
+// are on the default board, so files written before boards existed stay
+// unchanged.
+
+pub const DEFAULT: &str = "main";
+const KEY: &str = "board";
+
+pub fn name(item: &Item) -> &str {
+    item.extensions
+        .iter()
+        .find(|(key, _)| key == KEY)
+        .map_or(DEFAULT, |(_, value)| value.as_str())
+}
+
+pub fn assign(item: &mut Item, name: &str) {
+    item.extensions.retain(|(key, _)| key != KEY);
+    if name != DEFAULT {
+        item.extensions.push((KEY.to_string(), name.to_string()));
+    }
+}
+
+/// Names of the boards items are on, in the order they first appear.
+pub fn names(todos: &[Item], dones: &[Item]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for item in todos.iter().chain(dones) {
+        if !names.iter().any(|other| other == name(item)) {
+            names.push(name(item).to_string());
+        }
+    }
+    names
+}
+
+/// A board name has to be a single word to fit in a `key:value` extension.
+pub fn valid(name: &str) -> Result<&str, String> {
+    match name {
+        "" => Err("board name is missing".to_string()),
+        name if name.contains(char::is_whitespace) || name.contains(':') => {
+            Err(format!("board name `{}` can't have spaces or `:`", name))
+        }
+        name => Ok(name),
+    }
+}
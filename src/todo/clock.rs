@@ -0,0 +1,83 @@
+use super::date::Date;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the current time, so that time dependent logic doesn't have to
+/// ask the system directly.
+pub trait Clock {
+    /// Seconds since the Unix epoch.
+    fn now(&self) -> i64;
+
+    /// Offset of the local time zone from UTC in seconds.
+    fn utc_offset(&self) -> i64 {
+        0
+    }
+
+    fn today(&self) -> Date {
+        Date::from_days((self.now() + self.utc_offset()).div_euclid(86400))
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(0)
+    }
+
+    fn utc_offset(&self) -> i64 {
+        let now = self.now() as libc::time_t;
+        // SAFETY: `localtime_r` only writes into the `tm` we hand it.
+        unsafe {
+            let mut tm: libc::tm = std::mem::zeroed();
+            if libc::localtime_r(&now, &mut tm).is_null() {
+                0
+            } else {
+                tm.tm_gmtoff as i64
+            }
+        }
+    }
+}
+
+/// Always the same time, for tests.
+#[cfg(test)]
+pub struct FixedClock {
+    pub now: i64,
+    pub utc_offset: i64,
+}
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> i64 {
+        self.now
+    }
+
+    fn utc_offset(&self) -> i64 {
+        self.utc_offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn today_is_the_local_date() {
+        // 2024-05-01T23:30:00Z
+        let now = Date::new(2024, 5, 1).unwrap().days() * 86400 + 23 * 3600 + 1800;
+        let utc = FixedClock { now, utc_offset: 0 };
+        assert_eq!(utc.today(), Date::new(2024, 5, 1).unwrap());
+        let east = FixedClock {
+            now,
+            utc_offset: 3600,
+        };
+        assert_eq!(east.today(), Date::new(2024, 5, 2).unwrap());
+        let west = FixedClock {
+            now: now - 23 * 3600,
+            utc_offset: -3600,
+        };
+        assert_eq!(west.today(), Date::new(2024, 4, 30).unwrap());
+    }
+}
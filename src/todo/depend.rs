@@ -0,0 +1,130 @@
+use super::item::Item;
+use std::collections::HashSet;
+
+/// Ids of the items that are not done yet, those of every board when an
+/// item depends on one elsewhere.
+pub fn open_ids(todos: &[Item]) -> HashSet<u64> {
+    todos.iter().map(|item| item.id).collect()
+}
+
+/// Dependencies of the item that are still `open`. Dependencies that don't
+/// exist anymore don't block anything.
+pub fn blockers(item: &Item, open: &HashSet<u64>) -> Vec<u64> {
+    item.depends
+        .iter()
+        .filter(|id| open.contains(id))
+        .copied()
+        .collect()
+}
+
+pub fn blocked_ids(todos: &[Item], open: &HashSet<u64>) -> HashSet<u64> {
+    todos
+        .iter()
+        .filter(|item| !blockers(item, open).is_empty())
+        .map(|item| item.id)
+        .collect()
+}
+
+fn find(id: u64, todos: &[Item], dones: &[Item]) -> Option<Item> {
+    todos
+        .iter()
+        .chain(dones.iter())
+        .find(|item| item.id == id)
+        .cloned()
+}
+
+/// Whether `to` already depends on `from`, directly or transitively.
+fn reaches(to: u64, from: u64, todos: &[Item], dones: &[Item]) -> bool {
+    let mut seen = HashSet::new();
+    let mut stack = vec![to];
+    while let Some(id) = stack.pop() {
+        if id == from {
+            return true;
+        }
+        if seen.insert(id) {
+            if let Some(item) = find(id, todos, dones) {
+                stack.extend(item.depends);
+            }
+        }
+    }
+    false
+}
+
+/// Makes item `from` depend on item `to`, refusing dependencies that would
+/// form a cycle.
+pub fn add(from: u64, to: u64, todos: &mut [Item], dones: &mut [Item]) -> Result<(), String> {
+    if find(to, todos, dones).is_none() {
+        return Err(format!("no item #{}", to));
+    }
+    if from == to || reaches(to, from, todos, dones) {
+        return Err(format!(
+            "#{} already depends on #{}, that would be a cycle",
+            to, from
+        ));
+    }
+    let item = todos
+        .iter_mut()
+        .chain(dones.iter_mut())
+        .find(|item| item.id == from)
+        .ok_or_else(|| format!("no item #{}", from))?;
+    if !item.depends.contains(&to) {
+        item.depends.push(to);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(ids: &[u64]) -> Vec<Item> {
+        ids.iter()
+            .map(|id| Item::new(*id, &format!("Item {}", id)))
+            .collect()
+    }
+
+    #[test]
+    fn items_can_not_depend_on_themselves() {
+        let (mut todos, mut dones) = (items(&[1]), Vec::new());
+        assert!(add(1, 1, &mut todos, &mut dones).is_err());
+        assert!(todos[0].depends.is_empty());
+    }
+
+    #[test]
+    fn direct_and_transitive_cycles_are_refused() {
+        let (mut todos, mut dones) = (items(&[1, 2]), items(&[3]));
+        add(1, 2, &mut todos, &mut dones).unwrap();
+        assert!(add(2, 1, &mut todos, &mut dones).is_err());
+        // 3 is done, the cycle would still be there when it is reopened
+        add(2, 3, &mut todos, &mut dones).unwrap();
+        assert!(add(3, 1, &mut todos, &mut dones).is_err());
+        assert_eq!(todos[0].depends, [2]);
+        assert_eq!(todos[1].depends, [3]);
+        assert!(dones[0].depends.is_empty());
+    }
+
+    #[test]
+    fn adding_a_dependency_twice_keeps_one() {
+        let (mut todos, mut dones) = (items(&[1, 2]), Vec::new());
+        add(1, 2, &mut todos, &mut dones).unwrap();
+        add(1, 2, &mut todos, &mut dones).unwrap();
+        assert_eq!(todos[0].depends, [2]);
+        assert_eq!(
+            add(1, 9, &mut todos, &mut dones),
+            Err("no item #9".to_string())
+        );
+    }
+
+    #[test]
+    fn only_open_dependencies_block() {
+        let mut todos = items(&[1, 2]);
+        // 3 is done and 4 was deleted
+        todos[0].depends = vec![2, 3, 4];
+        let open = open_ids(&todos);
+        assert_eq!(blockers(&todos[0], &open), [2]);
+        assert_eq!(blocked_ids(&todos, &open), HashSet::from([1]));
+        let open = open_ids(&todos[..1]);
+        assert!(blockers(&todos[0], &open).is_empty());
+        assert!(blocked_ids(&todos, &open).is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+use super::date::Date;
+use super::recurrence::Recurrence;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub id: u64,
+    pub title: String,
+    pub parent: Option<u64>,
+    pub folded: bool,
+    pub note: String,
+    pub due: Option<Date>,
+    pub recurrence: Option<Recurrence>,
+    pub depends: Vec<u64>,
+    pub completed: Option<i64>,
+    /// Earlier completions of an item that got moved back to TODO.
+    pub history: Vec<i64>,
+    pub created: Option<i64>,
+    /// `A` being the highest priority.
+    pub priority: Option<char>,
+    pub projects: Vec<String>,
+    pub tags: Vec<String>,
+    /// `key:value` pairs coming from other formats that todors has no use
+    /// for, kept so they survive a round trip.
+    pub extensions: Vec<(String, String)>,
+}
+
+impl Item {
+    pub fn new(id: u64, title: &str) -> Self {
+        Item {
+            id,
+            title: title.to_string(),
+            parent: None,
+            folded: false,
+            note: String::new(),
+            due: None,
+            recurrence: None,
+            depends: Vec::new(),
+            completed: None,
+            history: Vec::new(),
+            created: None,
+            priority: None,
+            projects: Vec::new(),
+            tags: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+
+    pub fn reopen(&mut self) {
+        if let Some(completed) = self.completed.take() {
+            self.history.push(completed);
+        }
+    }
+
+    pub fn completions(&self) -> impl Iterator<Item = i64> + '_ {
+        self.history.iter().copied().chain(self.completed)
+    }
+}
+
+pub fn next_id(todos: &[Item], dones: &[Item]) -> u64 {
+    todos
+        .iter()
+        .chain(dones.iter())
+        .map(|item| item.id)
+        .max()
+        .unwrap_or(0)
+        + 1
+}
+
+/// Gives an id to items that came without one (id `0`).
+pub fn assign_missing_ids(todos: &mut [Item], dones: &mut [Item]) {
+    let mut next_id = next_id(todos, dones);
+    for item in todos.iter_mut().chain(dones.iter_mut()) {
+        if item.id == 0 {
+            item.id = next_id;
+            next_id += 1;
+        }
+    }
+}
+
+/// Appends items read from elsewhere, renumbering them after the existing
+/// ones so their subtasks and dependencies keep pointing at each other.
+pub fn append_renumbered(
+    todos: &mut Vec<Item>,
+    dones: &mut Vec<Item>,
+    new_todos: Vec<Item>,
+    new_dones: Vec<Item>,
+) -> usize {
+    let offset = next_id(todos, dones) - 1;
+    let count = new_todos.len() + new_dones.len();
+    let renumber = |mut item: Item| {
+        item.id += offset;
+        item.parent = item.parent.map(|id| id + offset);
+        item.depends.iter_mut().for_each(|id| *id += offset);
+        item
+    };
+    todos.extend(new_todos.into_iter().map(renumber));
+    dones.extend(new_dones.into_iter().map(renumber));
+    count
+}
@@ -0,0 +1,52 @@
+use super::date::{Date, WEEKDAYS};
+use super::item::Item;
+use std::cmp::Reverse;
+
+/// Lines of the `:log` view: every completion of every item, newest first,
+/// grouped by local day or by week starting on Monday.
+pub fn lines(todos: &[Item], dones: &[Item], by_week: bool, utc_offset: i64) -> Vec<String> {
+    let mut entries: Vec<(i64, &str)> = todos
+        .iter()
+        .chain(dones.iter())
+        .flat_map(|item| {
+            item.completions()
+                .map(move |secs| (secs, item.title.as_str()))
+        })
+        .collect();
+    entries.sort_by_key(|entry| Reverse(entry.0));
+
+    let mut lines = Vec::new();
+    let mut group: Option<Date> = None;
+    for (secs, title) in entries {
+        let local = secs + utc_offset;
+        let date = Date::from_days(local.div_euclid(86400));
+        let start = if by_week {
+            date.add_days(-(date.weekday() as i64))
+        } else {
+            date
+        };
+        if group != Some(start) {
+            if group.is_some() {
+                lines.push(String::new());
+            }
+            lines.push(if by_week {
+                format!("Week of {}", start)
+            } else {
+                format!("{} {}", date, WEEKDAYS[date.weekday()])
+            });
+            group = Some(start);
+        }
+        let time = local.rem_euclid(86400);
+        lines.push(format!(
+            "  {} {:02}:{:02} {}",
+            WEEKDAYS[date.weekday()],
+            time / 3600,
+            time % 3600 / 60,
+            title
+        ));
+    }
+    if lines.is_empty() {
+        lines.push("Nothing done yet".to_string());
+    }
+    lines
+}
@@ -0,0 +1,499 @@
+use super::item::Item;
+use super::tree;
+use std::collections::{HashMap, HashSet};
+
+/// Items and whether they are done, by id.
+type Side = HashMap<u64, (Item, bool)>;
+
+/// An item both sides changed in different ways: the same field differently,
+/// or one removed it while the other changed it.
+pub struct Conflict {
+    pub ours: Option<(Item, bool)>,
+    pub theirs: Option<(Item, bool)>,
+}
+
+impl Conflict {
+    pub fn id(&self) -> u64 {
+        self.ours
+            .as_ref()
+            .or(self.theirs.as_ref())
+            .map_or(0, |(item, _)| item.id)
+    }
+}
+
+pub struct Merge {
+    pub todos: Vec<Item>,
+    pub dones: Vec<Item>,
+    /// The merged lists hold our version of these, or theirs when we removed
+    /// an item they changed.
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Merge {
+    /// The merged lists with one side's version of every conflicting item.
+    pub fn resolved(&self, ours: bool) -> (Vec<Item>, Vec<Item>) {
+        let mut lists = (self.todos.clone(), self.dones.clone());
+        for conflict in &self.conflicts {
+            let id = conflict.id();
+            let at = |list: &[Item]| list.iter().position(|item| item.id == id);
+            let place = match at(&lists.0) {
+                Some(index) => Some((false, index)),
+                None => at(&lists.1).map(|index| (true, index)),
+            };
+            let picked = if ours {
+                &conflict.ours
+            } else {
+                &conflict.theirs
+            };
+            match (place, picked) {
+                (Some((done, index)), Some((item, picked_done))) if done == *picked_done => {
+                    let list = if done { &mut lists.1 } else { &mut lists.0 };
+                    list[index] = item.clone();
+                }
+                (place, picked) => {
+                    if let Some((done, index)) = place {
+                        let list = if done { &mut lists.1 } else { &mut lists.0 };
+                        list.remove(index);
+                    }
+                    if let Some((item, done)) = picked {
+                        let list = if *done { &mut lists.1 } else { &mut lists.0 };
+                        list.push(item.clone());
+                    }
+                }
+            }
+        }
+        tree::normalize(&mut lists.0);
+        tree::normalize(&mut lists.1);
+        lists
+    }
+}
+
+fn side(todos: &[Item], dones: &[Item]) -> Side {
+    todos
+        .iter()
+        .map(|item| (item.id, (item.clone(), false)))
+        .chain(dones.iter().map(|item| (item.id, (item.clone(), true))))
+        .collect()
+}
+
+fn ids(list: &[Item]) -> Vec<u64> {
+    list.iter().map(|item| item.id).collect()
+}
+
+fn uuid(item: &Item) -> Option<&str> {
+    item.extensions
+        .iter()
+        .find(|(key, _)| key == "uuid")
+        .map(|(_, uuid)| uuid.as_str())
+}
+
+/// Gives the items of `lists` the id of the base item with the same `uuid`,
+/// like the tasks of Taskwarrior have, or else the same title. For files
+/// that do not keep ids and number items by their position.
+pub fn match_items(base: (&[Item], &[Item]), lists: &mut (Vec<Item>, Vec<Item>)) {
+    let mut unused: Vec<&Item> = base.0.iter().chain(base.1.iter()).collect();
+    let mut next_id = unused
+        .iter()
+        .map(|item| item.id)
+        .chain(lists.0.iter().chain(lists.1.iter()).map(|item| item.id))
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut ids: HashMap<u64, u64> = HashMap::new();
+    for item in lists.0.iter().chain(lists.1.iter()) {
+        let same_uuid = uuid(item)
+            .and_then(|wanted| unused.iter().position(|other| uuid(other) == Some(wanted)));
+        let same = same_uuid.or_else(|| unused.iter().position(|other| other.title == item.title));
+        let id = match same {
+            Some(index) => unused.remove(index).id,
+            None => {
+                next_id += 1;
+                next_id - 1
+            }
+        };
+        ids.insert(item.id, id);
+    }
+    for item in lists.0.iter_mut().chain(lists.1.iter_mut()) {
+        item.id = ids[&item.id];
+        item.parent = item.parent.and_then(|parent| ids.get(&parent).copied());
+        for depend in &mut item.depends {
+            if let Some(id) = ids.get(depend) {
+                *depend = *id;
+            }
+        }
+    }
+}
+
+/// How many items `after` added, removed and changed compared to `before`.
+pub fn diff(before: (&[Item], &[Item]), after: (&[Item], &[Item])) -> (usize, usize, usize) {
+    let before = side(before.0, before.1);
+    let after = side(after.0, after.1);
+    let added = after.keys().filter(|id| !before.contains_key(id)).count();
+    let removed = before.keys().filter(|id| !after.contains_key(id)).count();
+    let changed = after
+        .iter()
+        .filter(|(id, item)| before.get(id).is_some_and(|old| old != *item))
+        .count();
+    (added, removed, changed)
+}
+
+/// Gives items both sides added under the same id a new id on their side.
+fn renumber_added(base: &Side, ours: &Side, theirs: &mut (Vec<Item>, Vec<Item>)) {
+    let mut next_id = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.0.iter().chain(theirs.1.iter()).map(|item| &item.id))
+        .max()
+        .copied()
+        .unwrap_or(0)
+        + 1;
+    let their_side = side(&theirs.0, &theirs.1);
+    let mut renames: HashMap<u64, u64> = HashMap::new();
+    for (id, (item, done)) in &their_side {
+        let clash = !base.contains_key(id)
+            && ours
+                .get(id)
+                .is_some_and(|ours| ours.0 != *item || ours.1 != *done);
+        if clash {
+            renames.insert(*id, next_id);
+            next_id += 1;
+        }
+    }
+    // The renamed ids are not in the base, on their side they can only mean
+    // their new items
+    for item in theirs.0.iter_mut().chain(theirs.1.iter_mut()) {
+        if let Some(id) = renames.get(&item.id) {
+            item.id = *id;
+        }
+        if let Some(parent) = item.parent.and_then(|parent| renames.get(&parent)) {
+            item.parent = Some(*parent);
+        }
+        for depend in &mut item.depends {
+            if let Some(id) = renames.get(depend) {
+                *depend = *id;
+            }
+        }
+    }
+}
+
+/// Orders the ids of one merged list: the side that reordered the items
+/// they share with the base decides, items missing from it go after the
+/// item they follow on the other side.
+fn order(wanted: &HashSet<u64>, base: &[u64], ours: &[u64], theirs: &[u64]) -> Vec<u64> {
+    let common = |list: &[u64], other: &[u64]| -> Vec<u64> {
+        list.iter()
+            .filter(|id| other.contains(id))
+            .copied()
+            .collect()
+    };
+    let ours_reordered = common(ours, base) != common(base, ours);
+    let (first, second) = if ours_reordered {
+        (ours, theirs)
+    } else {
+        (theirs, ours)
+    };
+    let mut result: Vec<u64> = first
+        .iter()
+        .filter(|id| wanted.contains(id))
+        .copied()
+        .collect();
+    for sequence in [second, base] {
+        let mut after: Option<u64> = None;
+        for id in sequence {
+            if wanted.contains(id) && !result.contains(id) {
+                let at = after
+                    .and_then(|after| result.iter().position(|other| *other == after))
+                    .map_or(0, |index| index + 1);
+                result.insert(at, *id);
+            }
+            if result.contains(id) {
+                after = Some(*id);
+            }
+        }
+    }
+    result
+}
+
+/// The side that changed a field, `None` when both changed it differently.
+fn pick<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    } else if ours == base {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+/// Merges an item both sides changed field by field, `None` when they
+/// changed the same field in different ways.
+fn merge_fields(
+    base: &(Item, bool),
+    ours: &(Item, bool),
+    theirs: &(Item, bool),
+) -> Option<(Item, bool)> {
+    let ((b, b_done), (o, o_done), (t, t_done)) = (base, ours, theirs);
+    let item = Item {
+        id: o.id,
+        title: pick(&b.title, &o.title, &t.title)?,
+        parent: pick(&b.parent, &o.parent, &t.parent)?,
+        folded: pick(&b.folded, &o.folded, &t.folded)?,
+        note: pick(&b.note, &o.note, &t.note)?,
+        due: pick(&b.due, &o.due, &t.due)?,
+        recurrence: pick(&b.recurrence, &o.recurrence, &t.recurrence)?,
+        depends: pick(&b.depends, &o.depends, &t.depends)?,
+        completed: pick(&b.completed, &o.completed, &t.completed)?,
+        history: pick(&b.history, &o.history, &t.history)?,
+        created: pick(&b.created, &o.created, &t.created)?,
+        priority: pick(&b.priority, &o.priority, &t.priority)?,
+        projects: pick(&b.projects, &o.projects, &t.projects)?,
+        tags: pick(&b.tags, &o.tags, &t.tags)?,
+        extensions: pick(&b.extensions, &o.extensions, &t.extensions)?,
+    };
+    Some((item, pick(b_done, o_done, t_done)?))
+}
+
+/// Merges the changes both sides made to `base` item by item, and the items
+/// both changed field by field.
+pub fn merge(
+    base: (&[Item], &[Item]),
+    ours: (&[Item], &[Item]),
+    theirs: (&[Item], &[Item]),
+) -> Merge {
+    let base_side = side(base.0, base.1);
+    let our_side = side(ours.0, ours.1);
+    let mut theirs = (theirs.0.to_vec(), theirs.1.to_vec());
+    renumber_added(&base_side, &our_side, &mut theirs);
+    let their_side = side(&theirs.0, &theirs.1);
+
+    let mut all: Vec<u64> = base_side
+        .keys()
+        .chain(our_side.keys())
+        .chain(their_side.keys())
+        .copied()
+        .collect();
+    all.sort();
+    all.dedup();
+
+    let mut merged: HashMap<u64, (Item, bool)> = HashMap::new();
+    let mut conflicts = Vec::new();
+    for id in all {
+        let (b, o, t) = (base_side.get(&id), our_side.get(&id), their_side.get(&id));
+        let result = if o == t || t == b {
+            o.cloned()
+        } else if o == b {
+            t.cloned()
+        } else if let Some(fields) = b
+            .zip(o)
+            .zip(t)
+            .and_then(|((b, o), t)| merge_fields(b, o, t))
+        {
+            Some(fields)
+        } else {
+            conflicts.push(Conflict {
+                ours: o.cloned(),
+                theirs: t.cloned(),
+            });
+            o.or(t).cloned()
+        };
+        if let Some(result) = result {
+            merged.insert(id, result);
+        }
+    }
+
+    let mut lists = (Vec::new(), Vec::new());
+    for done in [false, true] {
+        let wanted: HashSet<u64> = merged
+            .iter()
+            .filter(|(_, (_, d))| *d == done)
+            .map(|(id, _)| *id)
+            .collect();
+        let pick = |lists: (&[Item], &[Item])| ids(if done { lists.1 } else { lists.0 });
+        let ids = order(
+            &wanted,
+            &pick(base),
+            &pick(ours),
+            &pick((&theirs.0, &theirs.1)),
+        );
+        let list = if done { &mut lists.1 } else { &mut lists.0 };
+        list.extend(
+            ids.iter()
+                .filter_map(|id| merged.remove(id))
+                .map(|(item, _)| item),
+        );
+        tree::normalize(list);
+    }
+    Merge {
+        todos: lists.0,
+        dones: lists.1,
+        conflicts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(items: &[(u64, &str)]) -> Vec<Item> {
+        items
+            .iter()
+            .map(|(id, title)| Item::new(*id, title))
+            .collect()
+    }
+
+    fn titles(list: &[Item]) -> Vec<&str> {
+        list.iter().map(|item| item.title.as_str()).collect()
+    }
+
+    fn run(
+        base: (Vec<Item>, Vec<Item>),
+        ours: (Vec<Item>, Vec<Item>),
+        theirs: (Vec<Item>, Vec<Item>),
+    ) -> Merge {
+        merge(
+            (&base.0, &base.1),
+            (&ours.0, &ours.1),
+            (&theirs.0, &theirs.1),
+        )
+    }
+
+    #[test]
+    fn adds_on_both_sides_are_kept() {
+        let base = (items(&[(1, "a")]), vec![]);
+        let ours = (items(&[(1, "a"), (2, "ours")]), vec![]);
+        let theirs = (items(&[(1, "a"), (3, "theirs")]), vec![]);
+        let merged = run(base, ours, theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(titles(&merged.todos), ["a", "ours", "theirs"]);
+    }
+
+    #[test]
+    fn adds_under_the_same_id_are_renumbered() {
+        let base = (items(&[(1, "a")]), vec![]);
+        let ours = (items(&[(1, "a"), (2, "ours")]), vec![]);
+        let theirs = (items(&[(1, "a"), (2, "theirs")]), vec![]);
+        let merged = run(base, ours, theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(titles(&merged.todos), ["a", "ours", "theirs"]);
+        let ids: HashSet<u64> = merged.todos.iter().map(|item| item.id).collect();
+        assert_eq!(ids.len(), 3);
+    }
+
+    #[test]
+    fn the_same_add_on_both_sides_is_kept_once() {
+        let base = (items(&[(1, "a")]), vec![]);
+        let ours = (items(&[(1, "a"), (2, "same")]), vec![]);
+        let theirs = ours.clone();
+        let merged = run(base, ours, theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(titles(&merged.todos), ["a", "same"]);
+    }
+
+    #[test]
+    fn removals_on_either_side_are_kept() {
+        let base = (items(&[(1, "a"), (2, "b"), (3, "c")]), vec![]);
+        let ours = (items(&[(1, "a"), (3, "c")]), vec![]);
+        let theirs = (items(&[(1, "a"), (2, "b")]), vec![]);
+        let merged = run(base, ours, theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(titles(&merged.todos), ["a"]);
+    }
+
+    #[test]
+    fn status_changes_move_items_between_lists() {
+        let base = (items(&[(1, "a"), (2, "b")]), items(&[(3, "c")]));
+        let ours = (items(&[(2, "b")]), items(&[(3, "c"), (1, "a")]));
+        let theirs = (items(&[(1, "a"), (2, "b"), (3, "c")]), vec![]);
+        let merged = run(base, ours, theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(titles(&merged.todos), ["b", "c"]);
+        assert_eq!(titles(&merged.dones), ["a"]);
+    }
+
+    #[test]
+    fn a_reorder_on_one_side_keeps_edits_and_adds_of_the_other() {
+        let base = (items(&[(1, "a"), (2, "b"), (3, "c")]), vec![]);
+        let ours = (items(&[(3, "c"), (1, "a"), (2, "b")]), vec![]);
+        let theirs = (
+            items(&[(1, "a edited"), (2, "b"), (4, "d"), (3, "c")]),
+            vec![],
+        );
+        let merged = run(base, ours, theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(titles(&merged.todos), ["c", "a edited", "b", "d"]);
+    }
+
+    #[test]
+    fn edits_to_different_items_are_combined() {
+        let base = (items(&[(1, "a"), (2, "b")]), vec![]);
+        let ours = (items(&[(1, "a ours"), (2, "b")]), vec![]);
+        let theirs = (items(&[(1, "a"), (2, "b theirs")]), vec![]);
+        let merged = run(base, ours, theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(titles(&merged.todos), ["a ours", "b theirs"]);
+    }
+
+    #[test]
+    fn edits_to_different_fields_of_an_item_are_combined() {
+        let base = (items(&[(1, "a")]), vec![]);
+        let ours = (items(&[(1, "renamed")]), vec![]);
+        let mut completed = items(&[(1, "a")]);
+        completed[0].completed = Some(100);
+        completed[0].priority = Some('A');
+        let theirs = (vec![], completed);
+        let merged = run(base, ours, theirs);
+        assert!(merged.conflicts.is_empty());
+        assert!(merged.todos.is_empty());
+        assert_eq!(titles(&merged.dones), ["renamed"]);
+        assert_eq!(merged.dones[0].completed, Some(100));
+        assert_eq!(merged.dones[0].priority, Some('A'));
+    }
+
+    #[test]
+    fn the_same_field_changed_differently_conflicts() {
+        let base = (items(&[(1, "a"), (2, "b")]), vec![]);
+        let ours = (items(&[(1, "ours"), (2, "b")]), vec![]);
+        let theirs = (items(&[(1, "theirs"), (2, "b")]), vec![]);
+        let merged = run(base, ours, theirs);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].id(), 1);
+        assert_eq!(titles(&merged.resolved(true).0), ["ours", "b"]);
+        assert_eq!(titles(&merged.resolved(false).0), ["theirs", "b"]);
+    }
+
+    #[test]
+    fn a_change_against_a_removal_conflicts() {
+        let base = (items(&[(1, "a"), (2, "b")]), vec![]);
+        let ours = (items(&[(2, "b")]), vec![]);
+        let theirs = (items(&[(1, "a edited"), (2, "b")]), vec![]);
+        let merged = run(base, ours, theirs);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(titles(&merged.resolved(true).0), ["b"]);
+        assert_eq!(titles(&merged.resolved(false).0), ["a edited", "b"]);
+    }
+
+    #[test]
+    fn items_without_ids_are_matched_by_title() {
+        let base = items(&[(1, "a"), (2, "b")]);
+        let mut theirs = (items(&[(1, "new"), (2, "a"), (3, "b")]), vec![]);
+        theirs.0[2].depends = vec![2];
+        match_items((&base, &[]), &mut theirs);
+        let ids: Vec<u64> = theirs.0.iter().map(|item| item.id).collect();
+        assert_eq!(ids, [4, 1, 2]);
+        assert_eq!(theirs.0[2].depends, [1]);
+    }
+
+    #[test]
+    fn items_with_a_uuid_are_matched_by_it() {
+        let mut base = items(&[(1, "a"), (2, "b")]);
+        base[1]
+            .extensions
+            .push(("uuid".to_string(), "u-b".to_string()));
+        let mut theirs = (items(&[(1, "b renamed")]), vec![]);
+        theirs.0[0]
+            .extensions
+            .push(("uuid".to_string(), "u-b".to_string()));
+        match_items((&base, &[]), &mut theirs);
+        assert_eq!(theirs.0[0].id, 2);
+    }
+}
@@ -0,0 +1,10 @@
+pub mod archive;
+pub mod board;
+pub mod clock;
+pub mod date;
+pub mod depend;
+pub mod item;
+pub mod log;
+pub mod merge;
+pub mod recurrence;
+pub mod tree;
@@ -0,0 +1,262 @@
+use super::board;
+use super::date::{days_in_month, Date, WEEKDAYS};
+use super::item::*;
+use super::tree;
+use std::fmt;
+
+/// Extension holding the day of the month a fixed monthly schedule falls on,
+/// while the due date is clamped to a shorter month.
+const DAY: &str = "recur_day";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frequency {
+    Daily,
+    /// Weekdays the item recurs on, `0` being Monday. Empty means every 7 days.
+    Weekly(Vec<usize>),
+    Monthly,
+    EveryDays(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    /// Count from the day the item got done instead of its due date.
+    pub after_completion: bool,
+}
+
+impl Recurrence {
+    /// Parses rules like `daily`, `weekly mon,thu`, `monthly` or `every 3 days`,
+    /// optionally followed by `after-completion`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut words: Vec<&str> = text.split_whitespace().collect();
+        let after_completion = words.last() == Some(&"after-completion");
+        if after_completion {
+            words.pop();
+        }
+        let frequency = match words.as_slice() {
+            ["daily"] => Frequency::Daily,
+            ["weekly"] => Frequency::Weekly(Vec::new()),
+            ["weekly", days] => {
+                let mut weekdays = Vec::new();
+                for day in days.split(',') {
+                    let weekday = WEEKDAYS
+                        .iter()
+                        .position(|name| *name == day)
+                        .ok_or_else(|| format!("unknown weekday `{}`", day))?;
+                    if !weekdays.contains(&weekday) {
+                        weekdays.push(weekday);
+                    }
+                }
+                weekdays.sort();
+                Frequency::Weekly(weekdays)
+            }
+            ["monthly"] => Frequency::Monthly,
+            ["every", days] | ["every", days, "days"] | ["every", days, "day"] => {
+                match days.parse() {
+                    Ok(days) if days > 0 => Frequency::EveryDays(days),
+                    _ => return Err(format!("invalid number of days `{}`", days)),
+                }
+            }
+            _ => return Err(format!("unknown recurrence `{}`", text)),
+        };
+        Ok(Recurrence {
+            frequency,
+            after_completion,
+        })
+    }
+
+    /// The occurrence after `from`, `day` being the day of the month monthly
+    /// ones fall on.
+    fn step(&self, from: Date, day: u32) -> Date {
+        match &self.frequency {
+            Frequency::Daily => from.add_days(1),
+            Frequency::Weekly(weekdays) if weekdays.is_empty() => from.add_days(7),
+            Frequency::Weekly(weekdays) => (1..=7)
+                .map(|days| from.add_days(days))
+                .find(|date| weekdays.contains(&date.weekday()))
+                .unwrap_or_else(|| from.add_days(7)),
+            Frequency::Monthly => {
+                let next = from.add_months(1);
+                Date {
+                    day: day.min(days_in_month(next.year, next.month)),
+                    ..next
+                }
+            }
+            Frequency::EveryDays(days) => from.add_days(*days as i64),
+        }
+    }
+
+    /// Due date of the occurrence following one that was `due` and got done
+    /// `today`. A fixed schedule skips the occurrences that were missed, and
+    /// a monthly one stays on `day`, the day of the month it started on.
+    pub fn next(&self, due: Option<Date>, day: Option<u32>, today: Date) -> Date {
+        match due {
+            Some(due) if !self.after_completion => {
+                let day = day.unwrap_or(due.day);
+                let mut next = self.step(due, day);
+                while next <= today {
+                    next = self.step(next, day);
+                }
+                next
+            }
+            _ => self.step(today, today.day),
+        }
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.frequency {
+            Frequency::Daily => write!(f, "daily")?,
+            Frequency::Weekly(weekdays) if weekdays.is_empty() => write!(f, "weekly")?,
+            Frequency::Weekly(weekdays) => {
+                let names: Vec<&str> = weekdays.iter().map(|day| WEEKDAYS[*day]).collect();
+                write!(f, "weekly {}", names.join(","))?
+            }
+            Frequency::Monthly => write!(f, "monthly")?,
+            Frequency::EveryDays(days) => write!(f, "every {} days", days)?,
+        }
+        if self.after_completion {
+            write!(f, " after-completion")?;
+        }
+        Ok(())
+    }
+}
+
+fn day(item: &Item) -> Option<u32> {
+    item.extensions
+        .iter()
+        .find(|(key, _)| key == DAY)
+        .and_then(|(_, day)| day.parse().ok())
+}
+
+/// Creates the next occurrence in `todos` for each recurring item of `ids`
+/// that just moved to `dones`. The rule moves over to the new occurrence, so
+/// the completed one doesn't spawn again when it goes back and forth.
+/// Returns the due date of the last occurrence created.
+pub fn spawn_next(
+    ids: &[u64],
+    todos: &mut Vec<Item>,
+    dones: &mut [Item],
+    today: Date,
+) -> Option<Date> {
+    let mut last = None;
+    for id in ids {
+        let Some(index) = tree::position(dones, *id) else {
+            continue;
+        };
+        let Some(recurrence) = dones[index].recurrence.take() else {
+            continue;
+        };
+        let done = &dones[index];
+        let day = day(done).or(done.due.map(|due| due.day));
+        let due = recurrence.next(done.due, day, today);
+        let mut item = Item::new(next_id(todos, dones), &done.title);
+        item.parent = done.parent;
+        item.note = done.note.clone();
+        item.due = Some(due);
+        item.depends = done.depends.clone();
+        item.priority = done.priority;
+        item.projects = done.projects.clone();
+        item.tags = done.tags.clone();
+        board::assign(&mut item, board::name(done));
+        let fixed_monthly =
+            recurrence.frequency == Frequency::Monthly && !recurrence.after_completion;
+        if let Some(day) = day.filter(|day| fixed_monthly && *day != due.day) {
+            item.extensions.push((DAY.to_string(), day.to_string()));
+        }
+        item.recurrence = Some(recurrence);
+        todos.push(item);
+        last = Some(due);
+    }
+    tree::normalize(todos);
+    last
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> Date {
+        Date::parse(text).unwrap()
+    }
+
+    fn rule(text: &str) -> Recurrence {
+        Recurrence::parse(text).unwrap()
+    }
+
+    #[test]
+    fn a_fixed_schedule_skips_missed_occurrences() {
+        let weekly = rule("weekly");
+        let due = Some(date("2024-05-06"));
+        assert_eq!(
+            weekly.next(due, None, date("2024-05-06")),
+            date("2024-05-13")
+        );
+        assert_eq!(
+            weekly.next(due, None, date("2024-05-20")),
+            date("2024-05-27")
+        );
+    }
+
+    #[test]
+    fn after_completion_counts_from_today() {
+        let every = rule("every 3 days after-completion");
+        assert_eq!(
+            every.next(Some(date("2024-05-01")), None, date("2024-05-10")),
+            date("2024-05-13")
+        );
+    }
+
+    #[test]
+    fn weekdays_pick_the_next_one() {
+        let weekly = rule("weekly mon,fri");
+        // 2024-05-07 is a Tuesday
+        assert_eq!(
+            weekly.next(Some(date("2024-05-06")), None, date("2024-05-07")),
+            date("2024-05-10")
+        );
+    }
+
+    #[test]
+    fn a_fixed_monthly_schedule_keeps_its_day() {
+        let monthly = rule("monthly");
+        let feb = monthly.next(Some(date("2023-01-31")), Some(31), date("2023-01-31"));
+        assert_eq!(feb, date("2023-02-28"));
+        let mar = monthly.next(Some(feb), Some(31), feb);
+        assert_eq!(mar, date("2023-03-31"));
+        let apr = monthly.next(Some(mar), Some(31), mar);
+        assert_eq!(apr, date("2023-04-30"));
+    }
+
+    #[test]
+    fn the_next_occurrence_keeps_the_fields_of_the_done_one() {
+        let mut item = Item::new(1, "Pay rent");
+        item.due = Some(date("2023-01-31"));
+        item.recurrence = Some(rule("monthly"));
+        item.priority = Some('A');
+        item.projects = vec!["home".to_string()];
+        item.tags = vec!["bank".to_string()];
+        item.depends = vec![7];
+        board::assign(&mut item, "life");
+        let mut todos = vec![Item::new(7, "Get paid")];
+        let mut dones = vec![item];
+
+        let due = spawn_next(&[1], &mut todos, &mut dones, date("2023-01-31"));
+        assert_eq!(due, Some(date("2023-02-28")));
+        assert_eq!(dones[0].recurrence, None);
+        let next = todos.last().unwrap().clone();
+        assert_eq!(next.title, "Pay rent");
+        assert_eq!(next.priority, Some('A'));
+        assert_eq!(next.projects, ["home"]);
+        assert_eq!(next.tags, ["bank"]);
+        assert_eq!(next.depends, [7]);
+        assert_eq!(board::name(&next), "life");
+
+        // Through February the schedule stays on the 31st
+        let mut dones = vec![todos.pop().unwrap()];
+        let due = spawn_next(&[next.id], &mut todos, &mut dones, date("2023-02-28"));
+        assert_eq!(due, Some(date("2023-03-31")));
+        assert_eq!(day(todos.last().unwrap()), None);
+    }
+}
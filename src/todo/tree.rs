@@ -0,0 +1,306 @@
+use super::item::Item;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// Items of a list are kept in outline order: every item is directly followed
+// by its subtree. Only ancestors living in the same list count towards depth,
+// so a subtask moved to the other list is rendered there as a root item.
+
+pub fn depths(list: &[Item]) -> Vec<usize> {
+    let index: HashMap<u64, usize> = list
+        .iter()
+        .enumerate()
+        .map(|(i, item)| (item.id, i))
+        .collect();
+    list.iter()
+        .map(|item| {
+            let mut depth = 0;
+            let mut parent = item.parent;
+            while let Some(i) = parent.and_then(|id| index.get(&id)) {
+                depth += 1;
+                parent = list[*i].parent;
+                if depth > list.len() {
+                    break;
+                }
+            }
+            depth
+        })
+        .collect()
+}
+
+fn block_end(depths: &[usize], index: usize) -> usize {
+    let mut end = index + 1;
+    while end < depths.len() && depths[end] > depths[index] {
+        end += 1;
+    }
+    end
+}
+
+pub fn subtree_end(list: &[Item], index: usize) -> usize {
+    block_end(&depths(list), index)
+}
+
+pub fn has_children(list: &[Item], index: usize) -> bool {
+    subtree_end(list, index) > index + 1
+}
+
+pub fn visible(list: &[Item]) -> Vec<usize> {
+    let depths = depths(list);
+    let mut result = Vec::new();
+    let mut folded_at: Option<usize> = None;
+    for (index, item) in list.iter().enumerate() {
+        if let Some(depth) = folded_at {
+            if depths[index] > depth {
+                continue;
+            }
+            folded_at = None;
+        }
+        result.push(index);
+        if item.folded && block_end(&depths, index) > index + 1 {
+            folded_at = Some(depths[index]);
+        }
+    }
+    result
+}
+
+/// Moves `curr` onto the closest visible item at or above it.
+pub fn snap(list: &[Item], curr: &mut usize) {
+    if let Some(index) = visible(list).into_iter().rev().find(|index| index <= curr) {
+        *curr = index;
+    }
+}
+
+/// Number of direct children already done and the total number of direct
+/// children, or `None` if the item has no children at all.
+pub fn progress(id: u64, todos: &[Item], dones: &[Item]) -> Option<(usize, usize)> {
+    let is_child = |item: &&Item| item.parent == Some(id);
+    let done = dones.iter().filter(is_child).count();
+    let total = done + todos.iter().filter(is_child).count();
+    if total > 0 {
+        Some((done, total))
+    } else {
+        None
+    }
+}
+
+/// Restores outline order after items were moved between lists or reparented,
+/// keeping the relative order of siblings.
+pub fn normalize(list: &mut Vec<Item>) {
+    let ids: HashSet<u64> = list.iter().map(|item| item.id).collect();
+    let mut groups: BTreeMap<Option<u64>, Vec<Item>> = BTreeMap::new();
+    for item in list.drain(..) {
+        let parent = item.parent.filter(|id| ids.contains(id));
+        groups.entry(parent).or_default().push(item);
+    }
+
+    fn visit(
+        parent: Option<u64>,
+        groups: &mut BTreeMap<Option<u64>, Vec<Item>>,
+        out: &mut Vec<Item>,
+    ) {
+        if let Some(items) = groups.remove(&parent) {
+            for item in items {
+                let id = item.id;
+                out.push(item);
+                visit(Some(id), groups, out);
+            }
+        }
+    }
+    visit(None, &mut groups, list);
+
+    // Whatever is left is part of a parent cycle, keep it rather than lose it.
+    for (_, items) in groups {
+        for mut item in items {
+            item.parent = None;
+            list.push(item);
+        }
+    }
+}
+
+pub fn position(list: &[Item], id: u64) -> Option<usize> {
+    list.iter().position(|item| item.id == id)
+}
+
+/// Unfolds the ancestors of the item so it is visible.
+pub fn reveal(list: &mut [Item], index: usize) {
+    let mut parent = list.get(index).and_then(|item| item.parent);
+    let mut steps = 0;
+    while let Some(at) = parent.and_then(|id| position(list, id)) {
+        list[at].folded = false;
+        parent = list[at].parent;
+        steps += 1;
+        if steps > list.len() {
+            break;
+        }
+    }
+}
+
+/// Makes the item a child of its previous sibling.
+pub fn indent(list: &mut [Item], curr: usize) {
+    if curr >= list.len() {
+        return;
+    }
+    let depths = depths(list);
+    let mut index = curr;
+    while index > 0 {
+        index -= 1;
+        if depths[index] < depths[curr] {
+            return;
+        }
+        if depths[index] == depths[curr] {
+            list[curr].parent = Some(list[index].id);
+            list[index].folded = false;
+            return;
+        }
+    }
+}
+
+/// Makes the item a sibling of its parent, placed right after the parent's subtree.
+pub fn outdent(list: &mut Vec<Item>, curr: &mut usize) {
+    let Some(parent) = list.get(*curr).and_then(|item| item.parent) else {
+        return;
+    };
+    let id = list[*curr].id;
+    list[*curr].parent = position(list, parent).and_then(|index| list[index].parent);
+    normalize(list);
+    if let Some(index) = position(list, id) {
+        *curr = index;
+    }
+}
+
+pub fn fold(list: &mut [Item], curr: &mut usize, folded: Option<bool>) {
+    if *curr >= list.len() {
+        return;
+    }
+    let target = if has_children(list, *curr) {
+        Some(*curr)
+    } else {
+        list[*curr].parent.and_then(|parent| position(list, parent))
+    };
+    if let Some(index) = target {
+        let item = &mut list[index];
+        item.folded = folded.unwrap_or(!item.folded);
+        if item.folded {
+            *curr = index;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Items with `(id, parent)`, in the given order.
+    fn list(items: &[(u64, Option<u64>)]) -> Vec<Item> {
+        items
+            .iter()
+            .map(|(id, parent)| {
+                let mut item = Item::new(*id, &format!("Item {}", id));
+                item.parent = *parent;
+                item
+            })
+            .collect()
+    }
+
+    fn ids(list: &[Item]) -> Vec<u64> {
+        list.iter().map(|item| item.id).collect()
+    }
+
+    #[test]
+    fn indenting_the_first_item_does_nothing() {
+        let mut items = list(&[(1, None), (2, None)]);
+        indent(&mut items, 0);
+        assert_eq!(items[0].parent, None);
+        assert_eq!(depths(&items), [0, 0]);
+    }
+
+    #[test]
+    fn indenting_makes_a_child_of_the_previous_sibling() {
+        let mut items = list(&[(1, None), (2, Some(1)), (3, None)]);
+        items[0].folded = true;
+        indent(&mut items, 2);
+        assert_eq!(items[2].parent, Some(1));
+        assert!(!items[0].folded);
+        // The first child has no sibling above it
+        indent(&mut items, 1);
+        assert_eq!(items[1].parent, Some(1));
+        assert_eq!(depths(&items), [0, 1, 1]);
+    }
+
+    #[test]
+    fn outdenting_a_root_does_nothing() {
+        let mut items = list(&[(1, None), (2, None)]);
+        let mut curr = 0;
+        outdent(&mut items, &mut curr);
+        assert_eq!(ids(&items), [1, 2]);
+        assert_eq!(curr, 0);
+    }
+
+    #[test]
+    fn outdented_items_follow_the_subtree_of_their_parent() {
+        let mut items = list(&[
+            (1, None),
+            (2, Some(1)),
+            (3, Some(2)),
+            (4, Some(1)),
+            (5, None),
+        ]);
+        let mut curr = 1;
+        outdent(&mut items, &mut curr);
+        assert_eq!(ids(&items), [1, 4, 2, 3, 5]);
+        assert_eq!(depths(&items), [0, 1, 0, 1, 0]);
+        assert_eq!(curr, 2);
+    }
+
+    #[test]
+    fn folding_a_subtask_folds_its_parent() {
+        let mut items = list(&[(1, None), (2, Some(1)), (3, Some(2)), (4, None)]);
+        let mut curr = 2;
+        fold(&mut items, &mut curr, Some(true));
+        assert!(items[1].folded);
+        assert_eq!(curr, 1);
+        assert_eq!(visible(&items), [0, 1, 3]);
+        fold(&mut items, &mut curr, None);
+        assert!(!items[1].folded);
+        assert_eq!(visible(&items), [0, 1, 2, 3]);
+        // Nothing to fold around a root without children
+        let mut curr = 3;
+        fold(&mut items, &mut curr, Some(true));
+        assert!(!items[3].folded);
+    }
+
+    #[test]
+    fn hidden_items_are_revealed_and_snapped_to() {
+        let mut items = list(&[(1, None), (2, Some(1)), (3, Some(2))]);
+        items[0].folded = true;
+        items[1].folded = true;
+        let mut curr = 2;
+        snap(&items, &mut curr);
+        assert_eq!(curr, 0);
+        reveal(&mut items, 2);
+        assert_eq!(visible(&items), [0, 1, 2]);
+    }
+
+    #[test]
+    fn progress_counts_direct_children_in_both_lists() {
+        let todos = list(&[(1, None), (2, Some(1)), (4, Some(2))]);
+        let dones = list(&[(3, Some(1)), (5, Some(2))]);
+        assert_eq!(progress(1, &todos, &dones), Some((1, 2)));
+        assert_eq!(progress(2, &todos, &dones), Some((1, 2)));
+        assert_eq!(progress(4, &todos, &dones), None);
+    }
+
+    #[test]
+    fn normalize_puts_subtrees_after_their_parents() {
+        let mut items = list(&[
+            (2, Some(1)),
+            (3, None),
+            (1, None),
+            (4, Some(5)),
+            (5, Some(4)),
+        ]);
+        normalize(&mut items);
+        assert_eq!(ids(&items), [3, 1, 2, 5, 4]);
+        // The parent cycle is broken rather than lost
+        assert!(items[3..].iter().all(|item| item.parent.is_none()));
+    }
+}
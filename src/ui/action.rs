@@ -1,4 +1,11 @@
-#[derive(PartialEq)]
+use crate::todo::clock::Clock;
+use crate::todo::date::Date;
+use crate::todo::item::Item;
+use crate::todo::recurrence;
+use crate::todo::tree;
+use std::cmp::Ordering;
+
+#[derive(PartialEq, Clone, Copy)]
 #[repr(u8)]
 pub enum Direction {
     Down,
@@ -36,38 +43,208 @@ pub fn go(dir: Direction, size: usize, index: &mut usize) {
     }
 }
 
-pub fn delete(src: &mut Vec<String>, curr: &mut usize) {
+/// Same as `go`, but skips items hidden inside folded subtrees.
+pub fn go_visible(dir: Direction, src: &[Item], curr: &mut usize) {
+    let visible = tree::visible(src);
+    let mut index = visible.iter().rposition(|i| i <= curr).unwrap_or(0);
+    go(dir, visible.len(), &mut index);
+    if let Some(i) = visible.get(index) {
+        *curr = *i;
+    }
+}
+
+pub fn delete(src: &mut Vec<Item>, curr: &mut usize) {
     if *curr < src.len() {
-        src.remove(*curr);
+        let end = tree::subtree_end(src, *curr);
+        src.drain(*curr..end);
         if *curr >= src.len() {
             go(Direction::Up, src.len(), curr);
         }
+        tree::snap(src, curr);
     }
 }
 
-pub fn drag(dir: Direction, src: &mut [String], curr: &mut usize) {
+pub fn drag(dir: Direction, src: &mut [Item], curr: &mut usize) {
+    if *curr >= src.len() {
+        return;
+    }
+    let depths = tree::depths(src);
+    let depth = depths[*curr];
+    let end = tree::subtree_end(src, *curr);
     match dir {
-        Direction::Down => {
-            if *curr + 1 < src.len() {
-                src.swap(*curr, *curr + 1);
-                *curr += 1;
-            }
+        Direction::Down if end < src.len() && depths[end] == depth => {
+            let next_end = tree::subtree_end(src, end);
+            src[*curr..next_end].rotate_left(end - *curr);
+            *curr += next_end - end;
         }
         Direction::Up => {
-            if *curr > 0 {
-                src.swap(*curr, *curr - 1);
-                *curr -= 1;
+            let prev = (0..*curr)
+                .rev()
+                .take_while(|i| depths[*i] >= depth)
+                .find(|i| depths[*i] == depth);
+            if let Some(prev) = prev {
+                src[prev..end].rotate_right(end - *curr);
+                *curr = prev;
             }
         }
         _ => {}
     }
 }
 
-pub fn transfer(dst: &mut Vec<String>, src: &mut Vec<String>, curr: &mut usize) {
+/// Drags the item at `curr` until it takes the place of the one at `target`.
+pub fn drag_to(src: &mut [Item], curr: &mut usize, target: usize) {
+    let Some(target_id) = src.get(target).map(|item| item.id) else {
+        return;
+    };
+    let dir = match target.cmp(curr) {
+        Ordering::Greater => Direction::Down,
+        Ordering::Less => Direction::Up,
+        Ordering::Equal => return,
+    };
+    loop {
+        let before = *curr;
+        drag(dir, src, curr);
+        let Some(target) = src.iter().position(|item| item.id == target_id) else {
+            return;
+        };
+        let passed = match dir {
+            Direction::Down => *curr > target,
+            _ => *curr <= target,
+        };
+        if *curr == before || passed {
+            return;
+        }
+    }
+}
+
+/// Moves the item together with its subtree to the other list.
+pub fn transfer(dst: &mut Vec<Item>, src: &mut Vec<Item>, curr: &mut usize) {
     if !src.is_empty() && *curr < src.len() {
-        dst.push(src.remove(*curr));
+        let end = tree::subtree_end(src, *curr);
+        dst.extend(src.drain(*curr..end));
+        tree::normalize(dst);
         if *curr >= src.len() {
             go(Direction::Up, src.len(), curr);
         }
+        tree::snap(src, curr);
+    }
+}
+
+/// Removes the item together with its subtree, for another board.
+pub fn take_subtree(src: &mut Vec<Item>, curr: &mut usize) -> Vec<Item> {
+    if *curr >= src.len() {
+        return Vec::new();
+    }
+    let end = tree::subtree_end(src, *curr);
+    let taken = src.drain(*curr..end).collect();
+    if *curr >= src.len() {
+        go(Direction::Up, src.len(), curr);
+    }
+    tree::snap(src, curr);
+    taken
+}
+
+/// Moves the item with its subtree to DONE, stamps their completion time and
+/// schedules the next occurrence of the recurring ones, returning the due
+/// date of the new occurrence.
+pub fn complete(
+    todos: &mut Vec<Item>,
+    dones: &mut Vec<Item>,
+    curr: &mut usize,
+    clock: &dyn Clock,
+) -> Option<Date> {
+    if *curr >= todos.len() {
+        return None;
+    }
+    let end = tree::subtree_end(todos, *curr);
+    let ids: Vec<u64> = todos[*curr..end].iter().map(|item| item.id).collect();
+    transfer(dones, todos, curr);
+    for item in dones.iter_mut().filter(|item| ids.contains(&item.id)) {
+        item.completed = Some(clock.now());
+    }
+    recurrence::spawn_next(&ids, todos, dones, clock.today())
+}
+
+/// Moves the item with its subtree back to TODO, their completion stamps
+/// only stay in the history.
+pub fn reopen(todos: &mut Vec<Item>, dones: &mut Vec<Item>, curr: &mut usize) {
+    if *curr >= dones.len() {
+        return;
+    }
+    let end = tree::subtree_end(dones, *curr);
+    let ids: Vec<u64> = dones[*curr..end].iter().map(|item| item.id).collect();
+    transfer(todos, dones, curr);
+    for item in todos.iter_mut().filter(|item| ids.contains(&item.id)) {
+        item.reopen();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::todo::clock::FixedClock;
+    use crate::todo::recurrence::Recurrence;
+
+    #[test]
+    fn complete_stamps_the_clock_time_and_schedules_from_its_day() {
+        let today = Date::parse("2024-05-10").unwrap();
+        let clock = FixedClock {
+            now: today.days() * 86400 + 12 * 3600,
+            utc_offset: 0,
+        };
+        let mut item = Item::new(1, "Water plants");
+        item.due = Date::parse("2024-05-01");
+        item.recurrence = Some(Recurrence::parse("every 3 days after-completion").unwrap());
+        let mut todos = vec![item, Item::new(2, "Other")];
+        let mut dones = Vec::new();
+        let mut curr = 0;
+
+        let due = complete(&mut todos, &mut dones, &mut curr, &clock);
+
+        assert_eq!(due, Date::parse("2024-05-13"));
+        assert_eq!(dones[0].completed, Some(clock.now));
+        let titles: Vec<&str> = todos.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(titles, ["Other", "Water plants"]);
+        assert_eq!(todos[1].due, due);
+    }
+
+    fn folded_subtree() -> Vec<Item> {
+        let mut items: Vec<Item> = (1..=4)
+            .map(|id| Item::new(id, &format!("Item {}", id)))
+            .collect();
+        items[0].folded = true;
+        items[1].parent = Some(1);
+        items[2].parent = Some(2);
+        items
+    }
+
+    fn ids(list: &[Item]) -> Vec<u64> {
+        list.iter().map(|item| item.id).collect()
+    }
+
+    #[test]
+    fn dragging_a_folded_subtree_moves_it_whole() {
+        let mut items = folded_subtree();
+        let mut curr = 0;
+        drag(Direction::Down, &mut items, &mut curr);
+        assert_eq!(ids(&items), [4, 1, 2, 3]);
+        assert_eq!(curr, 1);
+        assert_eq!(tree::visible(&items), [0, 1]);
+        drag(Direction::Up, &mut items, &mut curr);
+        assert_eq!(ids(&items), [1, 2, 3, 4]);
+        assert_eq!(curr, 0);
+    }
+
+    #[test]
+    fn a_folded_subtree_moves_to_the_other_list_whole() {
+        let mut items = folded_subtree();
+        let mut others = Vec::new();
+        let mut curr = 0;
+        transfer(&mut others, &mut items, &mut curr);
+        assert_eq!(ids(&others), [1, 2, 3]);
+        assert!(others[0].folded);
+        assert_eq!(tree::visible(&others), [0]);
+        assert_eq!(ids(&items), [4]);
+        assert_eq!(curr, 0);
     }
 }
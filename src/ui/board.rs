@@ -0,0 +1,457 @@
+use super::status::Status;
+use crate::todo::board;
+use crate::todo::item::Item;
+use crate::todo::tree;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+// The main loop edits the lists of the board on screen, every other board
+// waits here with its cursors and active panel. Saving and reloading work on
+// the items of all boards, see `join` and `split`.
+
+pub struct Board {
+    pub name: String,
+    todos: Vec<Item>,
+    dones: Vec<Item>,
+    todo_curr: usize,
+    done_curr: usize,
+    status: Status,
+}
+
+impl Board {
+    fn new(name: &str) -> Self {
+        Board {
+            name: name.to_string(),
+            todos: Vec::new(),
+            dones: Vec::new(),
+            todo_curr: 0,
+            done_curr: 0,
+            status: Status::Todo,
+        }
+    }
+}
+
+pub struct Boards {
+    /// All boards, the lists of the current one are out being edited.
+    boards: Vec<Board>,
+    current: usize,
+}
+
+/// Keeps `curr` on the item with `id`, or as close as the list allows.
+fn follow(list: &[Item], id: Option<u64>, curr: &mut usize) {
+    match id.and_then(|id| tree::position(list, id)) {
+        Some(index) => *curr = index,
+        None => {
+            *curr = (*curr).min(list.len().saturating_sub(1));
+            tree::snap(list, curr);
+        }
+    }
+}
+
+fn id_at(list: &[Item], curr: usize) -> Option<u64> {
+    list.get(curr).map(|item| item.id)
+}
+
+impl Boards {
+    /// Splits the items by board, returning the lists of the first one which
+    /// becomes the current board.
+    pub fn new(todos: Vec<Item>, dones: Vec<Item>) -> (Self, Vec<Item>, Vec<Item>) {
+        let mut boards = Boards {
+            boards: Vec::new(),
+            current: 0,
+        };
+        let (mut todo_curr, mut done_curr) = (0, 0);
+        let (mut current_todos, mut current_dones) = (Vec::new(), Vec::new());
+        boards.split(
+            todos,
+            dones,
+            &mut current_todos,
+            &mut current_dones,
+            &mut todo_curr,
+            &mut done_curr,
+        );
+        (boards, current_todos, current_dones)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.boards.iter().map(|board| board.name.as_str())
+    }
+
+    pub fn count(&self) -> usize {
+        self.boards.len()
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn current_name(&self) -> &str {
+        &self.boards[self.current].name
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.boards.iter().position(|board| board.name == name)
+    }
+
+    /// The board holding the item with `id`, the current one excluded.
+    pub fn find(&self, id: u64) -> Option<usize> {
+        self.boards.iter().position(|board| {
+            board
+                .todos
+                .iter()
+                .chain(&board.dones)
+                .any(|item| item.id == id)
+        })
+    }
+
+    /// Ids of the TODO items of all boards, `todos` being the current lists.
+    pub fn open_ids(&self, todos: &[Item]) -> HashSet<u64> {
+        self.boards
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != self.current)
+            .flat_map(|(_, board)| &board.todos)
+            .chain(todos)
+            .map(|item| item.id)
+            .collect()
+    }
+
+    /// Items of all boards, board by board, with the current lists in place.
+    pub fn join(&self, todos: &[Item], dones: &[Item]) -> (Vec<Item>, Vec<Item>) {
+        let mut all_todos = Vec::new();
+        let mut all_dones = Vec::new();
+        for (index, board) in self.boards.iter().enumerate() {
+            if index == self.current {
+                all_todos.extend_from_slice(todos);
+                all_dones.extend_from_slice(dones);
+            } else {
+                all_todos.extend_from_slice(&board.todos);
+                all_dones.extend_from_slice(&board.dones);
+            }
+        }
+        (all_todos, all_dones)
+    }
+
+    /// Puts the items of all boards back where they belong, after a reload or
+    /// a merge. Boards keep their cursors on the same items, boards left
+    /// empty stay and new ones are added at the end.
+    pub fn split(
+        &mut self,
+        all_todos: Vec<Item>,
+        all_dones: Vec<Item>,
+        todos: &mut Vec<Item>,
+        dones: &mut Vec<Item>,
+        todo_curr: &mut usize,
+        done_curr: &mut usize,
+    ) {
+        if let Some(board) = self.boards.get_mut(self.current) {
+            board.todos = mem::take(todos);
+            board.dones = mem::take(dones);
+            board.todo_curr = *todo_curr;
+            board.done_curr = *done_curr;
+        }
+        let followed: Vec<(Option<u64>, Option<u64>)> = self
+            .boards
+            .iter_mut()
+            .map(|board| {
+                let todo_id = id_at(&board.todos, board.todo_curr);
+                let done_id = id_at(&board.dones, board.done_curr);
+                board.todos.clear();
+                board.dones.clear();
+                (todo_id, done_id)
+            })
+            .collect();
+        for name in board::names(&all_todos, &all_dones) {
+            if self.position(&name).is_none() {
+                self.boards.push(Board::new(&name));
+            }
+        }
+        if self.boards.is_empty() {
+            self.boards.push(Board::new(board::DEFAULT));
+        }
+        let index: HashMap<String, usize> = self
+            .boards
+            .iter()
+            .enumerate()
+            .map(|(index, board)| (board.name.clone(), index))
+            .collect();
+        for item in all_todos {
+            self.boards[index[board::name(&item)]].todos.push(item);
+        }
+        for item in all_dones {
+            self.boards[index[board::name(&item)]].dones.push(item);
+        }
+        for (board, (todo_id, done_id)) in self.boards.iter_mut().zip(followed) {
+            follow(&board.todos, todo_id, &mut board.todo_curr);
+            follow(&board.dones, done_id, &mut board.done_curr);
+        }
+        let board = &mut self.boards[self.current];
+        *todos = mem::take(&mut board.todos);
+        *dones = mem::take(&mut board.dones);
+        *todo_curr = board.todo_curr;
+        *done_curr = board.done_curr;
+    }
+
+    /// Puts the current lists on the current board: items added since the
+    /// last call get its name, and a fresh id when theirs is taken on
+    /// another board.
+    pub fn sync(&self, todos: &mut [Item], dones: &mut [Item]) {
+        let name = self.current_name();
+        let others: HashSet<u64> = self
+            .boards
+            .iter()
+            .flat_map(|board| board.todos.iter().chain(&board.dones))
+            .map(|item| item.id)
+            .collect();
+        let mut next_id = others
+            .iter()
+            .copied()
+            .chain(todos.iter().chain(dones.iter()).map(|item| item.id))
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let mut renames: HashMap<u64, u64> = HashMap::new();
+        for item in todos.iter_mut().chain(dones.iter_mut()) {
+            if others.contains(&item.id) {
+                renames.insert(item.id, next_id);
+                item.id = next_id;
+                next_id += 1;
+            }
+        }
+        for item in todos.iter_mut().chain(dones.iter_mut()) {
+            if board::name(item) != name {
+                board::assign(item, name);
+            }
+            if renames.is_empty() {
+                continue;
+            }
+            if let Some(parent) = item.parent.and_then(|parent| renames.get(&parent)) {
+                item.parent = Some(*parent);
+            }
+            for id in item.depends.iter_mut() {
+                if let Some(renamed) = renames.get(id) {
+                    *id = *renamed;
+                }
+            }
+        }
+    }
+
+    /// Shows board `to`, the current lists, cursors and panel are kept for
+    /// when the current board is shown again.
+    pub fn switch(
+        &mut self,
+        to: usize,
+        todos: &mut Vec<Item>,
+        dones: &mut Vec<Item>,
+        todo_curr: &mut usize,
+        done_curr: &mut usize,
+        status: &mut Status,
+    ) {
+        if to == self.current || to >= self.boards.len() {
+            return;
+        }
+        let board = &mut self.boards[self.current];
+        board.todos = mem::take(todos);
+        board.dones = mem::take(dones);
+        board.todo_curr = *todo_curr;
+        board.done_curr = *done_curr;
+        board.status = *status;
+        self.current = to;
+        let board = &mut self.boards[to];
+        *todos = mem::take(&mut board.todos);
+        *dones = mem::take(&mut board.dones);
+        *todo_curr = board.todo_curr;
+        *done_curr = board.done_curr;
+        *status = board.status;
+    }
+
+    /// Adds an empty board at the end unless there is one by that name,
+    /// returns its index.
+    pub fn create(&mut self, name: &str) -> usize {
+        self.position(name).unwrap_or_else(|| {
+            self.boards.push(Board::new(name));
+            self.boards.len() - 1
+        })
+    }
+
+    /// Renames the current board, its items follow on the next `sync`.
+    pub fn rename(&mut self, name: &str) -> Result<(), String> {
+        if self.position(name).is_some() {
+            return Err(format!("there already is a board `{}`", name));
+        }
+        self.boards[self.current].name = name.to_string();
+        Ok(())
+    }
+
+    /// Drops the current board with its items and shows the one before it.
+    pub fn delete(
+        &mut self,
+        todos: &mut Vec<Item>,
+        dones: &mut Vec<Item>,
+        todo_curr: &mut usize,
+        done_curr: &mut usize,
+        status: &mut Status,
+    ) -> Result<(), String> {
+        if self.boards.len() == 1 {
+            return Err("the only board can't be deleted".to_string());
+        }
+        self.boards.remove(self.current);
+        self.current = self.current.saturating_sub(1);
+        let board = &mut self.boards[self.current];
+        *todos = mem::take(&mut board.todos);
+        *dones = mem::take(&mut board.dones);
+        *todo_curr = board.todo_curr;
+        *done_curr = board.done_curr;
+        *status = board.status;
+        Ok(())
+    }
+
+    /// Adds items taken from the current board to the same list of board
+    /// `to`, which must not be the current one.
+    pub fn move_to(&mut self, to: usize, status: Status, items: Vec<Item>) {
+        let board = &mut self.boards[to];
+        let ids: HashSet<u64> = items.iter().map(|item| item.id).collect();
+        let list = match status {
+            Status::Todo => &mut board.todos,
+            Status::Done => &mut board.dones,
+        };
+        for mut item in items {
+            // The parent of the subtree stays behind
+            if !item.parent.is_some_and(|parent| ids.contains(&parent)) {
+                item.parent = None;
+            }
+            board::assign(&mut item, &board.name);
+            list.push(item);
+        }
+        tree::normalize(list);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(id: u64, name: &str) -> Item {
+        let mut item = Item::new(id, &format!("Item {}", id));
+        board::assign(&mut item, name);
+        item
+    }
+
+    fn ids(list: &[Item]) -> Vec<u64> {
+        list.iter().map(|item| item.id).collect()
+    }
+
+    /// Boards `main` with item 1 and `home` with items 2 and 3, the second
+    /// a subtask of the first. Returns the lists of `main`.
+    fn boards() -> (Boards, Vec<Item>, Vec<Item>) {
+        let mut child = on(3, "home");
+        child.parent = Some(2);
+        Boards::new(vec![on(1, "main"), on(2, "home"), child], Vec::new())
+    }
+
+    #[test]
+    fn items_are_split_by_board_and_joined_back() {
+        let (boards, todos, dones) = boards();
+        assert_eq!(boards.names().collect::<Vec<_>>(), ["main", "home"]);
+        assert_eq!(ids(&todos), [1]);
+        let (all_todos, all_dones) = boards.join(&todos, &dones);
+        assert_eq!(ids(&all_todos), [1, 2, 3]);
+        assert!(all_dones.is_empty());
+        assert_eq!(boards.find(3), Some(1));
+    }
+
+    #[test]
+    fn created_boards_are_empty_and_names_stay_unique() {
+        let (mut boards, mut todos, mut dones) = boards();
+        let index = boards.create("work");
+        assert_eq!(index, 2);
+        assert_eq!(boards.create("home"), 1);
+        let (mut todo_curr, mut done_curr, mut status) = (0, 0, Status::Todo);
+        boards.switch(
+            index,
+            &mut todos,
+            &mut dones,
+            &mut todo_curr,
+            &mut done_curr,
+            &mut status,
+        );
+        assert!(todos.is_empty());
+        todos.push(Item::new(1, "New"));
+        boards.sync(&mut todos, &mut dones);
+        // Id 1 is taken on `main`
+        assert_eq!(ids(&todos), [4]);
+        assert_eq!(board::name(&todos[0]), "work");
+    }
+
+    #[test]
+    fn renamed_boards_rename_their_items() {
+        let (mut boards, mut todos, mut dones) = boards();
+        assert!(boards.rename("home").is_err());
+        boards.rename("work").unwrap();
+        boards.sync(&mut todos, &mut dones);
+        assert_eq!(boards.current_name(), "work");
+        assert_eq!(board::name(&todos[0]), "work");
+    }
+
+    #[test]
+    fn deleting_drops_the_board_and_its_items() {
+        let (mut boards, mut todos, mut dones) = boards();
+        let (mut todo_curr, mut done_curr, mut status) = (0, 0, Status::Todo);
+        boards.switch(
+            1,
+            &mut todos,
+            &mut dones,
+            &mut todo_curr,
+            &mut done_curr,
+            &mut status,
+        );
+        boards
+            .delete(
+                &mut todos,
+                &mut dones,
+                &mut todo_curr,
+                &mut done_curr,
+                &mut status,
+            )
+            .unwrap();
+        assert_eq!(boards.current_name(), "main");
+        assert_eq!(ids(&boards.join(&todos, &dones).0), [1]);
+        assert!(boards
+            .delete(
+                &mut todos,
+                &mut dones,
+                &mut todo_curr,
+                &mut done_curr,
+                &mut status,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn moved_items_keep_their_subtree_but_not_their_parent() {
+        let (mut boards, mut todos, mut dones) = boards();
+        let (mut todo_curr, mut done_curr, mut status) = (0, 0, Status::Todo);
+        boards.switch(
+            1,
+            &mut todos,
+            &mut dones,
+            &mut todo_curr,
+            &mut done_curr,
+            &mut status,
+        );
+        let moved = mem::take(&mut todos);
+        boards.move_to(0, Status::Todo, moved[1..].to_vec());
+        boards.move_to(0, Status::Todo, moved[..1].to_vec());
+        let (all_todos, _) = boards.join(&todos, &dones);
+        assert_eq!(ids(&all_todos), [1, 3, 2]);
+        assert!(all_todos.iter().all(|item| board::name(item) == "main"));
+        assert_eq!(all_todos[1].parent, None);
+    }
+
+    #[test]
+    fn open_ids_cover_every_board() {
+        let (boards, todos, _) = boards();
+        let open = boards.open_ids(&todos);
+        assert_eq!(open, HashSet::from([1, 2, 3]));
+    }
+}
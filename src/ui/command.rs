@@ -0,0 +1,164 @@
+use crate::format::csv;
+use crate::todo::board;
+use crate::todo::date::Date;
+use crate::todo::recurrence::Recurrence;
+
+/// Usage and description of every command, listed by `:help`.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("w", "Saves items to the file"),
+    ("q", "Quits, saving the items"),
+    ("help", "Shows keys and commands"),
+    ("messages", "Lists the notifications of the session"),
+    (
+        "export FILE [COLUMNS]",
+        "Exports items, format by extension",
+    ),
+    ("import FILE", "Adds the items of FILE"),
+    ("log [day|week]", "Lists completed items by day or week"),
+    ("archive [DAYS]", "Moves DONE items to FILE.archive"),
+    ("archived", "Browses archived items"),
+    ("due DATE", "Sets due date, `none` clears it"),
+    ("recur RULE", "Makes item recurring, `none` stops it"),
+    ("dep ID", "Makes item depend on item #ID"),
+    ("undep ID", "Removes dependency on item #ID"),
+    ("pri A-Z", "Sets priority, `none` clears it"),
+    ("tag +PROJECT|@TAG", "Adds project or tag"),
+    ("untag +PROJECT|@TAG", "Removes project or tag"),
+    ("board NAME", "Switches to board NAME, creating it"),
+    ("board rename NAME", "Renames the current board"),
+    ("board delete", "Deletes the current board and its items"),
+    ("move BOARD", "Moves item (with its subtasks) to BOARD"),
+];
+
+/// Whether the first word of `input` names a command, otherwise `:` picks
+/// from the palette.
+pub fn is_known(input: &str) -> bool {
+    let name = input.split_whitespace().next().unwrap_or("");
+    COMMANDS
+        .iter()
+        .any(|(usage, _)| usage.split(' ').next() == Some(name))
+}
+
+/// Commands typed after `:` in Normal mode.
+pub enum Command {
+    Write,
+    Quit,
+    Export {
+        path: String,
+        columns: Option<Vec<String>>,
+    },
+    Import(String),
+    Due(Option<Date>),
+    Recur(Option<Recurrence>),
+    Depend(u64),
+    Undepend(u64),
+    Log {
+        by_week: bool,
+    },
+    Archive {
+        days: Option<u32>,
+    },
+    Archived,
+    Messages,
+    Help,
+    Priority(Option<char>),
+    Tag(String),
+    Untag(String),
+    Board(String),
+    RenameBoard(String),
+    DeleteBoard,
+    Move(String),
+}
+
+impl Command {
+    pub fn parse(input: &str, today: Date) -> Result<Self, String> {
+        let input = input.trim();
+        let (name, args) = match input.split_once(' ') {
+            Some((name, args)) => (name, args.trim()),
+            None => (input, ""),
+        };
+        match (name, args) {
+            ("w", "") => Ok(Command::Write),
+            ("q", "") => Ok(Command::Quit),
+            ("export", args) if !args.is_empty() => match args.split_once(' ') {
+                Some((path, columns)) => Ok(Command::Export {
+                    path: path.to_string(),
+                    columns: Some(csv::parse_columns(columns.trim())?),
+                }),
+                None => Ok(Command::Export {
+                    path: args.to_string(),
+                    columns: None,
+                }),
+            },
+            ("import", path) if !path.is_empty() => Ok(Command::Import(path.to_string())),
+            ("log", "") | ("log", "day") => Ok(Command::Log { by_week: false }),
+            ("log", "week") => Ok(Command::Log { by_week: true }),
+            ("archive", "") => Ok(Command::Archive { days: None }),
+            ("archive", days) => days
+                .parse()
+                .map(|days| Command::Archive { days: Some(days) })
+                .map_err(|_| format!("invalid number of days `{}`", days)),
+            ("archived", "") => Ok(Command::Archived),
+            ("messages", "") => Ok(Command::Messages),
+            ("help", "") => Ok(Command::Help),
+            ("pri", "none") => Ok(Command::Priority(None)),
+            ("pri", priority) => match priority.as_bytes() {
+                [priority @ b'A'..=b'Z'] => Ok(Command::Priority(Some(*priority as char))),
+                _ => Err(format!("invalid priority `{}`, expected A-Z", priority)),
+            },
+            ("tag", tag) if is_tag(tag) => Ok(Command::Tag(tag.to_string())),
+            ("untag", tag) if is_tag(tag) => Ok(Command::Untag(tag.to_string())),
+            ("due", "none") => Ok(Command::Due(None)),
+            ("due", date) => Date::parse_relative(date, today)
+                .map(|date| Command::Due(Some(date)))
+                .ok_or_else(|| format!("invalid date `{}`", date)),
+            ("recur", "none") => Ok(Command::Recur(None)),
+            ("recur", rule) => Recurrence::parse(rule).map(|rule| Command::Recur(Some(rule))),
+            ("board", "delete") => Ok(Command::DeleteBoard),
+            ("board", args) => match args.split_once(' ').unwrap_or((args, "")) {
+                ("rename", name) => {
+                    board::valid(name.trim()).map(|name| Command::RenameBoard(name.to_string()))
+                }
+                _ => board::valid(args).map(|name| Command::Board(name.to_string())),
+            },
+            ("move", name) => board::valid(name).map(|name| Command::Move(name.to_string())),
+            ("dep", id) => parse_id(id).map(Command::Depend),
+            ("undep", id) => parse_id(id).map(Command::Undepend),
+            _ => Err(format!("unknown command `{}`", input)),
+        }
+    }
+}
+
+fn parse_id(text: &str) -> Result<u64, String> {
+    text.trim_start_matches('#')
+        .parse()
+        .map_err(|_| format!("invalid item id `{}`", text))
+}
+
+/// `+project`, `@tag` or a bare tag name.
+fn is_tag(text: &str) -> bool {
+    let name = text.trim_start_matches(['+', '@']);
+    !name.is_empty() && !name.contains([' ', ',', ':', '+', '@'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_rename_needs_a_name() {
+        let today = Date::new(2024, 5, 1).unwrap();
+        assert!(matches!(
+            Command::parse("board rename", today),
+            Err(err) if err == "board name is missing"
+        ));
+        assert!(matches!(
+            Command::parse("board rename home", today),
+            Ok(Command::RenameBoard(name)) if name == "home"
+        ));
+        assert!(matches!(
+            Command::parse("board renamed", today),
+            Ok(Command::Board(name)) if name == "renamed"
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+use ncurses::{endwin, refresh};
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::hash::BuildHasher;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+/// Creates a file only we can read in the shared temp dir. The name ends
+/// with a random number, and `create_new` refuses one that is taken, even by
+/// a symlink, in which case another number is tried.
+fn create_temp(name: &str) -> io::Result<(PathBuf, File)> {
+    let mut attempt: u32 = 0;
+    loop {
+        let path = env::temp_dir().join(format!(
+            "todors-{}-{}-{:016x}.txt",
+            process::id(),
+            name,
+            RandomState::new().hash_one(attempt)
+        ));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 16 => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Suspends curses, opens `text` in `$EDITOR` and returns the edited text once
+/// the editor exits. The screen is restored even if the editor fails.
+pub fn edit(name: &str, text: &str) -> io::Result<String> {
+    let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let mut args = editor.split_whitespace();
+    let program = args.next().unwrap_or("vi");
+    let (path, mut file) = create_temp(name)?;
+    let written = writeln!(file, "{}", text);
+    drop(file);
+    if let Err(err) = written {
+        let _ = fs::remove_file(&path);
+        return Err(err);
+    }
+
+    endwin();
+    let status = Command::new(program).args(args).arg(&path).status();
+    refresh();
+
+    let result = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path),
+        Ok(status) => Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        ))),
+        Err(err) => Err(err),
+    };
+    let _ = fs::remove_file(&path);
+    result.map(|text| text.trim_end_matches('\n').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn temp_files_are_private_and_not_reused() {
+        let (first, _) = create_temp("test").unwrap();
+        let (second, _) = create_temp("test").unwrap();
+        let mode = fs::metadata(&first).unwrap().permissions().mode();
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
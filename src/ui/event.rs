@@ -0,0 +1,145 @@
+use super::vec2::Vec2;
+use ncurses::{
+    getch, getmouse, mmask_t, timeout, BUTTON1_PRESSED, BUTTON1_RELEASED, BUTTON4_PRESSED,
+    BUTTON5_PRESSED, ERR, KEY_MOUSE, KEY_RESIZE, MEVENT, OK,
+};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Something the main loop has to react to, the screen is redrawn after each.
+pub enum Event {
+    Key(i32),
+    Mouse(Mouse),
+    Resize,
+    /// Sent every tick so time based parts of the screen stay current.
+    Tick,
+    Timer(Timer),
+    /// A signal interrupted the wait, see `terminal`.
+    Signal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseKind {
+    Press,
+    Release,
+    WheelUp,
+    WheelDown,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mouse {
+    pub kind: MouseKind,
+    pub pos: Vec2,
+}
+
+/// Buttons reported by ncurses, pressing and releasing separately so
+/// dragging can be told from clicking.
+pub const MOUSE_MASK: mmask_t =
+    (BUTTON1_PRESSED | BUTTON1_RELEASED | BUTTON4_PRESSED | BUTTON5_PRESSED) as mmask_t;
+
+fn mouse() -> Option<Mouse> {
+    let mut event = MEVENT {
+        id: 0,
+        x: 0,
+        y: 0,
+        z: 0,
+        bstate: 0,
+    };
+    if getmouse(&mut event) != OK {
+        return None;
+    }
+    let kind = [
+        (BUTTON1_PRESSED, MouseKind::Press),
+        (BUTTON1_RELEASED, MouseKind::Release),
+        (BUTTON4_PRESSED, MouseKind::WheelUp),
+        (BUTTON5_PRESSED, MouseKind::WheelDown),
+    ]
+    .into_iter()
+    .find(|(button, _)| event.bstate & *button as mmask_t != 0)?
+    .1;
+    Some(Mouse {
+        kind,
+        pos: Vec2::new(event.x, event.y),
+    })
+}
+
+/// One-shot timers, setting one again moves it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timer {
+    /// Look for changes someone else made to the file.
+    CheckFile,
+    /// Save once no key was pressed for a while.
+    Autosave,
+    /// Clear the status bar.
+    Notification,
+}
+
+pub struct Events {
+    tick: Duration,
+    next_tick: Instant,
+    timers: Vec<(Instant, Timer)>,
+    /// Keys pressed on behalf of the user, handled before any real input.
+    fed: VecDeque<i32>,
+}
+
+impl Events {
+    pub fn new(tick: Duration) -> Self {
+        Events {
+            tick,
+            next_tick: Instant::now() + tick,
+            timers: Vec::new(),
+            fed: VecDeque::new(),
+        }
+    }
+
+    pub fn feed(&mut self, keys: &[i32]) {
+        self.fed.extend(keys);
+    }
+
+    pub fn set(&mut self, timer: Timer, after: Duration) {
+        self.cancel(timer);
+        self.timers.push((Instant::now() + after, timer));
+    }
+
+    pub fn cancel(&mut self, timer: Timer) {
+        self.timers.retain(|(_, other)| *other != timer);
+    }
+
+    /// Waits for input until the next timer or tick is due.
+    pub fn next(&mut self) -> Event {
+        if let Some(key) = self.fed.pop_front() {
+            return Event::Key(key);
+        }
+        if let Some(event) = self.due() {
+            return event;
+        }
+        let deadline = self
+            .timers
+            .iter()
+            .map(|(at, _)| *at)
+            .fold(self.next_tick, Instant::min);
+        let wait = deadline.saturating_duration_since(Instant::now());
+        // Rounded up so the deadline has passed when `getch` gives up
+        timeout(wait.as_millis() as i32 + 1);
+        match getch() {
+            KEY_RESIZE => Event::Resize,
+            // Events of buttons nothing is done with are skipped
+            KEY_MOUSE => mouse().map_or_else(|| self.next(), Event::Mouse),
+            ERR => self.due().unwrap_or(Event::Signal),
+            key => Event::Key(key),
+        }
+    }
+
+    fn due(&mut self) -> Option<Event> {
+        let now = Instant::now();
+        if let Some(index) = self.timers.iter().position(|(at, _)| *at <= now) {
+            return Some(Event::Timer(self.timers.remove(index).1));
+        }
+        if self.next_tick <= now {
+            // Ticks missed while suspended are not caught up on
+            self.next_tick = now + self.tick;
+            return Some(Event::Tick);
+        }
+        None
+    }
+}
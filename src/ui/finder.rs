@@ -0,0 +1,261 @@
+use super::action::Direction;
+use super::command::COMMANDS;
+use super::key_map::{key_name, Action, BINDINGS};
+use super::mode::Mode;
+use super::status::Status;
+use crate::todo::board;
+use crate::todo::item::Item;
+
+/// What choosing an entry does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// Jumps to the item with the id in the list.
+    Item(Status, u64),
+    /// Presses the keys in Normal mode.
+    Keys(&'static [i32]),
+    /// Types the command, `usage` as listed in `COMMANDS`.
+    Command(&'static str),
+}
+
+pub struct Entry {
+    /// What the query is matched against.
+    pub text: String,
+    /// Shown after the text but not matched, like the keys of an action.
+    pub hint: String,
+    pub target: Target,
+}
+
+/// An entry matching the query, with the characters of its text that did.
+pub struct Match<'a> {
+    pub entry: &'a Entry,
+    pub positions: Vec<usize>,
+}
+
+/// Floating list narrowed down by fuzzy matching as the query is typed.
+pub struct Finder {
+    pub title: String,
+    pub entries: Vec<Entry>,
+    pub query: String,
+    pub selected: usize,
+}
+
+impl Finder {
+    pub fn new(title: &str, entries: Vec<Entry>) -> Self {
+        Finder {
+            title: title.to_string(),
+            entries,
+            query: String::new(),
+            selected: 0,
+        }
+    }
+
+    /// Entries matching the query, best first. Ties keep the order of the
+    /// entries.
+    pub fn matches(&self) -> Vec<Match<'_>> {
+        let mut ranked: Vec<(i32, Match)> = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let (score, positions) = fuzzy(&self.query, &entry.text)?;
+                Some((score, Match { entry, positions }))
+            })
+            .collect();
+        ranked.sort_by_key(|(score, _)| -score);
+        ranked.into_iter().map(|(_, found)| found).collect()
+    }
+
+    pub fn search(&mut self, query: &str) {
+        self.query = query.to_string();
+        self.selected = 0;
+    }
+
+    pub fn go(&mut self, dir: Direction) {
+        let last = self.matches().len().saturating_sub(1);
+        match dir {
+            Direction::Down => self.selected = (self.selected + 1).min(last),
+            Direction::Up => self.selected = self.selected.saturating_sub(1),
+            Direction::First => self.selected = 0,
+            Direction::Last => self.selected = last,
+        }
+    }
+
+    pub fn chosen(&self) -> Option<Target> {
+        self.matches()
+            .get(self.selected)
+            .map(|found| found.entry.target.clone())
+    }
+}
+
+/// Entries for every item of both lists, naming their board when there is
+/// more than one.
+pub fn items(todos: &[Item], dones: &[Item], boards: bool) -> Vec<Entry> {
+    let lists = [(Status::Todo, todos), (Status::Done, dones)];
+    lists
+        .into_iter()
+        .flat_map(|(status, list)| {
+            list.iter().map(move |item| {
+                let mut text = item.title.clone();
+                for project in &item.projects {
+                    text.push_str(&format!(" +{}", project));
+                }
+                for tag in &item.tags {
+                    text.push_str(&format!(" @{}", tag));
+                }
+                let list = match status {
+                    Status::Todo => "TODO",
+                    Status::Done => "DONE",
+                };
+                let hint = if boards {
+                    format!("{} {} #{}", board::name(item), list, item.id)
+                } else {
+                    format!("{} #{}", list, item.id)
+                };
+                Entry {
+                    text,
+                    hint,
+                    target: Target::Item(status, item.id),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Entries for the keys of Normal mode and the commands, for the palette.
+pub fn actions() -> Vec<Entry> {
+    let keys = BINDINGS
+        .iter()
+        .filter(|binding| binding.modes.contains(&Mode::Normal))
+        .filter(|binding| binding.action != Action::Command)
+        .map(|binding| Entry {
+            text: binding.description.to_string(),
+            hint: binding.keys.iter().map(|key| key_name(*key)).collect(),
+            target: Target::Keys(binding.keys),
+        });
+    let commands = COMMANDS.iter().map(|(usage, description)| Entry {
+        text: format!(":{}", usage),
+        hint: description.to_string(),
+        target: Target::Command(usage),
+    });
+    keys.chain(commands).collect()
+}
+
+/// Scores `text` against `query` when all characters of the query appear in
+/// it in order. Runs of consecutive characters and characters starting a
+/// word count more, skipped characters count against. Case is ignored
+/// unless the query has an uppercase letter.
+pub fn fuzzy(query: &str, text: &str) -> Option<(i32, Vec<usize>)> {
+    let exact = query.chars().any(char::is_uppercase);
+    let fold = |c: char| if exact { c } else { c.to_ascii_lowercase() };
+    let query: Vec<char> = query.chars().filter(|c| *c != ' ').map(fold).collect();
+    let text: Vec<char> = text.chars().collect();
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+    // Greedy from every place the first character matches, keeping the best
+    (0..text.len())
+        .filter(|start| fold(text[*start]) == query[0])
+        .filter_map(|start| {
+            let mut positions = vec![start];
+            let mut next = start + 1;
+            for wanted in &query[1..] {
+                let found = (next..text.len()).find(|i| fold(text[*i]) == *wanted)?;
+                positions.push(found);
+                next = found + 1;
+            }
+            Some((score(&text, &positions), positions))
+        })
+        .max_by_key(|(score, positions)| (*score, -(positions[0] as i32)))
+}
+
+fn score(text: &[char], positions: &[usize]) -> i32 {
+    let mut score = -(positions[0].min(10) as i32);
+    for (n, position) in positions.iter().enumerate() {
+        score += 16;
+        let word_start = *position == 0 || !text[position - 1].is_alphanumeric();
+        if word_start {
+            score += 10;
+        }
+        if n > 0 {
+            let gap = position - positions[n - 1] - 1;
+            if gap == 0 {
+                score += 15;
+            } else {
+                score -= gap.min(5) as i32;
+            }
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(texts: &[&str]) -> Finder {
+        let entries = texts
+            .iter()
+            .enumerate()
+            .map(|(id, text)| Entry {
+                text: text.to_string(),
+                hint: String::new(),
+                target: Target::Item(Status::Todo, id as u64),
+            })
+            .collect();
+        Finder::new("FIND", entries)
+    }
+
+    fn ranked(finder: &mut Finder, query: &str) -> Vec<String> {
+        finder.search(query);
+        finder
+            .matches()
+            .iter()
+            .map(|found| found.entry.text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn positions_are_the_matched_characters() {
+        assert_eq!(fuzzy("wr", "Write report").unwrap().1, [0, 1]);
+        assert_eq!(fuzzy("rep", "Write report").unwrap().1, [6, 7, 8]);
+        assert_eq!(fuzzy("", "anything"), Some((0, Vec::new())));
+        assert_eq!(fuzzy("rw", "Write"), None);
+        // Spaces in the query are not matched
+        assert_eq!(fuzzy("w r", "Write report"), fuzzy("wr", "Write report"));
+    }
+
+    #[test]
+    fn a_prefix_beats_a_scattered_match() {
+        let mut finder = with(&["a long title", "along"]);
+        assert_eq!(ranked(&mut finder, "along"), ["along", "a long title"]);
+        let mut finder = with(&["sort items", "items"]);
+        assert_eq!(ranked(&mut finder, "items"), ["items", "sort items"]);
+    }
+
+    #[test]
+    fn word_starts_score_higher() {
+        let (scattered, _) = fuzzy("tr", "the report").unwrap();
+        let (inside, _) = fuzzy("tr", "cat rug").unwrap();
+        assert!(scattered > inside);
+        let mut finder = with(&["struct", "the report"]);
+        assert_eq!(ranked(&mut finder, "tr"), ["the report", "struct"]);
+    }
+
+    #[test]
+    fn an_uppercase_query_is_case_sensitive() {
+        let mut finder = with(&["readme", "README", "ReadMe"]);
+        assert_eq!(ranked(&mut finder, "rm"), ["readme", "README", "ReadMe"]);
+        assert_eq!(ranked(&mut finder, "RE"), ["README"]);
+        assert_eq!(ranked(&mut finder, "RdM"), ["ReadMe"]);
+    }
+
+    #[test]
+    fn ties_keep_the_order_of_the_entries() {
+        let mut finder = with(&["Buy milk", "Buy bread", "Call mom", "Buy eggs"]);
+        assert_eq!(
+            ranked(&mut finder, "buy"),
+            ["Buy milk", "Buy bread", "Buy eggs"]
+        );
+        finder.go(Direction::Down);
+        assert_eq!(finder.chosen(), Some(Target::Item(Status::Todo, 1)));
+    }
+}
@@ -0,0 +1,33 @@
+use super::command::COMMANDS;
+use super::key_map::{key_name, BINDINGS};
+use super::mode::Mode;
+
+fn keys(mode: &Mode) -> impl Iterator<Item = String> + '_ {
+    BINDINGS
+        .iter()
+        .filter(move |binding| binding.modes.contains(mode))
+        .map(|binding| {
+            let keys: String = binding.keys.iter().map(|key| key_name(*key)).collect();
+            format!("{:<22}{}", keys, binding.description)
+        })
+}
+
+/// Lines of the `?` overlay: the keys of `mode`, the commands when they can
+/// be typed from it, then the keys of the views that open over the lists.
+pub fn lines(mode: &Mode) -> Vec<String> {
+    let mut lines: Vec<String> = keys(mode).collect();
+    if matches!(mode, Mode::Normal | Mode::Command) {
+        lines.push(String::new());
+        lines.extend(
+            COMMANDS.iter().map(|(usage, description)| {
+                format!("{:<22}{}", format!(":{}", usage), description)
+            }),
+        );
+    }
+    for view in [Mode::Pager, Mode::Search, Mode::Finder, Mode::Prompt] {
+        lines.push(String::new());
+        lines.push(view.to_string());
+        lines.extend(keys(&view));
+    }
+    lines
+}
@@ -1,9 +1,15 @@
+use super::mode::Mode;
+
 pub const KEYMAP_QUIT: i32 = 'q' as i32;
 pub const KEYMAP_ESC: i32 = 27;
 pub const KEYMAP_DELETE: i32 = 330;
 pub const KEYMAP_BACKSPACE: i32 = 127;
 pub const KEYMAP_LEFT: i32 = 260;
 pub const KEYMAP_RIGHT: i32 = 261;
+pub const KEYMAP_UP: i32 = 259;
+pub const KEYMAP_DOWN: i32 = 258;
+pub const KEYMAP_CTRL_N: i32 = 14;
+pub const KEYMAP_CTRL_P: i32 = 16;
 pub const KEYMAP_TAB: i32 = '\t' as i32;
 pub const KEYMAP_NEWLINE: i32 = '\n' as i32;
 pub const KEYMAP_V: i32 = 'v' as i32;
@@ -18,3 +24,547 @@ pub const KEYMAP_O: i32 = 'o' as i32;
 pub const KEYMAP_SHIFT_O: i32 = 'O' as i32;
 pub const KEYMAP_SHIFT_A: i32 = 'A' as i32;
 pub const KEYMAP_SHIFT_I: i32 = 'I' as i32;
+pub const KEYMAP_A: i32 = 'a' as i32;
+pub const KEYMAP_C: i32 = 'c' as i32;
+pub const KEYMAP_Y: i32 = 'y' as i32;
+pub const KEYMAP_Z: i32 = 'z' as i32;
+pub const KEYMAP_GT: i32 = '>' as i32;
+pub const KEYMAP_LT: i32 = '<' as i32;
+pub const KEYMAP_E: i32 = 'e' as i32;
+pub const KEYMAP_N: i32 = 'n' as i32;
+pub const KEYMAP_COLON: i32 = ':' as i32;
+pub const KEYMAP_T: i32 = 't' as i32;
+pub const KEYMAP_SLASH: i32 = '/' as i32;
+pub const KEYMAP_R: i32 = 'r' as i32;
+pub const KEYMAP_M: i32 = 'm' as i32;
+pub const KEYMAP_QUESTION: i32 = '?' as i32;
+pub const KEYMAP_LEFT_BRACKET: i32 = '[' as i32;
+pub const KEYMAP_RIGHT_BRACKET: i32 = ']' as i32;
+
+/// What a binding does, the key handling matches on it rather than on keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Help,
+    Quit,
+    SwitchList,
+    Down,
+    Up,
+    First,
+    Last,
+    DragDown,
+    DragUp,
+    Move,
+    Delete,
+    AddAfter,
+    AddBefore,
+    EditFromStart,
+    EditFromEnd,
+    Indent,
+    Outdent,
+    ToggleNote,
+    ToggleStamps,
+    EditNote,
+    ToggleFold,
+    Unfold,
+    Fold,
+    Select,
+    Command,
+    Find,
+    NextBoard,
+    PreviousBoard,
+    Dismiss,
+    Unselect,
+    Leave,
+    DeleteNext,
+    DeletePrevious,
+    Left,
+    Right,
+    Run,
+    ChooseUp,
+    ChooseDown,
+    Cancel,
+    DeleteLast,
+    Search,
+    Close,
+    Confirm,
+    Reload,
+    Overwrite,
+    Merge,
+}
+
+/// A key, or a sequence of keys, and what it does in some modes. Keys are
+/// only handled through this table, so `?` lists the actual keys.
+pub struct Binding {
+    pub modes: &'static [Mode],
+    pub keys: &'static [i32],
+    pub action: Action,
+    pub description: &'static str,
+}
+
+const fn bind(
+    modes: &'static [Mode],
+    keys: &'static [i32],
+    action: Action,
+    description: &'static str,
+) -> Binding {
+    Binding {
+        modes,
+        keys,
+        action,
+        description,
+    }
+}
+
+const NORMAL: &[Mode] = &[Mode::Normal];
+const VISUAL: &[Mode] = &[Mode::Visual];
+const BROWSING: &[Mode] = &[Mode::Normal, Mode::Visual];
+const INSERT: &[Mode] = &[Mode::Insert];
+const COMMAND: &[Mode] = &[Mode::Command];
+const PAGER: &[Mode] = &[Mode::Pager];
+const SEARCH: &[Mode] = &[Mode::Search];
+const FINDER: &[Mode] = &[Mode::Finder];
+const PROMPT: &[Mode] = &[Mode::Prompt];
+
+pub const BINDINGS: &[Binding] = &[
+    bind(
+        BROWSING,
+        &[KEYMAP_QUESTION],
+        Action::Help,
+        "Shows the keys of the current mode",
+    ),
+    bind(
+        BROWSING,
+        &[KEYMAP_QUIT],
+        Action::Quit,
+        "Quits, saving the items",
+    ),
+    bind(
+        BROWSING,
+        &[KEYMAP_TAB],
+        Action::SwitchList,
+        "Switches between TODO and DONE",
+    ),
+    bind(NORMAL, &[KEYMAP_J], Action::Down, "Goes one item down"),
+    bind(NORMAL, &[KEYMAP_K], Action::Up, "Goes one item up"),
+    bind(NORMAL, &[KEYMAP_G], Action::First, "Goes to the first item"),
+    bind(
+        NORMAL,
+        &[KEYMAP_SHIFT_G],
+        Action::Last,
+        "Goes to the last item",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_SHIFT_J],
+        Action::DragDown,
+        "Drags item (with its subtasks) down",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_SHIFT_K],
+        Action::DragUp,
+        "Drags item (with its subtasks) up",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_NEWLINE],
+        Action::Move,
+        "Moves item (with its subtasks) to the other list",
+    ),
+    bind(NORMAL, &[KEYMAP_D], Action::Delete, "Deletes item in DONE"),
+    bind(
+        NORMAL,
+        &[KEYMAP_O],
+        Action::AddAfter,
+        "Adds an item after the current one in TODO",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_SHIFT_O],
+        Action::AddBefore,
+        "Adds an item before the current one in TODO",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_SHIFT_I],
+        Action::EditFromStart,
+        "Edits title from its start",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_SHIFT_A],
+        Action::EditFromEnd,
+        "Edits title from its end",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_GT],
+        Action::Indent,
+        "Makes item a subtask of the item above",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_LT],
+        Action::Outdent,
+        "Makes subtask a sibling of its parent",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_N],
+        Action::ToggleNote,
+        "Toggles the note pane",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_T],
+        Action::ToggleStamps,
+        "Toggles completion times in DONE",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_E],
+        Action::EditNote,
+        "Edits note in $EDITOR",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_Z, KEYMAP_A],
+        Action::ToggleFold,
+        "Toggles fold of subtasks",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_Z, KEYMAP_O],
+        Action::Unfold,
+        "Unfolds subtasks",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_Z, KEYMAP_C],
+        Action::Fold,
+        "Folds subtasks",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_V],
+        Action::Select,
+        "Starts selecting items",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_COLON],
+        Action::Command,
+        "Types a command or searches actions",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_CTRL_P],
+        Action::Find,
+        "Finds an item on any board",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_RIGHT_BRACKET],
+        Action::NextBoard,
+        "Switches to the next board",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_LEFT_BRACKET],
+        Action::PreviousBoard,
+        "Switches to the previous board",
+    ),
+    bind(
+        NORMAL,
+        &[KEYMAP_ESC],
+        Action::Dismiss,
+        "Dismisses notifications",
+    ),
+    bind(
+        VISUAL,
+        &[KEYMAP_J],
+        Action::Down,
+        "Selects or unselects item and goes down",
+    ),
+    bind(
+        VISUAL,
+        &[KEYMAP_K],
+        Action::Up,
+        "Selects or unselects item and goes up",
+    ),
+    bind(
+        VISUAL,
+        &[KEYMAP_NEWLINE],
+        Action::Move,
+        "Moves selected items to the other list",
+    ),
+    bind(
+        VISUAL,
+        &[KEYMAP_D],
+        Action::Delete,
+        "Deletes selected items in DONE",
+    ),
+    bind(VISUAL, &[KEYMAP_V], Action::Unselect, "Drops the selection"),
+    bind(
+        INSERT,
+        &[KEYMAP_NEWLINE],
+        Action::Leave,
+        "Goes back to Normal mode",
+    ),
+    bind(
+        INSERT,
+        &[KEYMAP_ESC],
+        Action::Leave,
+        "Goes back to Normal mode",
+    ),
+    bind(
+        INSERT,
+        &[KEYMAP_DELETE],
+        Action::DeleteNext,
+        "Deletes the character under the cursor",
+    ),
+    bind(
+        INSERT,
+        &[KEYMAP_BACKSPACE],
+        Action::DeletePrevious,
+        "Deletes the character before the cursor",
+    ),
+    bind(INSERT, &[KEYMAP_LEFT], Action::Left, "Moves cursor left"),
+    bind(INSERT, &[KEYMAP_RIGHT], Action::Right, "Moves cursor right"),
+    bind(
+        COMMAND,
+        &[KEYMAP_NEWLINE],
+        Action::Run,
+        "Runs the command, or else the chosen action",
+    ),
+    bind(
+        COMMAND,
+        &[KEYMAP_UP],
+        Action::ChooseUp,
+        "Chooses the action above",
+    ),
+    bind(
+        COMMAND,
+        &[KEYMAP_DOWN],
+        Action::ChooseDown,
+        "Chooses the action below",
+    ),
+    bind(
+        COMMAND,
+        &[KEYMAP_ESC],
+        Action::Cancel,
+        "Cancels the command",
+    ),
+    bind(
+        COMMAND,
+        &[KEYMAP_BACKSPACE],
+        Action::DeleteLast,
+        "Deletes the last character",
+    ),
+    bind(PAGER, &[KEYMAP_J], Action::Down, "Scrolls down"),
+    bind(PAGER, &[KEYMAP_K], Action::Up, "Scrolls up"),
+    bind(PAGER, &[KEYMAP_G], Action::First, "Scrolls to the top"),
+    bind(
+        PAGER,
+        &[KEYMAP_SHIFT_G],
+        Action::Last,
+        "Scrolls to the bottom",
+    ),
+    bind(PAGER, &[KEYMAP_SLASH], Action::Search, "Searches the lines"),
+    bind(PAGER, &[KEYMAP_QUIT], Action::Close, "Closes the view"),
+    bind(PAGER, &[KEYMAP_ESC], Action::Close, "Closes the view"),
+    bind(
+        SEARCH,
+        &[KEYMAP_NEWLINE],
+        Action::Leave,
+        "Keeps the search and goes back to scrolling",
+    ),
+    bind(SEARCH, &[KEYMAP_ESC], Action::Cancel, "Clears the search"),
+    bind(
+        SEARCH,
+        &[KEYMAP_BACKSPACE],
+        Action::DeleteLast,
+        "Deletes the last character",
+    ),
+    bind(
+        FINDER,
+        &[KEYMAP_NEWLINE],
+        Action::Run,
+        "Goes to the chosen item",
+    ),
+    bind(
+        FINDER,
+        &[KEYMAP_UP],
+        Action::ChooseUp,
+        "Chooses the item above",
+    ),
+    bind(
+        FINDER,
+        &[KEYMAP_CTRL_P],
+        Action::ChooseUp,
+        "Chooses the item above",
+    ),
+    bind(
+        FINDER,
+        &[KEYMAP_DOWN],
+        Action::ChooseDown,
+        "Chooses the item below",
+    ),
+    bind(
+        FINDER,
+        &[KEYMAP_CTRL_N],
+        Action::ChooseDown,
+        "Chooses the item below",
+    ),
+    bind(FINDER, &[KEYMAP_ESC], Action::Cancel, "Closes the finder"),
+    bind(
+        FINDER,
+        &[KEYMAP_BACKSPACE],
+        Action::DeleteLast,
+        "Deletes the last character",
+    ),
+    bind(PROMPT, &[KEYMAP_Y], Action::Confirm, "Answers yes"),
+    bind(
+        PROMPT,
+        &[KEYMAP_R],
+        Action::Reload,
+        "Reloads the file changed on disk",
+    ),
+    bind(
+        PROMPT,
+        &[KEYMAP_O],
+        Action::Overwrite,
+        "Overwrites the file changed on disk",
+    ),
+    bind(
+        PROMPT,
+        &[KEYMAP_M],
+        Action::Merge,
+        "Merges with the file changed on disk",
+    ),
+    bind(
+        PROMPT,
+        &[KEYMAP_N],
+        Action::Cancel,
+        "Answers no, like any other key",
+    ),
+    bind(PROMPT, &[KEYMAP_C], Action::Cancel, "Cancels"),
+    bind(PROMPT, &[KEYMAP_ESC], Action::Cancel, "Cancels"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    Action(Action),
+    /// The keys start a longer binding, the next key decides.
+    Prefix,
+    Unbound,
+}
+
+/// Finds what the keys typed so far do in `mode`.
+pub fn lookup(mode: &Mode, keys: &[i32]) -> Lookup {
+    let mut lookup = Lookup::Unbound;
+    for binding in BINDINGS
+        .iter()
+        .filter(|binding| binding.modes.contains(mode))
+    {
+        if binding.keys == keys {
+            return Lookup::Action(binding.action);
+        }
+        if binding.keys.starts_with(keys) {
+            lookup = Lookup::Prefix;
+        }
+    }
+    lookup
+}
+
+pub fn key_name(key: i32) -> String {
+    match key {
+        KEYMAP_ESC => "ESC".to_string(),
+        KEYMAP_DELETE => "DEL".to_string(),
+        KEYMAP_BACKSPACE => "BACKSPACE".to_string(),
+        KEYMAP_LEFT => "LEFT".to_string(),
+        KEYMAP_RIGHT => "RIGHT".to_string(),
+        KEYMAP_UP => "UP".to_string(),
+        KEYMAP_DOWN => "DOWN".to_string(),
+        KEYMAP_CTRL_N => "C-n".to_string(),
+        KEYMAP_CTRL_P => "C-p".to_string(),
+        KEYMAP_TAB => "TAB".to_string(),
+        KEYMAP_NEWLINE => "ENTER".to_string(),
+        key => char::from_u32(key as u32).map_or_else(|| key.to_string(), String::from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_waits_for_the_rest_of_a_sequence() {
+        assert_eq!(lookup(&Mode::Normal, &[KEYMAP_Z]), Lookup::Prefix);
+        assert_eq!(
+            lookup(&Mode::Normal, &[KEYMAP_Z, KEYMAP_A]),
+            Lookup::Action(Action::ToggleFold)
+        );
+        assert_eq!(
+            lookup(&Mode::Normal, &[KEYMAP_Z, KEYMAP_J]),
+            Lookup::Unbound
+        );
+        assert_eq!(lookup(&Mode::Visual, &[KEYMAP_Z]), Lookup::Unbound);
+    }
+
+    #[test]
+    fn lookup_depends_on_the_mode() {
+        assert_eq!(
+            lookup(&Mode::Normal, &[KEYMAP_V]),
+            Lookup::Action(Action::Select)
+        );
+        assert_eq!(
+            lookup(&Mode::Visual, &[KEYMAP_V]),
+            Lookup::Action(Action::Unselect)
+        );
+        assert_eq!(lookup(&Mode::Insert, &[KEYMAP_V]), Lookup::Unbound);
+        assert_eq!(
+            lookup(&Mode::Pager, &[KEYMAP_QUIT]),
+            Lookup::Action(Action::Close)
+        );
+        assert_eq!(
+            lookup(&Mode::Finder, &[KEYMAP_CTRL_N]),
+            Lookup::Action(Action::ChooseDown)
+        );
+        assert_eq!(
+            lookup(&Mode::Prompt, &[KEYMAP_Y]),
+            Lookup::Action(Action::Confirm)
+        );
+    }
+
+    #[test]
+    fn keys_are_bound_once_per_mode() {
+        for mode in [
+            Mode::Normal,
+            Mode::Visual,
+            Mode::Insert,
+            Mode::Command,
+            Mode::Pager,
+            Mode::Search,
+            Mode::Finder,
+            Mode::Prompt,
+        ] {
+            let bindings: Vec<&Binding> = BINDINGS
+                .iter()
+                .filter(|binding| binding.modes.contains(&mode))
+                .collect();
+            for (i, binding) in bindings.iter().enumerate() {
+                for other in &bindings[i + 1..] {
+                    assert!(
+                        !binding.keys.starts_with(other.keys)
+                            && !other.keys.starts_with(binding.keys),
+                        "{} and {} in {}",
+                        binding.description,
+                        other.description,
+                        mode
+                    );
+                }
+            }
+        }
+    }
+}
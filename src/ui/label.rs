@@ -0,0 +1,82 @@
+use super::prompt::ids;
+use crate::todo::depend;
+use crate::todo::item::Item;
+use crate::todo::tree;
+use std::collections::HashSet;
+
+/// The line of an item in the lists and in `todors list`: checkbox, priority,
+/// title, projects, tags, due date and rule, progress of the subtasks and the
+/// `open` items blocking it.
+pub fn item_label(
+    item: &Item,
+    done: bool,
+    depth: usize,
+    folded: bool,
+    todos: &[Item],
+    dones: &[Item],
+    open: &HashSet<u64>,
+) -> String {
+    let mut label = format!(
+        " {}- [{}] ",
+        "  ".repeat(depth),
+        if done { 'x' } else { ' ' }
+    );
+    if let Some(priority) = item.priority {
+        label.push_str(&format!("({}) ", priority));
+    }
+    label.push_str(&item.title);
+    for project in &item.projects {
+        label.push_str(&format!(" +{}", project));
+    }
+    for tag in &item.tags {
+        label.push_str(&format!(" @{}", tag));
+    }
+    let mut details = Vec::new();
+    if let Some(due) = item.due {
+        details.push(format!("due {}", due));
+    }
+    if let Some(recurrence) = &item.recurrence {
+        details.push(recurrence.to_string());
+    }
+    if !details.is_empty() {
+        label.push_str(&format!(" [{}]", details.join(", ")));
+    }
+    if let Some((finished, total)) = tree::progress(item.id, todos, dones) {
+        label.push_str(&format!(" ({}/{})", finished, total));
+    }
+    if !done {
+        let blockers = depend::blockers(item, open);
+        if !blockers.is_empty() {
+            label.push_str(&format!(" (blocked by {})", ids(&blockers)));
+        }
+    }
+    if folded {
+        label.push_str(" ...");
+    }
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_show_progress_blockers_and_folds() {
+        let mut parent = Item::new(1, "Release");
+        parent.priority = Some('A');
+        parent.projects = vec!["work".to_string()];
+        parent.depends = vec![3];
+        let mut child = Item::new(2, "Changelog");
+        child.parent = Some(1);
+        let todos = vec![parent, child, Item::new(3, "Review")];
+        let open = depend::open_ids(&todos);
+        assert_eq!(
+            item_label(&todos[0], false, 0, true, &todos, &[], &open),
+            " - [ ] (A) Release +work (0/1) (blocked by #3) ..."
+        );
+        assert_eq!(
+            item_label(&todos[1], true, 1, false, &todos, &[], &open),
+            "   - [x] Changelog"
+        );
+    }
+}
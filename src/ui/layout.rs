@@ -5,6 +5,23 @@ pub enum LayoutKind {
     Vert,
 }
 
+/// Where something was drawn.
+#[derive(Debug, Clone, Copy)]
+pub struct Rect {
+    pub pos: Vec2,
+    pub size: Vec2,
+}
+
+impl Rect {
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.contains_x(point.x) && point.y >= self.pos.y && point.y < self.pos.y + self.size.y
+    }
+
+    pub fn contains_x(&self, x: i32) -> bool {
+        x >= self.pos.x && x < self.pos.x + self.size.x
+    }
+}
+
 pub struct Layout {
     pub kind: LayoutKind,
     pub pos: Vec2,
@@ -1,7 +1,19 @@
 pub mod action;
+pub mod board;
+pub mod command;
+pub mod editor;
+pub mod event;
+pub mod finder;
+pub mod help;
 pub mod key_map;
+pub mod label;
 pub mod layout;
 pub mod mode;
+pub mod notify;
+pub mod pager;
+pub mod prompt;
 pub mod status;
 pub mod style;
+pub mod terminal;
 pub mod vec2;
+pub mod widget;
@@ -1,16 +1,30 @@
+use std::fmt;
+
+/// The editing modes, and the views that take the keys while they are open,
+/// each with its own bindings.
 #[derive(PartialEq)]
 pub enum Mode {
     Normal,
     Visual,
     Insert,
+    Command,
+    Pager,
+    Search,
+    Finder,
+    Prompt,
 }
 
-impl ToString for Mode {
-    fn to_string(&self) -> String {
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Mode::Normal => "NORMAL".to_owned(),
-            Mode::Visual => "VISUAL".to_owned(),
-            Mode::Insert => "INSERT".to_owned(),
+            Mode::Normal => write!(f, "NORMAL"),
+            Mode::Visual => write!(f, "VISUAL"),
+            Mode::Insert => write!(f, "INSERT"),
+            Mode::Command => write!(f, "COMMAND"),
+            Mode::Pager => write!(f, "PAGER"),
+            Mode::Search => write!(f, "SEARCH"),
+            Mode::Finder => write!(f, "FIND"),
+            Mode::Prompt => write!(f, "PROMPT"),
         }
     }
 }
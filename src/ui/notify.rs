@@ -0,0 +1,191 @@
+use super::style;
+use crate::todo::clock::Clock;
+use crate::todo::date::format_local;
+use std::time::{Duration, Instant};
+
+/// Most messages shown at once, older ones still on screen are hidden.
+const STACK: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    /// How long a message stays on screen, the worse the longer.
+    fn timeout(self) -> Duration {
+        match self {
+            Level::Info => Duration::from_secs(4),
+            Level::Warn => Duration::from_secs(8),
+            Level::Error => Duration::from_secs(15),
+        }
+    }
+
+    pub fn pair(self) -> i16 {
+        match self {
+            Level::Info => style::REGULAR_PAIR,
+            Level::Warn => style::WARN_PAIR,
+            Level::Error => style::ERROR_PAIR,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+pub struct Message {
+    pub level: Level,
+    pub text: String,
+    /// When it arrived, in seconds since the epoch.
+    pub time: i64,
+}
+
+/// Messages of the session, the recent ones are shown until they time out.
+pub struct Notifications {
+    /// Stamps the history, the timeouts run on `Instant`.
+    clock: Box<dyn Clock>,
+    history: Vec<Message>,
+    /// Indexes into `history` with their expiry, oldest first.
+    shown: Vec<(usize, Instant)>,
+}
+
+impl Notifications {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        Notifications {
+            clock,
+            history: Vec::new(),
+            shown: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, level: Level, text: impl Into<String>) {
+        self.history.push(Message {
+            level,
+            text: text.into(),
+            time: self.clock.now(),
+        });
+        self.shown
+            .push((self.history.len() - 1, Instant::now() + level.timeout()));
+        if self.shown.len() > STACK {
+            self.shown.remove(0);
+        }
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(Level::Info, text);
+    }
+
+    pub fn warn(&mut self, text: impl Into<String>) {
+        self.push(Level::Warn, text);
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(Level::Error, text);
+    }
+
+    /// Hides the messages whose time is up at `now`, returns when the next one is.
+    pub fn expire(&mut self, now: Instant) -> Option<Instant> {
+        self.shown.retain(|(_, expiry)| *expiry > now);
+        self.shown.iter().map(|(_, expiry)| *expiry).min()
+    }
+
+    pub fn dismiss(&mut self) {
+        self.shown.clear();
+    }
+
+    /// Messages on screen, newest first.
+    pub fn shown(&self) -> impl Iterator<Item = &Message> {
+        self.shown
+            .iter()
+            .rev()
+            .map(|(index, _)| &self.history[*index])
+    }
+
+    /// The history for `:messages`, newest first.
+    pub fn lines(&self, utc_offset: i64) -> Vec<String> {
+        self.history
+            .iter()
+            .rev()
+            .map(|message| {
+                format!(
+                    "{} {:<5} {}",
+                    format_local(message.time, utc_offset),
+                    message.level.name(),
+                    message.text
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::todo::clock::FixedClock;
+
+    // 2024-05-10 12:00 UTC.
+    const NOON: i64 = 1_715_342_400;
+
+    fn notifications() -> Notifications {
+        Notifications::new(Box::new(FixedClock {
+            now: NOON,
+            utc_offset: 0,
+        }))
+    }
+
+    fn texts(notifications: &Notifications) -> Vec<&str> {
+        notifications.shown().map(|m| m.text.as_str()).collect()
+    }
+
+    #[test]
+    fn stamps_messages_with_the_clock() {
+        let mut notifications = notifications();
+        notifications.info("Saved");
+        notifications.error("Broken");
+        assert_eq!(
+            notifications.lines(2 * 3600),
+            vec![
+                "2024-05-10 14:00 ERROR Broken",
+                "2024-05-10 14:00 INFO  Saved",
+            ]
+        );
+    }
+
+    #[test]
+    fn shows_the_newest_few() {
+        let mut notifications = notifications();
+        for text in ["a", "b", "c", "d"] {
+            notifications.info(text);
+        }
+        assert_eq!(texts(&notifications), vec!["d", "c", "b"]);
+        assert_eq!(notifications.lines(0).len(), 4);
+        notifications.dismiss();
+        assert_eq!(texts(&notifications), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn worse_messages_stay_longer() {
+        let mut notifications = notifications();
+        notifications.info("Saved");
+        notifications.warn("Careful");
+        notifications.error("Broken");
+        let start = Instant::now();
+
+        let next = notifications.expire(start + Duration::from_secs(5));
+        assert_eq!(texts(&notifications), vec!["Broken", "Careful"]);
+        assert!(next.is_some_and(|next| next <= start + Duration::from_secs(8)));
+
+        notifications.expire(start + Duration::from_secs(9));
+        assert_eq!(texts(&notifications), vec!["Broken"]);
+
+        assert_eq!(notifications.expire(start + Duration::from_secs(16)), None);
+        assert_eq!(notifications.lines(0).len(), 3);
+    }
+}
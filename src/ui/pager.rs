@@ -0,0 +1,73 @@
+use super::action::Direction;
+
+/// Read-only full screen list of lines, used for views like `:log`. Lines can
+/// be narrowed down with a case insensitive search.
+pub struct Pager {
+    pub title: String,
+    pub lines: Vec<String>,
+    pub scroll: usize,
+    pub query: String,
+    pub searching: bool,
+    /// Drawn in a box over the lists instead of the full screen.
+    pub overlay: bool,
+}
+
+impl Pager {
+    pub fn new(title: &str, lines: Vec<String>) -> Self {
+        Pager {
+            title: title.to_string(),
+            lines,
+            scroll: 0,
+            query: String::new(),
+            searching: false,
+            overlay: false,
+        }
+    }
+
+    pub fn overlay(title: &str, lines: Vec<String>) -> Self {
+        Pager {
+            overlay: true,
+            ..Pager::new(title, lines)
+        }
+    }
+
+    fn matching(&self) -> Vec<&str> {
+        let query = self.query.to_lowercase();
+        self.lines
+            .iter()
+            .filter(|line| line.to_lowercase().contains(&query))
+            .map(|line| line.as_str())
+            .collect()
+    }
+
+    pub fn scroll(&mut self, dir: Direction, height: usize) {
+        let last = self.matching().len().saturating_sub(height);
+        match dir {
+            Direction::Down => self.scroll = (self.scroll + 1).min(last),
+            Direction::Up => self.scroll = self.scroll.saturating_sub(1),
+            Direction::First => self.scroll = 0,
+            Direction::Last => self.scroll = last,
+        }
+    }
+
+    pub fn search(&mut self, query: &str) {
+        self.query = query.to_string();
+        self.scroll = 0;
+    }
+
+    pub fn header(&self) -> String {
+        if self.query.is_empty() && !self.searching {
+            self.title.clone()
+        } else {
+            format!("{} /{}", self.title, self.query)
+        }
+    }
+
+    pub fn visible(&self, height: usize) -> Vec<&str> {
+        self.matching()
+            .into_iter()
+            .skip(self.scroll)
+            .take(height)
+            .collect()
+    }
+}
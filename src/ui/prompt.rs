@@ -0,0 +1,58 @@
+use crate::todo::item::Item;
+
+/// A question shown in the status bar that must be answered before any other key is handled.
+pub enum Prompt {
+    /// Completing `items` would take subtasks not among them along.
+    CompleteSubtree {
+        pending: usize,
+        items: Vec<u64>,
+    },
+    CompleteBlocked {
+        blockers: Vec<u64>,
+        items: Vec<u64>,
+    },
+    /// Someone else wrote the file while saving or quitting.
+    Changed {
+        quit: bool,
+    },
+    /// Deleting a board that still has items.
+    DeleteBoard {
+        name: String,
+        items: usize,
+    },
+    /// Items a session that did not exit cleanly left unsaved.
+    Recover {
+        todos: Vec<Item>,
+        dones: Vec<Item>,
+    },
+}
+
+impl Prompt {
+    pub fn message(&self) -> String {
+        match self {
+            Prompt::CompleteSubtree { pending, .. } => format!(
+                "Item has {} incomplete subtask(s), move them to DONE as well? [y/n]",
+                pending
+            ),
+            Prompt::CompleteBlocked { blockers, .. } => format!(
+                "Item is blocked by {}, move it to DONE anyway? [y/n]",
+                ids(blockers)
+            ),
+            Prompt::DeleteBoard { name, items } => format!(
+                "Board {} has {} item(s), delete it with them? [y/n]",
+                name, items
+            ),
+            Prompt::Recover { .. } => {
+                "Recover unsaved changes of a session that did not exit cleanly? [y/n]".to_string()
+            }
+            Prompt::Changed { .. } => {
+                "File changed on disk: [r]eload, [o]verwrite, [m]erge or [c]ancel".to_string()
+            }
+        }
+    }
+}
+
+pub fn ids(ids: &[u64]) -> String {
+    let ids: Vec<String> = ids.iter().map(|id| format!("#{}", id)).collect();
+    ids.join(", ")
+}
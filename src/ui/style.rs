@@ -1,9 +1,13 @@
 use ncurses::{
-    curs_set, init_pair, noecho, start_color, COLOR_BLACK, COLOR_WHITE, CURSOR_VISIBILITY,
+    attr_t, curs_set, init_pair, noecho, start_color, A_BOLD, A_DIM, A_UNDERLINE, COLOR_BLACK,
+    COLOR_PAIR, COLOR_RED, COLOR_WHITE, COLOR_YELLOW, CURSOR_VISIBILITY,
 };
 
 pub const REGULAR_PAIR: i16 = 0;
 pub const HIGHLIGHT_PAIR: i16 = 1;
+pub const DIM_PAIR: i16 = 2;
+pub const WARN_PAIR: i16 = 3;
+pub const ERROR_PAIR: i16 = 4;
 
 pub fn init_style() {
     noecho();
@@ -11,4 +15,19 @@ pub fn init_style() {
     start_color();
     init_pair(REGULAR_PAIR, COLOR_WHITE, COLOR_BLACK);
     init_pair(HIGHLIGHT_PAIR, COLOR_BLACK, COLOR_WHITE);
+    init_pair(DIM_PAIR, COLOR_WHITE, COLOR_BLACK);
+    init_pair(WARN_PAIR, COLOR_YELLOW, COLOR_BLACK);
+    init_pair(ERROR_PAIR, COLOR_RED, COLOR_BLACK);
+}
+
+pub fn attr(pair: i16) -> attr_t {
+    match pair {
+        DIM_PAIR => COLOR_PAIR(pair) | A_DIM,
+        _ => COLOR_PAIR(pair),
+    }
+}
+
+/// Characters a search matched, on top of the pair of their line.
+pub fn matched(pair: i16) -> attr_t {
+    attr(pair) | A_BOLD | A_UNDERLINE
 }
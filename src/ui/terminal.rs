@@ -0,0 +1,77 @@
+use ncurses::{endwin, refresh};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Keeps the terminal usable whatever ends or pauses the interface:
+//
+// - a panic leaves curses before the message is printed,
+// - SIGINT and SIGTERM ask the main loop to quit, which saves,
+// - SIGTSTP (Ctrl-Z) leaves curses before stopping and SIGCONT redraws.
+//
+// Handlers only set flags, the main loop acts on them after `getch` returns.
+// They are installed without SA_RESTART so a signal interrupts the wait.
+
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+static RESUMED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    match signal {
+        libc::SIGTSTP => SUSPENDED.store(true, Ordering::SeqCst),
+        libc::SIGCONT => RESUMED.store(true, Ordering::SeqCst),
+        _ => TERMINATED.store(true, Ordering::SeqCst),
+    }
+}
+
+fn handle(signal: libc::c_int, handler: libc::sighandler_t) {
+    // SAFETY: the action is fully initialized before use and `on_signal` only
+    // touches atomics, which is async-signal-safe.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+fn catch(signal: libc::c_int) {
+    handle(
+        signal,
+        on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+    );
+}
+
+/// Installs the panic hook and signal handlers, after curses started.
+pub fn install() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        endwin();
+        default_hook(info);
+    }));
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGTSTP, libc::SIGCONT] {
+        catch(signal);
+    }
+}
+
+pub fn terminated() -> bool {
+    TERMINATED.load(Ordering::SeqCst)
+}
+
+/// Stops the process if Ctrl-Z was pressed and redraws once it continues,
+/// or after any other stop.
+pub fn suspend_if_asked() {
+    if SUSPENDED.swap(false, Ordering::SeqCst) {
+        endwin();
+        handle(libc::SIGTSTP, libc::SIG_DFL);
+        // SAFETY: raising a signal has no memory safety requirements.
+        unsafe {
+            libc::raise(libc::SIGTSTP);
+        }
+        catch(libc::SIGTSTP);
+        RESUMED.store(true, Ordering::SeqCst);
+    }
+    if RESUMED.swap(false, Ordering::SeqCst) {
+        endwin();
+        refresh();
+    }
+}
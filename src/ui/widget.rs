@@ -0,0 +1,285 @@
+use super::event::{Mouse, MouseKind};
+use super::layout::Rect;
+use super::status::Status;
+use super::vec2::Vec2;
+use std::time::{Duration, Instant};
+
+// Immediate mode keeps no widgets around, so every frame each widget
+// registers where it was drawn under an id that stays the same between
+// frames. Mouse events arrive between frames and are tested against the
+// rectangles of the last one:
+//
+// - the hot widget is the one under the pointer,
+// - the active widget is the one pressed on, until the button is released,
+// - releasing on the active widget clicks it, anywhere else drags it.
+//
+// Nothing here draws, so widgets can be driven by made up events.
+
+/// Two clicks on the same widget within this are a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Id {
+    /// A whole column, header and the empty space below the items included.
+    Panel(Status),
+    /// An item by list and item id, so it keeps its id when it moves.
+    Item(Status, u64),
+    /// The `[ ]` of an item.
+    Checkbox(Status, u64),
+    /// A tab of the board bar by position.
+    Board(usize),
+}
+
+impl Id {
+    /// The list and item id of an item or its checkbox.
+    pub fn item(self) -> Option<(Status, u64)> {
+        match self {
+            Id::Item(status, id) | Id::Checkbox(status, id) => Some((status, id)),
+            Id::Panel(_) | Id::Board(_) => None,
+        }
+    }
+
+    /// The panel the widget is in, if it is in one.
+    pub fn status(self) -> Option<Status> {
+        match self {
+            Id::Panel(status) | Id::Item(status, _) | Id::Checkbox(status, _) => Some(status),
+            Id::Board(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interaction {
+    Click(Id),
+    /// A second click on the same widget soon after the first.
+    DoubleClick(Id),
+    /// Pressed on `from` and released somewhere else.
+    Drag {
+        from: Id,
+        to: Option<Id>,
+    },
+    /// The wheel turned over the hot widget.
+    Scroll {
+        up: bool,
+    },
+}
+
+#[derive(Default)]
+pub struct Widgets {
+    /// Rectangles of the last frame, later ones are on top.
+    rects: Vec<(Id, Rect)>,
+    hot: Option<Id>,
+    active: Option<Id>,
+    last_click: Option<(Instant, Id)>,
+}
+
+impl Widgets {
+    /// Forgets the rectangles of the last frame, hot and active stay.
+    pub fn begin_frame(&mut self) {
+        self.rects.clear();
+    }
+
+    pub fn register(&mut self, id: Id, rect: Rect) {
+        self.rects.push((id, rect));
+    }
+
+    /// The topmost widget at `pos`.
+    pub fn hit(&self, pos: Vec2) -> Option<Id> {
+        self.rects
+            .iter()
+            .rev()
+            .find(|(_, rect)| rect.contains(pos))
+            .map(|(id, _)| *id)
+    }
+
+    /// The widget under the pointer at the last mouse event.
+    pub fn hot(&self) -> Option<Id> {
+        self.hot
+    }
+
+    /// The widget held down, while dragging too.
+    pub fn active(&self) -> Option<Id> {
+        self.active
+    }
+
+    /// Updates hot and active for a mouse event and says what it did.
+    pub fn input(&mut self, mouse: Mouse, now: Instant) -> Option<Interaction> {
+        self.hot = self.hit(mouse.pos);
+        match mouse.kind {
+            MouseKind::WheelUp | MouseKind::WheelDown => Some(Interaction::Scroll {
+                up: mouse.kind == MouseKind::WheelUp,
+            }),
+            MouseKind::Press => {
+                self.active = self.hot;
+                None
+            }
+            MouseKind::Release => {
+                let from = self.active.take()?;
+                if self.hot != Some(from) {
+                    self.last_click = None;
+                    return Some(Interaction::Drag { from, to: self.hot });
+                }
+                match self.last_click.take() {
+                    Some((at, id)) if id == from && now.duration_since(at) < DOUBLE_CLICK => {
+                        Some(Interaction::DoubleClick(from))
+                    }
+                    _ => {
+                        self.last_click = Some((now, from));
+                        Some(Interaction::Click(from))
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEM: Id = Id::Item(Status::Todo, 1);
+    const OTHER: Id = Id::Item(Status::Todo, 2);
+    const CHECKBOX: Id = Id::Checkbox(Status::Todo, 1);
+
+    /// A TODO panel with two items, the first with its checkbox on top.
+    fn widgets() -> Widgets {
+        let mut widgets = Widgets::default();
+        widgets.begin_frame();
+        let rect = |x, y, w| Rect {
+            pos: Vec2::new(x, y),
+            size: Vec2::new(w, 1),
+        };
+        widgets.register(
+            Id::Panel(Status::Todo),
+            Rect {
+                pos: Vec2::new(0, 0),
+                size: Vec2::new(20, 10),
+            },
+        );
+        widgets.register(ITEM, rect(0, 1, 20));
+        widgets.register(CHECKBOX, rect(1, 1, 5));
+        widgets.register(OTHER, rect(0, 2, 20));
+        widgets
+    }
+
+    fn event(kind: MouseKind, x: i32, y: i32) -> Mouse {
+        Mouse {
+            kind,
+            pos: Vec2::new(x, y),
+        }
+    }
+
+    fn click(widgets: &mut Widgets, x: i32, y: i32, at: Instant) -> Option<Interaction> {
+        assert_eq!(widgets.input(event(MouseKind::Press, x, y), at), None);
+        widgets.input(event(MouseKind::Release, x, y), at)
+    }
+
+    #[test]
+    fn the_topmost_widget_is_hit() {
+        let widgets = widgets();
+        assert_eq!(widgets.hit(Vec2::new(2, 1)), Some(CHECKBOX));
+        assert_eq!(widgets.hit(Vec2::new(10, 1)), Some(ITEM));
+        assert_eq!(widgets.hit(Vec2::new(10, 5)), Some(Id::Panel(Status::Todo)));
+        assert_eq!(widgets.hit(Vec2::new(30, 1)), None);
+    }
+
+    #[test]
+    fn pressing_makes_a_widget_hot_and_active_until_released() {
+        let mut widgets = widgets();
+        let now = Instant::now();
+        widgets.input(event(MouseKind::Press, 10, 1), now);
+        assert_eq!(widgets.hot(), Some(ITEM));
+        assert_eq!(widgets.active(), Some(ITEM));
+        let released = widgets.input(event(MouseKind::Release, 10, 1), now);
+        assert_eq!(released, Some(Interaction::Click(ITEM)));
+        assert_eq!(widgets.hot(), Some(ITEM));
+        assert_eq!(widgets.active(), None);
+    }
+
+    #[test]
+    fn a_quick_second_click_is_a_double_click() {
+        let mut widgets = widgets();
+        let now = Instant::now();
+        assert_eq!(
+            click(&mut widgets, 2, 1, now),
+            Some(Interaction::Click(CHECKBOX))
+        );
+        let soon = now + Duration::from_millis(100);
+        assert_eq!(
+            click(&mut widgets, 2, 1, soon),
+            Some(Interaction::DoubleClick(CHECKBOX))
+        );
+        // A third click starts over
+        let sooner = soon + Duration::from_millis(100);
+        assert_eq!(
+            click(&mut widgets, 2, 1, sooner),
+            Some(Interaction::Click(CHECKBOX))
+        );
+    }
+
+    #[test]
+    fn slow_clicks_or_clicks_on_other_widgets_stay_single() {
+        let mut widgets = widgets();
+        let now = Instant::now();
+        click(&mut widgets, 10, 1, now);
+        let late = now + DOUBLE_CLICK;
+        assert_eq!(
+            click(&mut widgets, 10, 1, late),
+            Some(Interaction::Click(ITEM))
+        );
+        assert_eq!(
+            click(&mut widgets, 10, 2, late),
+            Some(Interaction::Click(OTHER))
+        );
+    }
+
+    #[test]
+    fn releasing_elsewhere_drags() {
+        let mut widgets = widgets();
+        let now = Instant::now();
+        widgets.input(event(MouseKind::Press, 10, 1), now);
+        let dropped = widgets.input(event(MouseKind::Release, 10, 2), now);
+        assert_eq!(
+            dropped,
+            Some(Interaction::Drag {
+                from: ITEM,
+                to: Some(OTHER)
+            })
+        );
+        assert_eq!(widgets.active(), None);
+
+        widgets.input(event(MouseKind::Press, 10, 2), now);
+        let dropped = widgets.input(event(MouseKind::Release, 40, 2), now);
+        assert_eq!(
+            dropped,
+            Some(Interaction::Drag {
+                from: OTHER,
+                to: None
+            })
+        );
+        // A drag is no first click of a double click
+        assert_eq!(
+            click(&mut widgets, 10, 2, now),
+            Some(Interaction::Click(OTHER))
+        );
+    }
+
+    #[test]
+    fn releasing_without_pressing_does_nothing() {
+        let mut widgets = widgets();
+        let released = widgets.input(event(MouseKind::Release, 10, 1), Instant::now());
+        assert_eq!(released, None);
+    }
+
+    #[test]
+    fn the_wheel_scrolls_the_hot_widget() {
+        let mut widgets = widgets();
+        let now = Instant::now();
+        let scrolled = widgets.input(event(MouseKind::WheelDown, 10, 5), now);
+        assert_eq!(scrolled, Some(Interaction::Scroll { up: false }));
+        assert_eq!(widgets.hot(), Some(Id::Panel(Status::Todo)));
+        let scrolled = widgets.input(event(MouseKind::WheelUp, 40, 5), now);
+        assert_eq!(scrolled, Some(Interaction::Scroll { up: true }));
+        assert_eq!(widgets.hot(), None);
+    }
+}